@@ -2,48 +2,694 @@
 
 use crate::view::*;
 use eframe::{egui, epi};
+use std::rc::Rc;
+
+/// Number keys selecting the first nine views
+const VIEW_KEYS: [egui::Key; 9] = [
+    egui::Key::Num1,
+    egui::Key::Num2,
+    egui::Key::Num3,
+    egui::Key::Num4,
+    egui::Key::Num5,
+    egui::Key::Num6,
+    egui::Key::Num7,
+    egui::Key::Num8,
+    egui::Key::Num9,
+];
+
+#[cfg(feature = "persistence")]
+const SELECTED_KEY: &str = "selected_view";
+#[cfg(feature = "persistence")]
+const PIN_SETTINGS_KEY: &str = "pin_settings";
+#[cfg(all(feature = "persistence", not(target_arch = "wasm32")))]
+const TARGET_FPS_KEY: &str = "target_fps";
+
+/// Startup configuration, from the command line on native
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Args {
+    /// View to open, matched against [`view_key`]
+    pub view: Option<String>,
+    pub view_args: ViewArgs,
+}
+
+impl Args {
+    /// Parse the query string of a link made by "Copy link", like
+    /// `?v=julia-set&cx=0&cy=0&z=1&cr=-0.8&ci=0.156&m=2`. Unknown keys and values that are not
+    /// numbers are skipped.
+    pub fn from_query(query: &str) -> Self {
+        let mut args = Self::default();
+        let (mut center, mut c) = ((None, None), (None, None));
+        for pair in query.trim_start_matches('?').split('&') {
+            let (key, value) = match pair.split_once('=') {
+                Some((key, value)) => (percent_decode(key), percent_decode(value)),
+                None => continue,
+            };
+            let (key, value) = (key.as_str(), value.as_str());
+            let view_args = &mut args.view_args;
+            match key {
+                "v" => args.view = Some(value.to_owned()),
+                "cx" => center.0 = value.parse().ok(),
+                "cy" => center.1 = value.parse().ok(),
+                "z" => view_args.zoom = value.parse().ok(),
+                "cr" => c.0 = value.parse().ok(),
+                "ci" => c.1 = value.parse().ok(),
+                "m" => view_args.m = value.parse().ok(),
+                "d" => view_args.depth = value.parse().ok(),
+                _ => tracing::warn!(key, "Unknown parameter in the link"),
+            }
+        }
+        args.view_args.center = center.0.zip(center.1);
+        args.view_args.c = c.0.zip(c.1);
+        args
+    }
+
+    /// The query string of a link opening these args, parsed back by [`Self::from_query`].
+    pub fn to_query(&self) -> String {
+        let args = &self.view_args;
+        let mut query = format!("?v={}", self.view.as_deref().unwrap_or_default());
+        if let Some((x, y)) = args.center {
+            query.push_str(&format!("&cx={}&cy={}", x, y));
+        }
+        if let Some(zoom) = args.zoom {
+            query.push_str(&format!("&z={}", zoom));
+        }
+        if let Some((re, im)) = args.c {
+            query.push_str(&format!("&cr={}&ci={}", re, im));
+        }
+        if let Some(m) = args.m {
+            query.push_str(&format!("&m={}", m));
+        }
+        if let Some(depth) = args.depth {
+            query.push_str(&format!("&d={}", depth));
+        }
+        query
+    }
+
+    /// Take the value of a command line option shared by the binaries, like `--view` or
+    /// `--zoom`. `Ok(false)` if `flag` is not one of them, an error if `value` is not valid.
+    pub fn parse_option(&mut self, flag: &str, value: &str) -> Result<bool, String> {
+        let view_args = &mut self.view_args;
+        match flag {
+            "--view" => self.view = Some(value.to_owned()),
+            "--c" => view_args.c = Some(parse_pair(flag, value, ',')?),
+            "--center" => view_args.center = Some(parse_pair(flag, value, ',')?),
+            "--zoom" => view_args.zoom = Some(parse_number(flag, value)?),
+            "--m" => view_args.m = Some(parse_number(flag, value)?),
+            "--depth" => view_args.depth = Some(parse_number(flag, value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Decode the `%XX` escapes of a query string, which browsers may add when a link is typed or
+/// edited. `+` is kept, it is the sign of a number more often than a space here.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        match escaped
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The `value` of the command line option `flag` as a number.
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{}: {:?} is not a number", flag, value))
+}
+
+/// The `value` of the command line option `flag` as two numbers, like `0.2,0.5` or `640x480`.
+pub fn parse_pair<T: std::str::FromStr>(
+    flag: &str,
+    value: &str,
+    separator: char,
+) -> Result<(T, T), String> {
+    match value.split_once(separator) {
+        Some((a, b)) => Ok((parse_number(flag, a)?, parse_number(flag, b)?)),
+        None => Err(format!(
+            "{} expects two numbers separated by {:?}",
+            flag, separator
+        )),
+    }
+}
+
+/// Render the view picked by `args` at `size` pixels into a PNG file, without a window or an
+/// eframe context, for the `render` binary. Every view painted with glow can, see
+/// [`View::init_headless`].
+pub fn render_headless(
+    gl: &glow::Context,
+    args: &Args,
+    size: (u32, u32),
+) -> Result<Vec<u8>, String> {
+    let mut views: Vec<_> = default_views()
+        .into_iter()
+        .filter(|view| view.requires_gl())
+        .collect();
+    let names: Vec<_> = views.iter().map(|view| view_key(view.name())).collect();
+    let query = args
+        .view
+        .as_deref()
+        .ok_or_else(|| format!("No view given, one of: {}", names.join(", ")))?;
+    let view = views
+        .iter_mut()
+        .find(|view| view_matches(view.name(), query))
+        .ok_or_else(|| {
+            format!(
+                "{} can't be rendered without a window, one of: {}",
+                query,
+                names.join(", ")
+            )
+        })?;
+    view.apply_args(&args.view_args);
+    view.init_headless(gl)
+        .map_err(|err| format!("Cannot build the shaders of {}: {}", view.name(), err))?;
+    let image = view
+        .render_to_image(gl, size)
+        .ok_or_else(|| format!("Cannot render {}", view.name()))?;
+    export::encode_png(&image).ok_or_else(|| "Cannot encode the PNG".to_owned())
+}
+
+/// Every view in the order of the menu bar. With a creation context they are restored from
+/// storage and build their GL resources, without one they keep their defaults and build nothing
+/// until [`View::init_headless`].
+fn views(cc: Option<&eframe::CreationContext<'_>>, julia_seed: &JuliaSeed) -> Vec<Box<dyn View>> {
+    vec![
+        Box::new(MandelbrotShader::new(cc, julia_seed.clone())),
+        Box::new(JuliaSetShader::new(cc, julia_seed.clone())),
+        Box::new(MandelbrotJuliaSplit::new(cc)),
+        Box::new(NewtonShader::new(cc)),
+        Box::new(KochSnowFlake::<false, false>::new(cc)),
+        Box::new(KochSnowFlake::<true, false>::new(cc)),
+        Box::new(KochSnowFlake::<false, true>::new(cc)),
+        Box::new(SierpinskiTriangle::new(cc)),
+        Box::new(SierpinskiCarpet::new(cc)),
+        Box::new(DragonCurve::new(cc)),
+        Box::new(PythagorasTree::new(cc)),
+        Box::new(LSystem::new(cc)),
+        Box::new(BarnsleyFern::new(cc)),
+        Box::new(FractalClock::new(cc)),
+    ]
+}
+
+/// Every view with its default settings, see [`views`]
+fn default_views() -> Vec<Box<dyn View>> {
+    views(None, &JuliaSeed::default())
+}
+
+/// Names of the views on the command line, like `mandelbrot-set`, in the order of the menu bar.
+pub fn view_keys() -> Vec<String> {
+    default_views()
+        .iter()
+        .map(|view| view_key(view.name()))
+        .collect()
+}
+
+/// Name of a view on the command line: `Mandelbrot Set (Shader)` is `mandelbrot-set`.
+fn view_key(name: &str) -> String {
+    let name = name.split('(').next().unwrap_or(name).trim();
+    name.to_lowercase().replace(' ', "-")
+}
+
+/// Whether `query` selects the view `name`, either its full key or the first words of it,
+/// so `julia` is enough for `julia-set`. `name` may also be one of the [`view_keys`].
+pub fn view_matches(name: &str, query: &str) -> bool {
+    let key = view_key(name);
+    let query = query.to_lowercase();
+    key == query || key.starts_with(&format!("{}-", query))
+}
+
+/// Timings of the last benchmark, one row per value of the swept parameter
+#[cfg(not(target_arch = "wasm32"))]
+struct Benchmark {
+    view: &'static str,
+    parameter: &'static str,
+    size: (u32, u32),
+    /// Swept value and milliseconds of its render and readback, `None` if it failed
+    rows: Vec<(u32, Option<f64>)>,
+}
+
+/// A thread requesting repaints at the instants sent to it, so that the frame rate cap doesn't
+/// block the UI thread. It ends with the app, when the sender is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+struct RepaintTimer(std::sync::mpsc::Sender<std::time::Instant>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RepaintTimer {
+    fn new(ctx: egui::Context) -> Self {
+        use std::sync::mpsc::RecvTimeoutError;
+        let (sender, receiver) = std::sync::mpsc::channel::<std::time::Instant>();
+        std::thread::spawn(move || {
+            while let Ok(mut at) = receiver.recv() {
+                // one pending wake-up is enough, the earliest one wins
+                loop {
+                    let left = at.saturating_duration_since(std::time::Instant::now());
+                    if left.is_zero() {
+                        break;
+                    }
+                    match receiver.recv_timeout(left) {
+                        Ok(next) => at = at.min(next),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                ctx.request_repaint();
+            }
+        });
+        Self(sender)
+    }
+
+    fn wake_at(&self, at: std::time::Instant) {
+        // the thread only stops once the app drops the sender
+        let _ = self.0.send(at);
+    }
+}
 
 pub struct FractalApp {
     selected: usize,
     views: Vec<Box<dyn View>>,
+    julia_seed: JuliaSeed,
+    julia_view: Option<usize>,
+    show_stats: bool,
+    /// The About window listing the views
+    show_about: bool,
+    /// Show the settings of the view in a side panel instead of a popup over the canvas
+    pin_settings: bool,
+    /// Only draw the canvas, for presentations
+    hide_ui: bool,
+    gl: Rc<glow::Context>,
+    /// Size in pixels of saved and copied images
+    export_size: (u32, u32),
+    /// Frame rate cap of the animated views, `None` repaints as fast as possible. The browser
+    /// already paces the frames to the display, so it only exists natively.
+    #[cfg(not(target_arch = "wasm32"))]
+    target_fps: Option<u32>,
+    /// When the last frame of an animated view was allowed by the cap
+    #[cfg(not(target_arch = "wasm32"))]
+    last_repaint: std::time::Instant,
+    /// Started by the first frame the cap holds back
+    #[cfg(not(target_arch = "wasm32"))]
+    repaint_timer: Option<RepaintTimer>,
+    /// Shown in the benchmark window until it is closed
+    #[cfg(not(target_arch = "wasm32"))]
+    benchmark: Option<Benchmark>,
 }
 
 impl FractalApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, args: Args) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
         // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
-        Self {
+        // A link made by "Copy link" reopens the view it was copied from
+        #[cfg(target_arch = "wasm32")]
+        let args = match web_sys::window().and_then(|window| window.location().search().ok()) {
+            Some(query) if !query.is_empty() => Args::from_query(&query),
+            _ => args,
+        };
+        let julia_seed = JuliaSeed::default();
+        let views = views(Some(cc), &julia_seed);
+        #[allow(unused_mut)]
+        let mut app = Self {
             selected: Default::default(),
-            views: vec![
-                Box::new(MandelbrotShader::new(cc)),
-                Box::new(JuliaSetShader::new(cc)),
-                Box::new(KochSnowFlake::<false>::new(cc)),
-                Box::new(KochSnowFlake::<true>::new(cc)),
-                Box::new(SierpinskiTriangle::new(cc)),
-                Box::new(FractalClock::default()),
-            ],
+            julia_view: views
+                .iter()
+                .position(|view| view.name() == JuliaSetShader::NAME),
+            views,
+            julia_seed,
+            show_stats: false,
+            show_about: false,
+            pin_settings: false,
+            hide_ui: false,
+            gl: cc.gl.clone(),
+            export_size: (1920, 1080),
+            #[cfg(not(target_arch = "wasm32"))]
+            target_fps: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            last_repaint: std::time::Instant::now(),
+            #[cfg(not(target_arch = "wasm32"))]
+            repaint_timer: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            benchmark: None,
+        };
+        #[cfg(feature = "persistence")]
+        if let Some(storage) = cc.storage {
+            if let Some(selected) = epi::get_value::<usize>(storage, SELECTED_KEY) {
+                // The stored index may be stale if views were added or removed since
+                app.selected = selected.min(app.views.len() - 1);
+            }
+            if let Some(pin_settings) = epi::get_value(storage, PIN_SETTINGS_KEY) {
+                app.pin_settings = pin_settings;
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(target_fps) = epi::get_value(storage, TARGET_FPS_KEY) {
+                app.target_fps = target_fps;
+            }
+        }
+        if let Some(query) = &args.view {
+            match app
+                .views
+                .iter()
+                .position(|view| view_matches(view.name(), query))
+            {
+                Some(selected) => app.selected = selected,
+                // the command line is checked against `view_keys` before, so only a link
+                None => tracing::warn!(view = %query, "Unknown view"),
+            }
+        }
+        app.views[app.selected].apply_args(&args.view_args);
+        app
+    }
+
+    /// Names of the views, in the order of the menu bar and of the indices of
+    /// [`Self::select_view`].
+    pub fn view_names(&self) -> Vec<&'static str> {
+        self.views.iter().map(|view| view.name()).collect()
+    }
+
+    /// Index in [`Self::view_names`] of the view on screen.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Show the view at `index` in [`Self::view_names`], as clicking it in the menu bar does.
+    /// An index past the last view is ignored.
+    pub fn select_view(&mut self, index: usize) {
+        if index < self.views.len() {
+            self.selected = index;
+        } else {
+            tracing::warn!(index, views = self.views.len(), "No view at this index");
         }
     }
 }
 
-impl epi::App for FractalApp {
-    /// Called by the frame work to save state before shutdown.
-    /// Note that you must enable the `persistence` feature for this to work.
+impl FractalApp {
+    fn export_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("size :");
+            ui.add(egui::DragValue::new(&mut self.export_size.0).clamp_range(16..=8192));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut self.export_size.1).clamp_range(16..=8192));
+        });
+        // only the views painted with glow can be rendered offscreen
+        let renderable = self.views[self.selected].requires_gl();
+        if ui
+            .add_enabled(renderable, egui::Button::new("Save PNG"))
+            .clicked()
+        {
+            ui.close_menu();
+            if let Some(image) = self.render_selected() {
+                let view = &self.views[self.selected];
+                export::save_png(&export::file_name(view.name(), "png"), &image);
+            }
+        }
+        if ui
+            .add_enabled(renderable, egui::Button::new("Copy image"))
+            .clicked()
+        {
+            ui.close_menu();
+            if let Some(image) = self.render_selected() {
+                export::copy_image(&image);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if ui
+            .add_enabled(
+                self.views[self.selected].benchmark_sweep().is_some(),
+                egui::Button::new("Benchmark"),
+            )
+            .on_hover_text("time offscreen renders over a range of iterations or depths")
+            .clicked()
+        {
+            ui.close_menu();
+            self.run_benchmark();
+        }
+        #[cfg(target_arch = "wasm32")]
+        if ui
+            .button("Copy link")
+            .on_hover_text("a link to this page that reopens the current view")
+            .clicked()
+        {
+            ui.close_menu();
+            self.copy_link(ui.ctx());
+        }
+    }
+
+    /// Query string of a link to the selected view with its current parameters, parsed back
+    /// by [`Args::from_query`].
+    #[cfg(target_arch = "wasm32")]
+    fn share_query(&self) -> String {
+        let view = &self.views[self.selected];
+        let args = Args {
+            view: Some(view_key(view.name())),
+            view_args: view.view_args(),
+        };
+        args.to_query()
+    }
+
+    /// Put a link to the selected view on the clipboard, and in the address bar for the
+    /// browsers where egui can't reach the clipboard.
+    #[cfg(target_arch = "wasm32")]
+    fn copy_link(&self, ctx: &egui::Context) {
+        use eframe::wasm_bindgen::JsValue;
+
+        let query = self.share_query();
+        let window = match web_sys::window() {
+            Some(window) => window,
+            None => return,
+        };
+        let location = window.location();
+        let page = location
+            .origin()
+            .and_then(|origin| Ok(origin + &location.pathname()?));
+        match page {
+            Ok(page) => ctx.output().copied_text = page + &query,
+            Err(err) => tracing::error!(?err, "Cannot read the address of the page"),
+        }
+        if let Err(err) = window
+            .history()
+            .and_then(|history| history.replace_state_with_url(&JsValue::NULL, "", Some(&query)))
+        {
+            tracing::error!(?err, "Cannot update the address bar");
+        }
+    }
+
+    /// Buttons saving the bookmarks of every view to a preset file, and adding those of a
+    /// preset file to the views. Importing needs a file dialog, so it is native only.
     #[cfg(feature = "persistence")]
-    fn save(&mut self, _storage: &mut dyn epi::Storage) {
-        //TODO: save the state
+    fn presets_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("Export presets")
+            .on_hover_text("save the bookmarks of every view to a JSON file")
+            .clicked()
+        {
+            ui.close_menu();
+            let presets: Vec<_> = self
+                .views
+                .iter()
+                .flat_map(|view| {
+                    view.bookmarks().iter().map(|bookmark| bookmark::Preset {
+                        view: view.name().to_owned(),
+                        bookmark: bookmark.clone(),
+                    })
+                })
+                .collect();
+            bookmark::save_presets(&presets);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if ui
+            .button("Import presets")
+            .on_hover_text("add the bookmarks of a JSON file to their views")
+            .clicked()
+        {
+            ui.close_menu();
+            if let Some(presets) = bookmark::load_presets() {
+                self.import_presets(presets);
+            }
+        }
     }
 
-    /// Called each time the UI needs repainting, which may be many times per second.
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+    /// Hand each preset to the view of the same name, the presets of unknown views are skipped.
+    #[cfg(all(feature = "persistence", not(target_arch = "wasm32")))]
+    fn import_presets(&mut self, presets: Vec<bookmark::Preset>) {
+        let unknown = presets
+            .iter()
+            .filter(|preset| self.views.iter().all(|view| view.name() != preset.view))
+            .count();
+        if unknown > 0 {
+            tracing::warn!(unknown, "Skipped the presets of unknown views");
+        }
+        for view in &mut self.views {
+            let bookmarks: Vec<_> = presets
+                .iter()
+                .filter(|preset| preset.view == view.name())
+                .map(|preset| preset.bookmark.clone())
+                .collect();
+            if !bookmarks.is_empty() {
+                view.import_bookmarks(bookmarks);
+            }
+        }
+    }
+
+    /// Render the selected view offscreen at every value of its [`View::benchmark_sweep`] and
+    /// time each render with its readback. The readback waits for the GPU, so the time covers
+    /// the whole frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run_benchmark(&mut self) {
+        let view = &self.views[self.selected];
+        let (parameter, values) = match view.benchmark_sweep() {
+            Some(sweep) => sweep,
+            None => return,
+        };
+        let size = self.export_size;
+        // the first render also pays for uploading the geometry or warming up the driver
+        if let Some(&first) = values.first() {
+            view.render_benchmark(&self.gl, size, first);
+        }
+        let rows = values
+            .into_iter()
+            .map(|value| {
+                let start = std::time::Instant::now();
+                let image = view.render_benchmark(&self.gl, size, value);
+                let ms = image.map(|_| start.elapsed().as_secs_f64() * 1e3);
+                tracing::info!(view = view.name(), parameter, value, ?ms, "Benchmark");
+                (value, ms)
+            })
+            .collect();
+        self.benchmark = Some(Benchmark {
+            view: view.name(),
+            parameter,
+            size,
+            rows,
+        });
+    }
+
+    /// Table of the last benchmark
+    #[cfg(not(target_arch = "wasm32"))]
+    fn benchmark_window(&mut self, ctx: &egui::Context) {
+        let benchmark = match &self.benchmark {
+            Some(benchmark) => benchmark,
+            None => return,
+        };
+        let mut open = true;
+        egui::Window::new("Benchmark")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} at {}x{}",
+                    benchmark.view, benchmark.size.0, benchmark.size.1
+                ));
+                egui::Grid::new("benchmark_rows")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong(benchmark.parameter);
+                        ui.strong("ms");
+                        ui.end_row();
+                        for (value, ms) in &benchmark.rows {
+                            ui.label(value.to_string());
+                            match ms {
+                                Some(ms) => ui.label(format!("{:.1}", ms)),
+                                None => ui.label("failed"),
+                            };
+                            ui.end_row();
+                        }
+                    });
+            });
+        if !open {
+            self.benchmark = None;
+        }
+    }
+
+    fn render_selected(&self) -> Option<egui::ColorImage> {
+        let view = &self.views[self.selected];
+        let image = view.render_to_image(&self.gl, self.export_size);
+        if image.is_none() {
+            tracing::warn!(view = view.name(), "View can't be rendered to an image");
+        }
+        image
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn frame_rate_ui(&mut self, ui: &mut egui::Ui) {
+        let mut capped = self.target_fps.is_some();
+        ui.checkbox(&mut capped, "Limit frame rate");
+        if capped {
+            let mut fps = self.target_fps.unwrap_or(60);
+            ui.add(egui::Slider::new(&mut fps, 1..=240).text("fps"));
+            self.target_fps = Some(fps);
+        } else {
+            self.target_fps = None;
+        }
+    }
+
+    /// Whether the frame rate cap lets an animated view repaint right away. If not, the
+    /// [`RepaintTimer`] requests the repaint once the next frame is due, so the UI thread never
+    /// waits.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn throttle(&mut self, ctx: &egui::Context) -> bool {
+        let fps = match self.target_fps {
+            Some(fps) => fps,
+            None => return true,
+        };
+        let now = std::time::Instant::now();
+        let frame = std::time::Duration::from_secs_f64(1.0 / fps as f64);
+        match frame.checked_sub(now - self.last_repaint) {
+            Some(left) if !left.is_zero() => {
+                self.repaint_timer
+                    .get_or_insert_with(|| RepaintTimer::new(ctx.clone()))
+                    .wake_at(now + left);
+                false
+            }
+            _ => {
+                self.last_repaint = now;
+                true
+            }
+        }
+    }
+
+    /// Menu bar, status bar and pinned settings around the canvas
+    fn panels_ui(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
             egui::menu::bar(ui, |ui| {
                 egui::widgets::global_dark_light_mode_switch(ui);
 
                 ui.menu_button("File", |ui| {
+                    self.export_ui(ui);
+                    #[cfg(feature = "persistence")]
+                    self.presets_ui(ui);
+                    ui.separator();
+                    ui.checkbox(&mut self.show_stats, "Frame stats");
+                    ui.checkbox(&mut self.pin_settings, "Settings in a side panel");
+                    if ui.button("Hide interface (H)").clicked() {
+                        self.hide_ui = true;
+                        ui.close_menu();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    self.frame_rate_ui(ui);
+                    if ui.button("About").clicked() {
+                        self.show_about = true;
+                        ui.close_menu();
+                    }
                     if ui.button("Quit").clicked() {
                         frame.quit();
                     }
@@ -58,11 +704,222 @@ impl epi::App for FractalApp {
                 }
             });
         });
+        self.status_bar(ctx);
+        if self.pin_settings {
+            self.settings_panel(ctx);
+        }
+    }
+
+    /// Keep painting the animated views, within the frame rate cap.
+    fn repaint_dynamic(&mut self, ctx: &egui::Context) {
+        if self.views[self.selected].is_dynamic() {
+            #[cfg(not(target_arch = "wasm32"))]
+            if !self.throttle(ctx) {
+                return;
+            }
+            ctx.request_repaint();
+        }
+    }
+
+    /// View name, frame rate and the view's own status along the bottom of the window
+    fn status_bar(&self, ctx: &egui::Context) {
+        let view = &self.views[self.selected];
+        let dt = ctx.input().unstable_dt;
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(view.name());
+                ui.separator();
+                ui.label(format!("{:.0} fps", 1.0 / dt));
+                if let Some(status) = view.status_text() {
+                    ui.separator();
+                    ui.label(status);
+                }
+            });
+        });
+    }
+
+    /// Settings and help of the selected view, when they are pinned instead of floating over
+    /// the canvas
+    fn settings_panel(&mut self, ctx: &egui::Context) {
+        let view = &mut self.views[self.selected];
+        egui::SidePanel::left("settings_panel").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading(view.name());
+                view.options_ui(ui);
+                if let Some(help) = view.help() {
+                    ui.separator();
+                    egui::CollapsingHeader::new("Help").show(ui, |ui| ui.label(help));
+                }
+            });
+        });
+    }
+
+    /// Every view with what it draws and its formula
+    fn about_window(&mut self, ctx: &egui::Context) {
+        let views = &self.views;
+        egui::Window::new("About")
+            .open(&mut self.show_about)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("about_views")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for view in views {
+                                ui.strong(view.name());
+                                ui.label(view.description().unwrap_or_default());
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+    }
+
+    /// Frame timing overlay in the bottom right corner of the canvas
+    fn stats_ui(&self, ctx: &egui::Context) {
+        let dt = ctx.input().unstable_dt;
+        let mut text = format!("{:.1} fps\n{:.2} ms", 1.0 / dt, dt * 1e3);
+        if let Some(stats) = self.views[self.selected].debug_stats() {
+            text.push('\n');
+            text.push_str(&stats);
+        }
+        egui::Area::new("frame_stats")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(egui::RichText::new(text).monospace());
+                });
+            });
+    }
+}
+
+impl epi::App for FractalApp {
+    /// Called by the frame work to save state before shutdown.
+    /// Note that you must enable the `persistence` feature for this to work.
+    #[cfg(feature = "persistence")]
+    fn save(&mut self, storage: &mut dyn epi::Storage) {
+        epi::set_value(storage, SELECTED_KEY, &self.selected);
+        epi::set_value(storage, PIN_SETTINGS_KEY, &self.pin_settings);
+        #[cfg(not(target_arch = "wasm32"))]
+        epi::set_value(storage, TARGET_FPS_KEY, &self.target_fps);
+        for view in &self.views {
+            view.save(storage);
+        }
+    }
+
+    /// Called each time the UI needs repainting, which may be many times per second.
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // Digits typed into a DragValue must not switch the view
+        if !ctx.wants_keyboard_input() {
+            for (i, key) in VIEW_KEYS.iter().enumerate().take(self.views.len()) {
+                if ctx.input().key_pressed(*key) {
+                    self.selected = i;
+                }
+            }
+            if ctx.input().key_pressed(egui::Key::Home) {
+                self.views[self.selected].reset();
+            }
+            if ctx.input().key_pressed(egui::Key::H) {
+                self.hide_ui = !self.hide_ui;
+            }
+            let (command, shift) = {
+                let modifiers = ctx.input().modifiers;
+                (modifiers.command, modifiers.shift)
+            };
+            if command && ctx.input().key_pressed(egui::Key::Z) {
+                if shift {
+                    self.views[self.selected].redo();
+                } else {
+                    self.views[self.selected].undo();
+                }
+            }
+            if command && ctx.input().key_pressed(egui::Key::Y) {
+                self.views[self.selected].redo();
+            }
+        }
+        set_settings_pinned(ctx, self.pin_settings);
+        set_ui_hidden(ctx, self.hide_ui);
+        if !self.hide_ui {
+            self.panels_ui(ctx, frame);
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::Frame::dark_canvas(ui.style()).show(ui, |ui| self.views[self.selected].ui(ui));
         });
-        if self.views[self.selected].is_dynamic() {
-            ctx.request_repaint();
+        if self.show_stats && !self.hide_ui {
+            self.stats_ui(ctx);
         }
+        self.about_window(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.benchmark_window(ctx);
+        // A point was clicked in the Mandelbrot view, the Julia view picks it up when shown
+        if let Some(julia_view) = self.julia_view {
+            if self.julia_seed.get().is_some() && self.selected != julia_view {
+                self.selected = julia_view;
+                ctx.request_repaint();
+            }
+        }
+        self.repaint_dynamic(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_round_trip() {
+        let args = Args {
+            view: Some("julia-set".to_owned()),
+            view_args: ViewArgs {
+                center: Some((-0.743_643_887_037_158_7, 0.131_825_904_205_311_97)),
+                zoom: Some(1.5e-12),
+                c: Some((-0.8, 0.156)),
+                m: Some(3),
+                depth: Some(7),
+            },
+        };
+        assert_eq!(Args::from_query(&args.to_query()), args);
+        let view_only = Args {
+            view: Some("koch-snowflake".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(Args::from_query(&view_only.to_query()), view_only);
+    }
+
+    #[test]
+    fn query_skips_unknown_keys_and_bad_numbers() {
+        let args = Args::from_query("?v=mandelbrot-set&foo=1&cx=abc&cy=2&z=&m=2.5&d=-1&x&cr=1");
+        assert_eq!(args.view.as_deref(), Some("mandelbrot-set"));
+        // a pair is only taken when both halves are numbers
+        assert_eq!(args.view_args.center, None);
+        assert_eq!(args.view_args.c, None);
+        assert_eq!(args.view_args.zoom, None);
+        assert_eq!(args.view_args.m, None);
+        assert_eq!(args.view_args.depth, None);
+        assert_eq!(Args::from_query(""), Args::default());
+    }
+
+    #[test]
+    fn query_is_percent_decoded() {
+        let args = Args::from_query("?v=julia%2Dset&cx=%2D0.5&cy=1e%2B2&z=%zz");
+        assert_eq!(args.view.as_deref(), Some("julia-set"));
+        assert_eq!(args.view_args.center, Some((-0.5, 100.0)));
+        assert_eq!(args.view_args.zoom, None);
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn parse_pair_needs_two_numbers() {
+        assert_eq!(parse_pair::<u32>("--size", "640x480", 'x'), Ok((640, 480)));
+        assert_eq!(
+            parse_pair::<f64>("--c", " -0.8, 0.156", ','),
+            Ok((-0.8, 0.156))
+        );
+        assert!(parse_pair::<u32>("--size", "640", 'x').is_err());
+        assert!(parse_pair::<u32>("--size", "640xabc", 'x').is_err());
     }
 }
@@ -0,0 +1,124 @@
+//! Render a view to a PNG file without opening a window, for image snapshots in CI.
+//!
+//! The GL context comes from glutin's headless contexts: an EGL surfaceless or pbuffer context
+//! on Linux, a hidden window on Windows and macOS. glutin still needs an event loop, which
+//! needs an X11 or Wayland display on Linux. Where no context can be created the binary prints
+//! why and exits with [`SKIP`], so a CI job can tell a skipped snapshot from a failed one.
+//!
+//! Build it with `cargo run --release --features headless --bin render -- --help`.
+
+#![warn(clippy::all, rust_2018_idioms)]
+
+use glutin::{dpi::PhysicalSize, event_loop::EventLoop, ContextBuilder, GlProfile, GlRequest};
+
+/// Exit code when no headless GL context is available, the "skipped" code of automake tests
+const SKIP: i32 = 77;
+
+const USAGE: &str = "\
+Usage: render --view NAME [OPTIONS]
+
+Options:
+  --view NAME       view to render, e.g. mandelbrot, julia, koch-snowflake
+  --output PATH     PNG file to write, fractal.png by default
+  --size WxH        size of the image in pixels, 1920x1080 by default
+  --c RE,IM         c of the Julia set
+  --center X,Y      center of the complex plane
+  --zoom RATIO      magnification of the complex plane
+  --m N             exponent of z^m + c
+  --depth N         depth of the Koch, Sierpinski, dragon curve, Pythagoras tree
+                    or L-system views
+  -h, --help        print this help
+
+Exits with 77 when no headless GL context can be created.";
+
+struct Options {
+    args: fractal_demo::Args,
+    output: String,
+    size: (u32, u32),
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    let options = parse_args();
+
+    // winit panics instead of returning an error when there is no display to connect to
+    let event_loop = match std::panic::catch_unwind(EventLoop::new) {
+        Ok(event_loop) => event_loop,
+        Err(_) => skip("no display for the event loop"),
+    };
+    let context = ContextBuilder::new()
+        .with_gl(GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(GlProfile::Core)
+        .build_headless(&event_loop, PhysicalSize::new(1, 1))
+        .unwrap_or_else(|err| skip(&err.to_string()));
+    // SAFETY: the only context of this thread, made current once and kept until exit
+    let context = match unsafe { context.make_current() } {
+        Ok(context) => context,
+        Err((_, err)) => skip(&err.to_string()),
+    };
+    // SAFETY: the context the functions are loaded from is current
+    let gl = unsafe {
+        glow::Context::from_loader_function(|name| context.get_proc_address(name) as *const _)
+    };
+
+    match fractal_demo::render_headless(&gl, &options.args, options.size) {
+        Ok(png) => {
+            if let Err(err) = std::fs::write(&options.output, png) {
+                eprintln!("Cannot write {}: {}", options.output, err);
+                std::process::exit(1);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn skip(reason: &str) -> ! {
+    eprintln!("No headless GL context, skipping: {}", reason);
+    std::process::exit(SKIP);
+}
+
+/// Parse the command line, printing the usage and exiting on bad input.
+fn parse_args() -> Options {
+    fn fail(message: &str) -> ! {
+        eprintln!("{}\n\n{}", message, USAGE);
+        std::process::exit(2);
+    }
+
+    let mut options = Options {
+        args: fractal_demo::Args::default(),
+        output: "fractal.png".to_owned(),
+        size: (1920, 1080),
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(flag) = iter.next() {
+        if flag == "-h" || flag == "--help" {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        let value = iter
+            .next()
+            .unwrap_or_else(|| fail(&format!("{} expects a value", flag)));
+        match flag.as_str() {
+            "--output" => options.output = value,
+            "--size" => {
+                options.size =
+                    fractal_demo::parse_pair(&flag, &value, 'x').unwrap_or_else(|err| fail(&err))
+            }
+            _ => match options.args.parse_option(&flag, &value) {
+                Ok(true) => {}
+                Ok(false) => fail(&format!("Unknown option {}", flag)),
+                Err(err) => fail(&err),
+            },
+        }
+    }
+    if options.args.view.is_none() {
+        fail("--view is required");
+    }
+    if options.size.0 == 0 || options.size.1 == 0 {
+        fail("--size must be at least 1x1");
+    }
+    options
+}
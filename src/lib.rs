@@ -3,7 +3,8 @@
 
 mod app;
 mod view;
-pub use app::FractalApp;
+pub use app::{parse_pair, render_headless, view_keys, view_matches, Args, FractalApp};
+pub use view::ViewArgs;
 
 // ----------------------------------------------------------------------------
 // When compiling for web:
@@ -24,5 +25,8 @@ pub fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
     // Redirect tracing to console.log and friends:
     tracing_wasm::set_as_global_default();
 
-    eframe::start_web(canvas_id, Box::new(|cc| Box::new(FractalApp::new(cc))))
+    eframe::start_web(
+        canvas_id,
+        Box::new(|cc| Box::new(FractalApp::new(cc, Args::default()))),
+    )
 }
@@ -23,10 +23,65 @@ fn main() {
         )
         .init();
 
+    let args = parse_args();
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "Fractal Viewer",
         native_options,
-        Box::new(|cc| Box::new(fractal_demo::FractalApp::new(cc))),
+        Box::new(|cc| Box::new(fractal_demo::FractalApp::new(cc, args))),
     );
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+const USAGE: &str = "\
+Usage: fractal_viewer [OPTIONS]
+
+Options:
+  --view NAME       open this view, e.g. mandelbrot, julia, koch-snowflake
+  --c RE,IM         c of the Julia set
+  --center X,Y      center of the complex plane
+  --zoom RATIO      magnification of the complex plane
+  --m N             exponent of z^m + c
+  --depth N         depth of the Koch, Sierpinski, dragon curve, Pythagoras tree
+                    or L-system views
+  -h, --help        print this help";
+
+/// Parse the command line, printing the usage and exiting on bad input.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_args() -> fractal_demo::Args {
+    fn fail(message: &str) -> ! {
+        eprintln!("{}\n\n{}", message, USAGE);
+        std::process::exit(2);
+    }
+
+    let mut args = fractal_demo::Args::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(flag) = iter.next() {
+        if flag == "-h" || flag == "--help" {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        let value = iter
+            .next()
+            .unwrap_or_else(|| fail(&format!("{} expects a value", flag)));
+        match args.parse_option(&flag, &value) {
+            Ok(true) => {}
+            Ok(false) => fail(&format!("Unknown option {}", flag)),
+            Err(err) => fail(&err),
+        }
+    }
+    if let Some(query) = &args.view {
+        let keys = fractal_demo::view_keys();
+        if !keys
+            .iter()
+            .any(|key| fractal_demo::view_matches(key, query))
+        {
+            eprintln!("Unknown view {:?}, the views are:", query);
+            for key in keys {
+                eprintln!("  {}", key);
+            }
+            std::process::exit(2);
+        }
+    }
+    args
+}
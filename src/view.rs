@@ -1,18 +1,485 @@
+#[cfg(not(target_arch = "wasm32"))]
+mod animation;
+mod barnsley_fern;
+pub mod bookmark;
+mod dragon_curve;
+pub mod export;
 mod fractal_clock;
+mod frame_cache;
+mod history;
 mod juliaset_shader;
 mod koch_snowflake;
+mod levels;
+mod lsystem;
+mod mandelbrot_julia_split;
 mod mandelbrot_shader;
+mod newton;
+mod plane;
+mod pythagoras_tree;
+mod rng;
+mod sierpinski_carpet;
 mod sierpinski_triangle;
 
-use eframe::egui::Ui;
+pub use barnsley_fern::BarnsleyFern;
+pub use dragon_curve::DragonCurve;
+use eframe::egui::{
+    CollapsingHeader, ColorImage, Context, Frame, Id, Key, Rect, Stroke, TextEdit, Ui,
+};
+#[cfg(feature = "persistence")]
+use eframe::epi;
 pub use fractal_clock::FractalClock;
 pub use juliaset_shader::JuliaSetShader;
 pub use koch_snowflake::KochSnowFlake;
+pub use lsystem::LSystem;
+pub use mandelbrot_julia_split::MandelbrotJuliaSplit;
 pub use mandelbrot_shader::MandelbrotShader;
+pub use newton::NewtonShader;
+pub use pythagoras_tree::PythagorasTree;
+pub use sierpinski_carpet::SierpinskiCarpet;
 pub use sierpinski_triangle::SierpinskiTriangle;
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
+/// `c` picked in the Mandelbrot view, waiting to be shown by the Julia view
+pub type JuliaSeed = Rc<Cell<Option<(f32, f32)>>>;
+
+/// View parameters given on the command line, each view applies those it understands.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ViewArgs {
+    pub center: Option<(f64, f64)>,
+    /// Magnification, the `ratio` of the complex plane views
+    pub zoom: Option<f64>,
+    pub c: Option<(f32, f32)>,
+    /// Exponent of `z^m + c`
+    pub m: Option<i32>,
+    pub depth: Option<u32>,
+}
+
+/// A fractal the app can show, as a boxed `dyn View`.
+///
+/// Only [`Self::name`], [`Self::ui`] and [`Self::options_ui`] must be written, every other
+/// method has a default doing nothing or answering `None` or `false` for "not supported".
+/// New methods must come with such a default, so adding one does not touch every view.
 pub trait View {
+    /// Title of the view in the menu bar, also the key of its saved settings.
     fn name(&self) -> &'static str;
-    fn is_dynamic(&self) -> bool;
+    /// Whether the view animates, the app then keeps repainting it.
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+    /// Whether the view paints with the glow context, so it can't run without one. Views
+    /// returning `false` only paint egui shapes and can't be rendered to an image.
+    fn requires_gl(&self) -> bool {
+        true
+    }
+    /// Paint the view over the whole of `ui`, with [`settings_popup`] on top.
     fn ui(&mut self, ui: &mut Ui);
+    /// The settings of the view, shown by [`settings_popup`] over the canvas or by the app in
+    /// a side panel when the settings are pinned.
+    fn options_ui(&mut self, ui: &mut Ui);
+    /// Restore the default parameters, triggered by the reset button or the Home key.
+    fn reset(&mut self) {}
+    /// Extra line for the frame timing overlay, e.g. how much work the last frame did.
+    fn debug_stats(&self) -> Option<String> {
+        None
+    }
+    /// Current position in the view, e.g. the center and zoom, shown in the status bar.
+    fn status_text(&self) -> Option<String> {
+        None
+    }
+    /// What the view draws in one line, then the formula it iterates, shown in the About window.
+    fn description(&self) -> Option<&'static str> {
+        None
+    }
+    /// Short description of the controls, shown in a "Help" section next to the settings.
+    fn help(&self) -> Option<&'static str> {
+        None
+    }
+    /// Go back to the settings before the last change, triggered by Ctrl+Z.
+    fn undo(&mut self) {}
+    /// Reapply the last undone change, triggered by Ctrl+Y or Ctrl+Shift+Z.
+    fn redo(&mut self) {}
+    /// Take the parameters given on the command line or in a shared link, once at startup.
+    fn apply_args(&mut self, _args: &ViewArgs) {}
+    /// The current parameters in the form [`Self::apply_args`] takes, to share the view as a link.
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs::default()
+    }
+    /// Saved locations of the view, exported by the app as presets.
+    fn bookmarks(&self) -> &[bookmark::Bookmark] {
+        &[]
+    }
+    /// Add the locations of an imported preset file, ignored by views without bookmarks.
+    fn import_bookmarks(&mut self, _bookmarks: Vec<bookmark::Bookmark>) {}
+    /// Render the view at `size` pixels, for saving or copying. `None` if it can't be rendered
+    /// offscreen, like the fractal clock which is painted with egui shapes.
+    fn render_to_image(&self, _gl: &glow::Context, _size: (u32, u32)) -> Option<ColorImage> {
+        None
+    }
+    /// Name of the parameter the benchmark sweeps and the values it renders, the iteration
+    /// counts of the escape time views or the depths of the geometry views. `None` for the
+    /// views the benchmark can't render.
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        None
+    }
+    /// Render like [`Self::render_to_image`], with the parameter of [`Self::benchmark_sweep`]
+    /// at `value`.
+    fn render_benchmark(
+        &self,
+        _gl: &glow::Context,
+        _size: (u32, u32),
+        _value: u32,
+    ) -> Option<ColorImage> {
+        None
+    }
+    /// Create what could not be deserialized, like the GL resources, from the creation context.
+    /// Called after the view is restored from storage or built from its defaults.
+    fn post_load(&mut self, _cc: &eframe::CreationContext<'_>) {}
+    /// Like [`Self::post_load`] from a bare GL context, for rendering without a window. Also
+    /// waits for the geometry of the current settings, so [`Self::render_to_image`] draws it
+    /// in full. Views building their GL resources for each render have nothing to do.
+    fn init_headless(&mut self, _gl: &glow::Context) -> Result<(), String> {
+        Ok(())
+    }
+    /// Store the view settings, they are restored by [`restore`] on the next start.
+    #[cfg(feature = "persistence")]
+    fn save(&self, _storage: &mut dyn epi::Storage) {}
+}
+
+/// Scale of the x and y clip coordinates that keeps shapes undistorted in a viewport whose
+/// height is `ratio` times its width. Only the longer side is shrunk, so a shape inside the
+/// unit square stays entirely visible.
+fn aspect_scale(ratio: f32) -> (f32, f32) {
+    if ratio < 1.0 {
+        (ratio, 1.0)
+    } else {
+        (1.0, 1.0 / ratio)
+    }
+}
+
+/// Iteration counts the benchmark renders the escape time views with
+const BENCHMARK_ITERATIONS: [u32; 6] = [64, 128, 256, 512, 1024, 2048];
+
+/// Vertices a geometry view may draw at its deepest level. Past this, computing and
+/// uploading a level stalls the app for seconds.
+const VERTEX_BUDGET: usize = 500_000;
+
+/// The deepest depth from `min` whose `vertices(depth)` fits in [`VERTEX_BUDGET`].
+fn max_depth(min: u32, vertices: impl Fn(u32) -> usize) -> u32 {
+    let mut depth = min;
+    while vertices(depth + 1) <= VERTEX_BUDGET {
+        depth += 1;
+    }
+    depth
+}
+
+/// `#version` line for the shaders, GLSL ES 3.00 for WebGL 2 and GLSL 3.30 natively.
+fn shader_version() -> &'static str {
+    if cfg!(target_arch = "wasm32") {
+        // in/out
+        "#version 300 es"
+    } else {
+        // location
+        "#version 330"
+    }
+}
+
+/// Compile and link `sources`, each a shader type and its source without the `#version` line.
+/// The error is the info log of the failed stage, nothing is left behind on failure.
+unsafe fn create_program(
+    gl: &glow::Context,
+    sources: &[(u32, &str)],
+) -> Result<glow::Program, String> {
+    use glow::HasContext as _;
+
+    let program = gl.create_program()?;
+    let mut shaders = Vec::with_capacity(sources.len());
+    let mut error = None;
+    for (shader_type, source) in sources {
+        let shader = match gl.create_shader(*shader_type) {
+            Ok(shader) => shader,
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        };
+        gl.shader_source(shader, &format!("{}\n{}", shader_version(), source));
+        gl.compile_shader(shader);
+        gl.attach_shader(program, shader);
+        shaders.push(shader);
+        if !gl.get_shader_compile_status(shader) {
+            error = Some(gl.get_shader_info_log(shader));
+            break;
+        }
+    }
+    if error.is_none() {
+        gl.link_program(program);
+        if !gl.get_program_link_status(program) {
+            error = Some(gl.get_program_info_log(program));
+        }
+    }
+
+    for shader in shaders {
+        gl.detach_shader(program, shader);
+        gl.delete_shader(shader);
+    }
+    match error {
+        Some(error) => {
+            gl.delete_program(program);
+            Err(error)
+        }
+        None => Ok(program),
+    }
+}
+
+/// The GL objects of a view, built by a `Context::new` of the view
+trait GlResources: Sized {
+    /// The program the view paints with, checked by [`revive`]
+    fn program(&self) -> glow::Program;
+    /// Take over what outlives the GL context from the `lost` resources, like the geometry
+    /// built on the CPU, so that [`revive`] doesn't compute it again.
+    fn keep_from(&mut self, _lost: Self) {}
+}
+
+/// Whether [`revive`] failed to build the GL resources again, so that the error is logged once
+/// while the context stays lost instead of every frame. All the views share one GL context.
+static REVIVE_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Build `resources` again with `build` if the GL context lost them, which happens when the OS
+/// suspends the GPU or the browser drops the WebGL context. Called before painting, so the
+/// canvas does not stay blank after the machine wakes up. The lost objects went away with the
+/// old context, so they are not deleted.
+fn revive<R: GlResources>(
+    resources: &mut R,
+    gl: &glow::Context,
+    build: impl FnOnce(&glow::Context) -> Result<R, String>,
+) {
+    use glow::HasContext as _;
+    // a program of a lost context reads as not linked, on desktop GL and WebGL alike
+    if unsafe { gl.get_program_link_status(resources.program()) } {
+        return;
+    }
+    let failed = REVIVE_FAILED.load(Ordering::Relaxed);
+    if !failed {
+        tracing::warn!("GL context lost, building the GL resources again");
+    }
+    match build(gl) {
+        Ok(new) => {
+            let lost = std::mem::replace(resources, new);
+            resources.keep_from(lost);
+            if failed {
+                tracing::info!("GL resources built again");
+            }
+            REVIVE_FAILED.store(false, Ordering::Relaxed);
+        }
+        Err(err) => {
+            if !REVIVE_FAILED.swap(true, Ordering::Relaxed) {
+                tracing::error!(%err, "Cannot build the GL resources again");
+            }
+        }
+    }
+}
+
+/// Fill the current viewport with `color`. `glClear` ignores the viewport, so it is scissored
+/// to it, within the scissor box already set by egui if any.
+fn clear_viewport(gl: &glow::Context, color: [f32; 3]) {
+    use glow::HasContext as _;
+    unsafe {
+        let mut viewport = [0; 4];
+        gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+        let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+        let mut saved = [0; 4];
+        gl.get_parameter_i32_slice(glow::SCISSOR_BOX, &mut saved);
+        let mut clip = viewport;
+        if scissor {
+            let (left, bottom) = (viewport[0].max(saved[0]), viewport[1].max(saved[1]));
+            let right = (viewport[0] + viewport[2]).min(saved[0] + saved[2]);
+            let top = (viewport[1] + viewport[3]).min(saved[1] + saved[3]);
+            clip = [left, bottom, (right - left).max(0), (top - bottom).max(0)];
+        }
+        gl.enable(glow::SCISSOR_TEST);
+        gl.scissor(clip[0], clip[1], clip[2], clip[3]);
+        gl.clear_color(color[0], color[1], color[2], 1.0);
+        gl.clear(glow::COLOR_BUFFER_BIT);
+        gl.scissor(saved[0], saved[1], saved[2], saved[3]);
+        if !scissor {
+            gl.disable(glow::SCISSOR_TEST);
+        }
+    }
+}
+
+/// Color picker of the background behind the transparent pixels of a shader view, `None`
+/// leaves the canvas of the app visible.
+fn background_ui(ui: &mut Ui, background: &mut Option<[f32; 3]>) {
+    ui.horizontal(|ui| {
+        ui.label("background :");
+        let mut color = background.unwrap_or([0.0; 3]);
+        if ui.color_edit_button_rgb(&mut color).changed() {
+            *background = Some(color);
+        }
+        if background.is_some() && ui.button("canvas").clicked() {
+            *background = None;
+        }
+    });
+}
+
+/// Single line text field editing `value` as text, applied by pressing enter if `parse` takes
+/// it. The text being edited lives in egui memory under `id_source`, otherwise the field would
+/// show `format(value)` again at every keystroke.
+fn text_value_ui<T>(
+    ui: &mut Ui,
+    id_source: &str,
+    value: &mut T,
+    format: impl FnOnce(&T) -> String,
+    parse: impl FnOnce(&str) -> Option<T>,
+) {
+    let id = ui.id().with(id_source);
+    let mut text = ui
+        .data()
+        .get_temp::<String>(id)
+        .unwrap_or_else(|| format(value));
+    let response = ui.add(TextEdit::singleline(&mut text).desired_width(80.0));
+    if response.lost_focus() && ui.input().key_pressed(Key::Enter) {
+        if let Some(parsed) = parse(&text) {
+            *value = parsed;
+        }
+    }
+    if response.has_focus() {
+        ui.data().insert_temp(id, text);
+    } else {
+        ui.data().remove::<String>(id);
+    }
+}
+
+/// Id of the pinned flag in the egui memory, set by the app for the views
+const SETTINGS_PINNED: &str = "settings pinned";
+
+/// Tell the views whether the app shows their settings in its side panel this frame.
+pub fn set_settings_pinned(ctx: &Context, pinned: bool) {
+    ctx.memory()
+        .data
+        .insert_temp(Id::new(SETTINGS_PINNED), pinned);
+}
+
+/// Id of the hidden flag in the egui memory, set by the app for the views
+const UI_HIDDEN: &str = "ui hidden";
+
+/// Tell the views whether the app hides its interface this frame, leaving only the canvas.
+pub fn set_ui_hidden(ctx: &Context, hidden: bool) {
+    ctx.memory().data.insert_temp(Id::new(UI_HIDDEN), hidden);
+}
+
+/// Popup over the top left corner of the canvas with `header`, like the coordinates under the
+/// cursor, then the settings and help of `view`. The settings and help are left out while they
+/// are pinned to the side panel of the app, and the whole popup while the app hides its
+/// interface. Returns the area of the popup, where the canvas ignores the pointer.
+fn settings_popup(ui: &mut Ui, view: &mut dyn View, header: Option<String>) -> Rect {
+    let hidden = ui
+        .ctx()
+        .memory()
+        .data
+        .get_temp(Id::new(UI_HIDDEN))
+        .unwrap_or(false);
+    if hidden {
+        return Rect::NOTHING;
+    }
+    let pinned = ui
+        .ctx()
+        .memory()
+        .data
+        .get_temp(Id::new(SETTINGS_PINNED))
+        .unwrap_or(false);
+    if pinned && header.is_none() {
+        return Rect::NOTHING;
+    }
+    Frame::popup(ui.style())
+        .stroke(Stroke::none())
+        .show(ui, |ui| {
+            ui.set_max_width(250.0);
+            if let Some(header) = header {
+                ui.label(header);
+            }
+            if !pinned {
+                CollapsingHeader::new("Settings").show(ui, |ui| view.options_ui(ui));
+                if let Some(help) = view.help() {
+                    CollapsingHeader::new("Help").show(ui, |ui| ui.label(help));
+                }
+            }
+        })
+        .response
+        .rect
+}
+
+/// Whether the keyboard shortcuts of a view may act, which they don't while a `DragValue` or
+/// text field is being edited. Checked before the widgets of the frame are added, so the key
+/// that ends an edit (Enter, Escape) is not taken as a shortcut either.
+fn shortcuts_enabled(ui: &Ui) -> bool {
+    !ui.ctx().wants_keyboard_input()
+}
+
+/// Shown instead of a view whose GL resources could not be created.
+fn gl_error_ui(ui: &mut Ui, error: &str) {
+    ui.colored_label(
+        eframe::egui::Color32::RED,
+        format!(
+            "This view can't be drawn, its shaders failed to build:\n\n{}",
+            error
+        ),
+    );
+}
+
+/// Load the settings saved by [`View::save`], falling back to the defaults, which are all there
+/// is without a creation context.
+#[cfg(feature = "persistence")]
+fn restore<T>(cc: Option<&eframe::CreationContext<'_>>) -> T
+where
+    T: View + Default + serde::de::DeserializeOwned,
+{
+    let default = T::default();
+    cc.and_then(|cc| cc.storage)
+        .and_then(|storage| epi::get_value(storage, default.name()))
+        .unwrap_or(default)
+}
+
+#[cfg(not(feature = "persistence"))]
+fn restore<T: Default>(_cc: Option<&eframe::CreationContext<'_>>) -> T {
+    T::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_scale_keeps_triangles_equilateral() {
+        let s = 3.0f32.sqrt();
+        let triangle = [(-0.8, -0.8 / s), (0.8, -0.8 / s), (0.0, 1.6 / s)];
+        for (width, height) in [(1600.0f32, 900.0f32), (900.0, 1600.0)] {
+            let (sx, sy) = aspect_scale(height / width);
+            // clip coordinates to pixels
+            let corners = triangle
+                .map(|(x, y)| ((x * sx + 1.0) / 2.0 * width, (y * sy + 1.0) / 2.0 * height));
+            let edge = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+            let edges = [
+                edge(corners[0], corners[1]),
+                edge(corners[1], corners[2]),
+                edge(corners[2], corners[0]),
+            ];
+            for e in edges {
+                assert!(
+                    (e - edges[0]).abs() < 1e-2,
+                    "{:?} at {}x{}",
+                    edges,
+                    width,
+                    height
+                );
+            }
+            assert!(corners
+                .iter()
+                .all(|&(x, y)| (0.0..=width).contains(&x) && (0.0..=height).contains(&y)));
+        }
+    }
 }
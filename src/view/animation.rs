@@ -0,0 +1,160 @@
+//! Export of a zoom between two places of the plane as numbered PNG frames
+//!
+//! The frames are rendered one per egui frame from the paint callback of the view, so the
+//! window stays responsive and the export can be cancelled.
+
+use super::plane;
+use eframe::egui::*;
+use parking_lot::Mutex;
+use std::{path::PathBuf, sync::Arc};
+
+/// A place of the plane, as `(center, ratio)`
+type Place = ((f64, f64), f64);
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Animation {
+    start: Option<Place>,
+    end: Option<Place>,
+    frames: u32,
+    size: (u32, u32),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    job: Option<Arc<Mutex<Job>>>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            start: None,
+            end: None,
+            frames: 120,
+            size: (1920, 1080),
+            job: None,
+        }
+    }
+}
+
+impl Animation {
+    /// Settings and progress of the export, `center` and `ratio` are the current view.
+    pub fn ui(&mut self, ui: &mut Ui, center: (f64, f64), ratio: f64) {
+        CollapsingHeader::new("Export animation").show(ui, |ui| {
+            for (label, place) in [("start", &mut self.start), ("end", &mut self.end)] {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} :", label));
+                    match place {
+                        Some((_, r)) => ui.label(format!("zoom {:.3e}", r)),
+                        None => ui.label("not set"),
+                    };
+                    if ui.button("set to view").clicked() {
+                        *place = Some((center, ratio));
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("frames :");
+                ui.add(DragValue::new(&mut self.frames).clamp_range(2..=10000));
+            });
+            ui.horizontal(|ui| {
+                ui.label("size :");
+                ui.add(DragValue::new(&mut self.size.0).clamp_range(16..=8192));
+                ui.label("×");
+                ui.add(DragValue::new(&mut self.size.1).clamp_range(16..=8192));
+            });
+
+            let finished = self.job.as_ref().map(|job| job.lock().finished());
+            match finished {
+                Some(false) => {
+                    let mut job = self.job.as_ref().unwrap().lock();
+                    let progress = job.done as f32 / job.frames as f32;
+                    ui.add(
+                        ProgressBar::new(progress)
+                            .text(format!("frame {} / {}", job.done, job.frames)),
+                    );
+                    if ui.button("Cancel").clicked() {
+                        job.cancelled = true;
+                    }
+                }
+                Some(true) => self.job = None,
+                None => {}
+            }
+            let ready = self.job.is_none() && self.start.is_some() && self.end.is_some();
+            if ui.add_enabled(ready, Button::new("Render")).clicked() {
+                if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    self.job = Some(Arc::new(Mutex::new(Job {
+                        dir,
+                        from: self.start.unwrap(),
+                        to: self.end.unwrap(),
+                        frames: self.frames,
+                        size: self.size,
+                        done: 0,
+                        cancelled: false,
+                        failed: false,
+                    })));
+                }
+            }
+        });
+    }
+
+    /// The running export, to be stepped from the paint callback
+    pub fn job(&self) -> Option<Arc<Mutex<Job>>> {
+        self.job.clone()
+    }
+
+    /// Whether frames are left to export. The paint callback renders one per repaint, so the
+    /// view keeps repainting until then, even with this header or the UI hidden.
+    pub fn running(&self) -> bool {
+        self.job
+            .as_ref()
+            .map_or(false, |job| !job.lock().finished())
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    dir: PathBuf,
+    from: Place,
+    to: Place,
+    frames: u32,
+    size: (u32, u32),
+    /// Frames written so far
+    done: u32,
+    cancelled: bool,
+    failed: bool,
+}
+
+impl Job {
+    fn finished(&self) -> bool {
+        self.cancelled || self.failed || self.done >= self.frames
+    }
+
+    /// Render the next frame with `render` and write it to `frame_0001.png`, ... with enough
+    /// digits for every frame to sort by name. `render` gets the center, ratio and size of the
+    /// frame and returns RGBA8 rows from top to bottom.
+    pub fn step(&mut self, render: impl FnOnce((f64, f64), f64, (u32, u32)) -> Option<Vec<u8>>) {
+        if self.finished() {
+            return;
+        }
+        let s = self.done as f64 / (self.frames - 1) as f64;
+        let (center, ratio) = plane::interpolate(self.from, self.to, s);
+        let digits = self.frames.to_string().len().max(4);
+        let path = self.dir.join(format!(
+            "frame_{:0digits$}.png",
+            self.done + 1,
+            digits = digits
+        ));
+        let (width, height) = self.size;
+        let saved = render(center, ratio, self.size).and_then(|pixels| {
+            image::save_buffer(&path, &pixels, width, height, image::ColorType::Rgba8)
+                .map_err(|err| tracing::error!(%err, ?path, "Cannot save frame"))
+                .ok()
+        });
+        match saved {
+            Some(()) => self.done += 1,
+            None => self.failed = true,
+        }
+        if self.done == self.frames {
+            tracing::info!(dir = ?self.dir, frames = self.frames, "Animation exported");
+        }
+    }
+}
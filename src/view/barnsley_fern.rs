@@ -0,0 +1,338 @@
+use super::{
+    export,
+    rng::{self, Rng},
+    View,
+};
+use eframe::{
+    egui::{self, containers::*, *},
+    emath::{pos2, Pos2},
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{
+    mem::{size_of, swap},
+    sync::Arc,
+};
+
+const DEFAULT_COUNT: u32 = 50_000;
+const MAX_COUNT: u32 = 200_000;
+const DEFAULT_COLOR: [f32; 3] = [0.2, 0.8, 0.3];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BarnsleyFern {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    count: u32,
+    color: [f32; 3],
+    /// Seed of the chaos game, the same seed always draws the same fern
+    seed: u64,
+}
+
+impl Default for BarnsleyFern {
+    fn default() -> Self {
+        Self {
+            gl: Default::default(),
+            error: None,
+            count: DEFAULT_COUNT,
+            color: DEFAULT_COLOR,
+            seed: rng::DEFAULT_SEED,
+        }
+    }
+}
+
+impl super::View for BarnsleyFern {
+    fn name(&self) -> &'static str {
+        "Barnsley Fern"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "The attractor of four affine maps picked at random, like a chaos game.\n\
+             (x, y) -> (a x + b y + e, c x + d y + f), the stem map 1%, the leaf 85%",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "points: how many steps of the chaos game are drawn\n\
+             seed: type one and press enter to draw the same fern again\n\
+             Home: reset the settings",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let context = self
+            .gl
+            .get_or_try_init(|| Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context))));
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        let context = Context::new(gl)?;
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, self.count, self.seed, self.color, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+
+        super::settings_popup(ui, self, None);
+
+        let gl = self.gl.clone();
+        let count = self.count;
+        let seed = self.seed;
+        let color = self.color;
+        let ratio = rect.height() / rect.width();
+
+        let callback = egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut gl = gl.get().unwrap().lock();
+                    super::revive(&mut *gl, painter.gl(), Context::new);
+                    gl.paint(painter.gl(), count, seed, color, ratio);
+                } else {
+                    eprintln!("Can't do custom painting because we are not using a glow context");
+                }
+            }),
+        };
+        painter.add(callback);
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Points :");
+            ui.add(
+                DragValue::new(&mut self.count)
+                    .speed(100.0)
+                    .clamp_range(1..=MAX_COUNT),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Color :");
+            ui.color_edit_button_rgb(&mut self.color);
+        });
+        rng::seed_ui(ui, &mut self.seed);
+        if ui.button("reset").clicked() {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.count = DEFAULT_COUNT;
+        self.color = DEFAULT_COLOR;
+        self.seed = rng::DEFAULT_SEED;
+    }
+}
+
+impl BarnsleyFern {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+}
+
+/// The four affine maps of the fern, `[a, b, c, d, e, f, cumulative probability]`:
+/// x' = a x + b y + e, y' = c x + d y + f
+const TRANSFORMS: [[f32; 7]; 4] = [
+    [0.0, 0.0, 0.0, 0.16, 0.0, 0.0, 0.01],
+    [0.85, 0.04, -0.04, 0.85, 0.0, 1.6, 0.86],
+    [0.2, -0.26, 0.23, 0.22, 0.0, 1.6, 0.93],
+    [-0.15, 0.28, 0.26, 0.24, 0.0, 0.44, 1.0],
+];
+
+#[derive(Debug)]
+struct Context {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+    /// Every point generated so far, the chaos game only ever appends
+    vertices: Vec<Pos2>,
+    /// Seed the points were generated from
+    seed: u64,
+    rng: Rng,
+    /// Number of points in the GPU buffer, `None` before the first upload
+    count: Option<u32>,
+}
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+uniform vec2 uni_scale;
+void main() {
+    // The fern lives in x in [-2.2, 2.7], y in [0, 10]
+    vec2 pos = (in_pos - vec2(0.25, 5.0)) / 5.2;
+    gl_Position = vec4(pos, 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+    gl_PointSize = 1.0;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+uniform vec3 uni_color;
+out vec4 out_color;
+void main() {
+    out_color = vec4(uni_color, 1.0);
+}
+"#;
+
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+}
+
+impl Context {
+    fn new(gl: &glow::Context) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            Ok(Self {
+                program,
+                vao: gl.create_vertex_array().unwrap(),
+                vbo: gl.create_buffer().unwrap(),
+                vertices: vec![pos2(0.0, 0.0)],
+                seed: rng::DEFAULT_SEED,
+                rng: Rng::new(rng::DEFAULT_SEED),
+                count: None,
+            })
+        }
+    }
+
+    /// Make sure at least `count` points are generated from `seed`.
+    fn calc(&mut self, count: u32, seed: u64) {
+        if self.seed != seed {
+            self.seed = seed;
+            self.rng = Rng::new(seed);
+            self.vertices.truncate(1);
+        }
+        let count = count as usize;
+        if self.vertices.len() >= count {
+            return;
+        }
+        self.vertices.reserve(count - self.vertices.len());
+        let mut p = *self.vertices.last().unwrap();
+        while self.vertices.len() < count {
+            let r = self.rng.next_f32();
+            let t = TRANSFORMS
+                .iter()
+                .find(|t| r <= t[6])
+                .unwrap_or(&TRANSFORMS[3]);
+            p = pos2(
+                t[0] * p.x + t[1] * p.y + t[4],
+                t[2] * p.x + t[3] * p.y + t[5],
+            );
+            self.vertices.push(p);
+        }
+        tracing::debug!(points = self.vertices.len());
+    }
+
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, count: u32) {
+        use glow::HasContext as _;
+
+        let mut vao = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao));
+
+        let verts_slice = &self.vertices[..count as usize];
+        let verts_slice = std::slice::from_raw_parts(
+            verts_slice.as_ptr() as *const u8,
+            verts_slice.len() * size_of::<Pos2>(),
+        );
+
+        let mut vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::STATIC_DRAW);
+
+        gl.enable_vertex_attrib_array(0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+        swap(&mut self.vao, &mut vao);
+        swap(&mut self.vbo, &mut vbo);
+        gl.delete_vertex_array(vao);
+        gl.delete_buffer(vbo);
+    }
+
+    fn export(
+        &mut self,
+        gl: &glow::Context,
+        count: u32,
+        seed: u64,
+        color: [f32; 3],
+        size: (u32, u32),
+    ) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| self.paint(gl, count, seed, color, ratio))
+    }
+
+    fn paint(
+        &mut self,
+        gl: &glow::Context,
+        mut count: u32,
+        seed: u64,
+        color: [f32; 3],
+        ratio: f32,
+    ) {
+        use glow::HasContext as _;
+        count = count.clamp(1, MAX_COUNT);
+        if self.count != Some(count) || self.seed != seed {
+            self.calc(count, seed);
+            self.count = Some(count);
+            unsafe { self.update_vertices(gl, count) };
+        }
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.bind_vertex_array(Some(self.vao));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            gl.uniform_3_f32(
+                gl.get_uniform_location(self.program, "uni_color").as_ref(),
+                color[0],
+                color[1],
+                color[2],
+            );
+            gl.draw_arrays(glow::POINTS, 0, count as i32);
+        }
+    }
+}
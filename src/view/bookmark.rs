@@ -0,0 +1,95 @@
+//! Saved locations of the escape time views, and the preset files sharing them
+
+#[cfg(feature = "persistence")]
+use super::export;
+use super::plane;
+use eframe::egui::{Button, Ui};
+
+/// A spot in the complex plane and the parameters it was found with
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Bookmark {
+    pub center: (f64, f64),
+    pub ratio: f64,
+    pub m: i32,
+    /// `None` for views without a `c` parameter
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub c: Option<(f32, f32)>,
+    /// `None` for views with a fixed iteration count
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub iterations: Option<i32>,
+}
+
+/// A bookmark in a preset file, with the name of the view it belongs to
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Preset {
+    /// [`super::View::name`] of the view
+    pub view: String,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub bookmark: Bookmark,
+}
+
+/// Add the `imported` bookmarks missing from `bookmarks`.
+pub fn merge(bookmarks: &mut Vec<Bookmark>, imported: Vec<Bookmark>) {
+    for bookmark in imported {
+        if !bookmarks.contains(&bookmark) {
+            bookmarks.push(bookmark);
+        }
+    }
+}
+
+/// Ask the user where to save `presets` as a JSON file.
+#[cfg(feature = "persistence")]
+pub fn save_presets(presets: &[Preset]) {
+    match serde_json::to_vec_pretty(presets) {
+        Ok(json) => export::save_file("fractal_presets.json", "Presets", "json", &json),
+        Err(err) => tracing::error!(%err, "Cannot serialize the presets"),
+    }
+}
+
+/// Ask the user for a JSON file saved by [`save_presets`], `None` if none was picked or it
+/// can't be read.
+#[cfg(all(feature = "persistence", not(target_arch = "wasm32")))]
+pub fn load_presets() -> Option<Vec<Preset>> {
+    let path = rfd::FileDialog::new()
+        .add_filter("Presets", &["json"])
+        .pick_file()?;
+    let json = std::fs::read(&path)
+        .map_err(|err| tracing::error!(%err, ?path, "Cannot read the presets"))
+        .ok()?;
+    serde_json::from_slice(&json)
+        .map_err(|err| tracing::error!(%err, ?path, "Cannot parse the presets"))
+        .ok()
+}
+
+/// List `bookmarks` with a button to add `current`, returns the bookmark clicked.
+pub fn ui(ui: &mut Ui, bookmarks: &mut Vec<Bookmark>, current: Bookmark) -> Option<Bookmark> {
+    let mut clicked = None;
+    let mut removed = None;
+    ui.horizontal(|ui| {
+        ui.label("bookmarks :");
+        if ui.button("Add bookmark").clicked() {
+            bookmarks.push(current);
+        }
+    });
+    for (i, bookmark) in bookmarks.iter().enumerate() {
+        ui.horizontal(|ui| {
+            let text = format!(
+                "{} ({:.1e}×)",
+                plane::format(bookmark.center),
+                bookmark.ratio
+            );
+            if ui.add(Button::new(text).small()).clicked() {
+                clicked = Some(bookmark.clone());
+            }
+            if ui.small_button("🗑").on_hover_text("remove").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        bookmarks.remove(i);
+    }
+    clicked
+}
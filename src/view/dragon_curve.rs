@@ -0,0 +1,349 @@
+use super::{export, levels::Levels, View, ViewArgs};
+use eframe::{
+    egui::{self, containers::*, *},
+    emath::{pos2, Pos2},
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{
+    mem::{size_of, swap},
+    sync::Arc,
+};
+
+const DEFAULT_DEPTH: u32 = 10;
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DragonCurve {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    depth: u32,
+}
+
+impl Default for DragonCurve {
+    fn default() -> Self {
+        Self {
+            gl: Default::default(),
+            error: None,
+            depth: DEFAULT_DEPTH,
+        }
+    }
+}
+
+impl super::View for DragonCurve {
+    fn name(&self) -> &'static str {
+        "Dragon Curve"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "A strip of paper folded in half again and again, then unfolded at right angles.\n\
+             every depth appends the curve turned by 90° around its end",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "+ / - buttons: change the depth\n\
+             Home: reset the depth",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let context = self
+            .gl
+            .get_or_try_init(|| Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context))));
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        let mut context = Context::new(gl)?;
+        context.levels.wait(self.depth.min(MAX_DEPTH) as usize);
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        self.gl.get()?.lock().export(gl, self.depth, size)
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        // only the depths computed so far, a deeper one would draw the deepest ready instead
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        Some(("depth", (0..ready.min(MAX_DEPTH + 1)).collect()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        self.gl.get()?.lock().export(gl, value, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+
+        super::settings_popup(ui, self, None);
+
+        let gl = self.gl.clone();
+        let depth = self.depth;
+        let ratio = rect.height() / rect.width();
+        if self.computing().is_some() {
+            // keep painting until the requested depth is ready
+            ui.ctx().request_repaint();
+        }
+
+        let callback = egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut gl = gl.get().unwrap().lock();
+                    super::revive(&mut *gl, painter.gl(), Context::new);
+                    gl.paint(painter.gl(), depth, ratio);
+                } else {
+                    eprintln!("Can't do custom painting because we are not using a glow context");
+                }
+            }),
+        };
+        painter.add(callback);
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        ui.label(format!("Painted line count: {}", 2usize.pow(self.depth)));
+        if let Some(depth) = self.computing() {
+            ui.label(format!("computing depth {}...", depth));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Depth :");
+            ui.add(
+                DragValue::new(&mut self.depth)
+                    .speed(1.0)
+                    .clamp_range(0..=MAX_DEPTH),
+            );
+            if ui.button("+").clicked() && self.depth < MAX_DEPTH {
+                self.depth += 1;
+            }
+            if ui.button("-").clicked() && self.depth > 0 {
+                self.depth -= 1;
+            }
+        });
+        if ui.button("reset").clicked() {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.depth = DEFAULT_DEPTH;
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        if let Some(depth) = args.depth {
+            self.depth = depth.min(MAX_DEPTH);
+        }
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            depth: Some(self.depth),
+            ..Default::default()
+        }
+    }
+}
+
+impl DragonCurve {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+    /// Depth being computed in the background, if the requested one is not ready yet.
+    fn computing(&self) -> Option<u32> {
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        (ready <= self.depth).then(|| ready)
+    }
+}
+
+/// Fold the curve `prev` once: append a copy of it turned by 90° around its last point and
+/// walked backwards, then scale and rotate the result back so that both ends stay in place.
+fn next_level(prev: &[Pos2]) -> Vec<Pos2> {
+    let len = prev.len();
+    let mut new = Vec::with_capacity(len * 2 - 1);
+    new.extend_from_slice(prev);
+    let last = prev[len - 1];
+    for p in prev[..len - 1].iter().rev() {
+        let d = *p - last;
+        new.push(last + vec2(-d.y, d.x));
+    }
+
+    // the similarity taking the new end onto the old one, as a complex factor
+    let start = prev[0];
+    let old = last - start;
+    let end = new[new.len() - 1] - start;
+    let norm = end.length_sq();
+    let f = vec2(
+        (old.x * end.x + old.y * end.y) / norm,
+        (old.y * end.x - old.x * end.y) / norm,
+    );
+    for p in &mut new {
+        let d = *p - start;
+        *p = start + vec2(d.x * f.x - d.y * f.y, d.x * f.y + d.y * f.x);
+    }
+    tracing::debug!(len = new.len());
+    new
+}
+
+#[derive(Debug)]
+struct Context {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+    /// `levels[d]` is the curve of depth `d`, `levels[0]` is a single segment
+    levels: Levels<Vec<Pos2>>,
+    /// Depth of the curve in the GPU buffer, `None` before the first upload
+    depth: Option<u32>,
+}
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+uniform vec2 uni_scale;
+uniform float uni_count;
+out vec3 v_color;
+void main() {
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+    // from blue at the start of the curve to orange at its end
+    float t = float(gl_VertexID) / uni_count;
+    v_color = mix(vec3(0.2, 0.5, 1.0), vec3(1.0, 0.6, 0.2), t);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+in vec3 v_color;
+out vec4 out_color;
+void main() {
+    out_color = vec4(v_color, 1.0);
+}
+"#;
+
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+
+    fn keep_from(&mut self, lost: Self) {
+        self.levels = lost.levels;
+    }
+}
+
+impl Context {
+    fn new(gl: &glow::Context) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            Ok(Self {
+                program,
+                vao: gl.create_vertex_array().unwrap(),
+                vbo: gl.create_buffer().unwrap(),
+                // The finished dragon spans 1.5 × 1 times its end to end distance
+                levels: Levels::new(
+                    vec![pos2(-0.44, -0.18), pos2(0.62, -0.18)],
+                    MAX_DEPTH as usize + 1,
+                    |prev: &Vec<Pos2>| next_level(prev),
+                ),
+                depth: None,
+            })
+        }
+    }
+
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, curve: &[Pos2]) {
+        use glow::HasContext as _;
+
+        let mut vao = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao));
+
+        let verts_slice = std::slice::from_raw_parts(
+            curve.as_ptr() as *const u8,
+            curve.len() * size_of::<Pos2>(),
+        );
+
+        let mut vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
+
+        gl.enable_vertex_attrib_array(0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+        swap(&mut self.vao, &mut vao);
+        swap(&mut self.vbo, &mut vbo);
+        gl.delete_vertex_array(vao);
+        gl.delete_buffer(vbo);
+    }
+
+    fn export(&mut self, gl: &glow::Context, depth: u32, size: (u32, u32)) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| self.paint(gl, depth, ratio))
+    }
+
+    fn paint(&mut self, gl: &glow::Context, mut depth: u32, ratio: f32) {
+        use glow::HasContext as _;
+        self.levels.step();
+        // draw the deepest ready depth until the requested one is computed
+        depth = depth.min(MAX_DEPTH).min(self.levels.ready() as u32 - 1);
+        if self.depth != Some(depth) {
+            let curve = self.levels.get(depth as usize).unwrap();
+            self.depth = Some(depth);
+            unsafe { self.update_vertices(gl, &curve) };
+        }
+        let count = 2i32.pow(depth) + 1;
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.bind_vertex_array(Some(self.vao));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "uni_count").as_ref(),
+                count as f32,
+            );
+            gl.draw_arrays(glow::LINE_STRIP, 0, count);
+        }
+    }
+}
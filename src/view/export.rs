@@ -0,0 +1,325 @@
+//! Offscreen rendering of views, saved as PNG or copied to the clipboard
+//!
+//! [`View::render_to_image`](super::View::render_to_image) renders into its own framebuffer,
+//! so it can be called from `App::update` as well as from a paint callback. The geometry
+//! views can also be saved as an [`Svg`] built from their vertices.
+
+use eframe::egui::{ColorImage, Pos2};
+use std::fmt::Write as _;
+
+/// Render `draw` into a `size` texture and read it back as an image.
+pub fn render_offscreen(
+    gl: &glow::Context,
+    size: (u32, u32),
+    draw: impl FnOnce(&glow::Context),
+) -> Option<ColorImage> {
+    render_pixels(gl, size, draw).map(|pixels| to_image(size, &pixels))
+}
+
+/// Image of RGBA8 `pixels` returned by [`render_pixels`].
+pub fn to_image(size: (u32, u32), pixels: &[u8]) -> ColorImage {
+    ColorImage::from_rgba_unmultiplied([size.0 as usize, size.1 as usize], pixels)
+}
+
+/// Render `draw` into a `size` texture and read it back as RGBA8 rows from top to bottom.
+///
+/// The GL context must be current. The viewport, scissor state and framebuffer set up by egui
+/// are restored before returning, on the web egui may be drawing into a framebuffer of its own.
+/// The draw function sees a viewport at the origin.
+pub fn render_pixels(
+    gl: &glow::Context,
+    size: (u32, u32),
+    draw: impl FnOnce(&glow::Context),
+) -> Option<Vec<u8>> {
+    use glow::HasContext as _;
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    unsafe {
+        let mut viewport = [0; 4];
+        gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+        let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+        let framebuffer = framebuffer_binding(gl);
+
+        let texture = gl.create_texture().ok()?;
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA8 as i32,
+            width,
+            height,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            None,
+        );
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        let fbo = match gl.create_framebuffer() {
+            Ok(fbo) => fbo,
+            Err(err) => {
+                tracing::error!(%err, "Cannot create the offscreen framebuffer");
+                gl.delete_texture(texture);
+                return None;
+            }
+        };
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(texture),
+            0,
+        );
+
+        let mut pixels = None;
+        if gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE {
+            gl.disable(glow::SCISSOR_TEST);
+            gl.viewport(0, 0, width, height);
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            draw(gl);
+
+            let mut buffer = vec![0u8; (size.0 * size.1 * 4) as usize];
+            gl.read_pixels(
+                0,
+                0,
+                width,
+                height,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut buffer),
+            );
+            // OpenGL rows start at the bottom
+            let stride = size.0 as usize * 4;
+            let flipped: Vec<u8> = buffer
+                .chunks_exact(stride)
+                .rev()
+                .flatten()
+                .copied()
+                .collect();
+            pixels = Some(flipped);
+        } else {
+            tracing::error!(?size, "Offscreen framebuffer is incomplete");
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+        gl.delete_framebuffer(fbo);
+        gl.delete_texture(texture);
+        gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        if scissor {
+            gl.enable(glow::SCISSOR_TEST);
+        }
+        pixels
+    }
+}
+
+/// The framebuffer bound for drawing, to bind it back after rendering offscreen. The GL
+/// context must be current.
+pub(super) unsafe fn framebuffer_binding(gl: &glow::Context) -> Option<glow::Framebuffer> {
+    use glow::HasContext as _;
+    gl.get_parameter_framebuffer(glow::FRAMEBUFFER_BINDING)
+}
+
+/// RGBA8 bytes of `image`, rows from top to bottom.
+fn rgba(image: &ColorImage) -> Vec<u8> {
+    image
+        .pixels
+        .iter()
+        .flat_map(|color| color.to_srgba_unmultiplied())
+        .collect()
+}
+
+pub fn encode_png(image: &ColorImage) -> Option<Vec<u8>> {
+    let [width, height] = image.size;
+    let buffer = image::RgbaImage::from_raw(width as u32, height as u32, rgba(image))?;
+    let mut bytes = Vec::new();
+    if let Err(err) = buffer.write_to(
+        &mut std::io::Cursor::new(&mut bytes),
+        image::ImageOutputFormat::Png,
+    ) {
+        tracing::error!(%err, "Cannot encode PNG");
+        return None;
+    }
+    Some(bytes)
+}
+
+pub fn save_png(file_name: &str, image: &ColorImage) {
+    if let Some(bytes) = encode_png(image) {
+        save_file(file_name, "PNG image", "png", &bytes);
+    }
+}
+
+/// File name for an export of the view called `name`, e.g. `mandelbrot_set_shader.png`.
+pub fn file_name(name: &str, extension: &str) -> String {
+    let words: Vec<_> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    format!("{}.{}", words.join("_").to_lowercase(), extension)
+}
+
+/// SVG document of shapes in the coordinates of the geometry views, `-1..1` on both axes
+/// with y up, the unit square the views keep visible whatever the aspect ratio.
+#[derive(Debug, Default)]
+pub struct Svg {
+    elements: String,
+}
+
+impl Svg {
+    /// Width and height of the document, in pixels
+    const SIZE: u32 = 1000;
+
+    /// Line through `points`, back to the first one if `closed`. `width` is in pixels.
+    pub fn polyline(&mut self, points: &[Pos2], closed: bool, color: [f32; 3], width: f32) {
+        let element = if closed { "polygon" } else { "polyline" };
+        let _ = writeln!(
+            self.elements,
+            r#"<{} points="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linejoin="round" vector-effect="non-scaling-stroke"/>"#,
+            element,
+            svg_points(points),
+            svg_color(color),
+            width
+        );
+    }
+
+    /// Filled polygon with the corners `points`.
+    pub fn polygon(&mut self, points: &[Pos2], color: [f32; 3]) {
+        let _ = writeln!(
+            self.elements,
+            r#"<polygon points="{}" fill="{}"/>"#,
+            svg_points(points),
+            svg_color(color)
+        );
+    }
+
+    /// The whole document, ready to be saved
+    pub fn finish(&self) -> String {
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" viewBox=\"-1 -1 2 2\">\n\
+             <g transform=\"scale(1,-1)\">\n{}</g>\n</svg>\n",
+            self.elements,
+            size = Self::SIZE
+        )
+    }
+}
+
+fn svg_points(points: &[Pos2]) -> String {
+    let mut text = String::with_capacity(points.len() * 20);
+    for p in points {
+        let _ = write!(text, "{:.5},{:.5} ", p.x, p.y);
+    }
+    text.pop();
+    text
+}
+
+fn svg_color(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+pub fn save_svg(file_name: &str, svg: &str) {
+    save_file(file_name, "SVG image", "svg", svg.as_bytes());
+}
+
+/// Put `image` on the system clipboard.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn copy_image(image: &ColorImage) {
+    let [width, height] = image.size;
+    let data = arboard::ImageData {
+        width,
+        height,
+        bytes: rgba(image).into(),
+    };
+    if let Err(err) = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_image(data))
+    {
+        tracing::error!(%err, "Cannot copy image");
+    }
+}
+
+/// Put `image` on the clipboard as a PNG, the one image type browsers accept there. Needs
+/// `--cfg=web_sys_unstable_apis` for the async Clipboard API.
+#[cfg(target_arch = "wasm32")]
+pub fn copy_image(image: &ColorImage) {
+    use eframe::wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+    let bytes = match encode_png(image) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+    let copy = || -> Result<(), JsValue> {
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&bytes[..]));
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_("image/png");
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        let clipboard = web_sys::window()
+            .ok_or("no window")?
+            .navigator()
+            .clipboard();
+        // web-sys binds neither ClipboardItem nor the `write` taking a list of them
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(&record, &JsValue::from_str("image/png"), &blob)?;
+        let item_class =
+            js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("ClipboardItem"))?
+                .dyn_into::<js_sys::Function>()?;
+        let item = js_sys::Reflect::construct(&item_class, &js_sys::Array::of1(&record))?;
+        let write = js_sys::Reflect::get(&clipboard, &JsValue::from_str("write"))?
+            .dyn_into::<js_sys::Function>()?;
+        let promise: js_sys::Promise = write
+            .call1(&clipboard, &js_sys::Array::of1(&item))?
+            .dyn_into()?;
+        let failed = Closure::wrap(Box::new(|err: JsValue| {
+            tracing::error!(?err, "Cannot copy image");
+        }) as Box<dyn FnMut(JsValue)>);
+        let _ = promise.catch(&failed);
+        // called at most once, long after this function returned
+        failed.forget();
+        Ok(())
+    };
+    if let Err(err) = copy() {
+        tracing::error!(?err, "Cannot copy image");
+    }
+}
+
+/// Ask the user where to save `bytes`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(file_name: &str, filter: &str, extension: &str, bytes: &[u8]) {
+    let path = rfd::FileDialog::new()
+        .set_file_name(file_name)
+        .add_filter(filter, &[extension])
+        .save_file();
+    if let Some(path) = path {
+        if let Err(err) = std::fs::write(&path, bytes) {
+            tracing::error!(%err, ?path, "Cannot save file");
+        }
+    }
+}
+
+/// Let the browser download `bytes`.
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(file_name: &str, _filter: &str, extension: &str, bytes: &[u8]) {
+    use eframe::wasm_bindgen::{JsCast, JsValue};
+
+    let download = || -> Result<(), JsValue> {
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_(match extension {
+            "png" => "image/png",
+            "svg" => "image/svg+xml",
+            "json" => "application/json",
+            _ => "application/octet-stream",
+        });
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or("no document")?;
+        let anchor: web_sys::HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+        anchor.set_href(&url);
+        anchor.set_download(file_name);
+        anchor.click();
+        web_sys::Url::revoke_object_url(&url)
+    };
+    if let Err(err) = download() {
+        tracing::error!(?err, "Cannot download file");
+    }
+}
@@ -1,5 +1,31 @@
+use super::View;
 use eframe::egui::{containers::*, widgets::*, *};
-use std::f32::consts::TAU;
+use std::{collections::VecDeque, f32::consts::TAU};
+
+const MAX_DEPTH: f32 = 14.0;
+/// Frame time the automatic depth aims for
+const TARGET_FRAME: f32 = 1.0 / 60.0;
+/// Average frame time above which the automatic depth drops, leaving room for the jitter of
+/// vsync around [`TARGET_FRAME`]
+const LAG_FRAME: f32 = 1.0 / 50.0;
+/// Weight of the newest frame in the average frame time
+const FRAME_TIME_SMOOTHING: f32 = 0.1;
+/// Seconds between two steps of the automatic depth, for the average to catch up
+const DEPTH_STEP: f64 = 0.5;
+/// Seconds after a lag before the automatic depth tries to go deeper again
+const DEPTH_RETRY: f64 = 10.0;
+/// Background of the ambient mode through the day, as (hour, color). Kept dark enough for
+/// the additive lines to stand out at noon.
+const AMBIENT_COLORS: [(f32, [u8; 3]); 8] = [
+    (0.0, [6, 8, 24]),
+    (5.0, [14, 14, 42]),
+    (6.5, [110, 56, 56]),
+    (9.0, [44, 82, 128]),
+    (17.0, [44, 82, 128]),
+    (19.0, [120, 56, 36]),
+    (21.0, [18, 14, 42]),
+    (24.0, [6, 8, 24]),
+];
 
 #[derive(PartialEq, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -9,13 +35,53 @@ pub struct FractalClock {
     time: f64,
     zoom: f32,
     start_line_width: f32,
-    depth: usize,
-    length_factor: f32,
+    /// Levels of branches, the fractional part fades in the last one
+    depth: f32,
+    /// Pick the depth from the frame time instead of the slider
+    auto_depth: bool,
+    /// Average frame time, in seconds
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_time: f32,
+    /// When the automatic depth last changed
+    #[cfg_attr(feature = "serde", serde(skip))]
+    depth_changed_at: f64,
+    /// When the automatic depth last dropped because frames were too slow
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lagged_at: Option<f64>,
+    /// Depth actually painted in the last frame, the branches stop once they are too dark
+    #[cfg_attr(feature = "serde", serde(skip))]
+    effective_depth: usize,
+    /// Length of the second, minute and hour hands, the branches shrink by the same factors
+    hand_lengths: [f32; 3],
+    /// Also branch from the end of the hour hand
+    hour_branch: bool,
+    /// Tint each depth with its own hue instead of `base_color`
+    rainbow: bool,
+    /// Color of the hands, and of the branches unless `rainbow`
+    base_color: [f32; 3],
     luminance_factor: f32,
+    /// Lowest luminance of a branch, out of 255. At 0 the branches stop at the depth where
+    /// they would be black, higher keeps the deeper ones at this brightness.
+    luminance_floor: u8,
+    /// Curve applied to the luminance of the branches, relative to the usual display gamma of
+    /// 2.2: higher darkens the dim branches so that overlaps clip to white later
+    gamma: f32,
     width_factor: f32,
     line_count: usize,
     timezone_offset: f64,
     offset_setting: (u8, u8, u8),
+    trail: bool,
+    /// Seconds for the trail to fade to 1/e of its brightness, whatever the frame rate
+    fade: f32,
+    /// Paint the canvas with the colors of the sky at the time of the clock
+    ambient: bool,
+    /// Tessellate the lines with [`feathered_lines`] instead of egui's one pixel feathering
+    antialias: bool,
+    /// Width of the faded edge of the lines with `antialias`, in pixels
+    feathering: f32,
+    /// Lines of the recent frames with the time they were painted at, newest last
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: VecDeque<(f64, Vec<Shape>)>,
 }
 
 impl Default for FractalClock {
@@ -27,13 +93,29 @@ impl Default for FractalClock {
             time: 0.0,
             zoom: 0.25,
             start_line_width: 2.5,
-            depth: 9,
-            length_factor: 0.8,
+            depth: 9.0,
+            auto_depth: false,
+            frame_time: TARGET_FRAME,
+            depth_changed_at: 0.0,
+            lagged_at: None,
+            effective_depth: 0,
+            hand_lengths: [0.8, 0.8, 0.5],
+            hour_branch: false,
+            rainbow: false,
+            base_color: [1.0, 1.0, 1.0],
             luminance_factor: 0.8,
+            luminance_floor: 0,
+            gamma: 2.2,
             width_factor: 0.9,
             line_count: 0,
             timezone_offset,
             offset_setting: (h, m, s),
+            trail: false,
+            fade: 0.2,
+            ambient: false,
+            antialias: false,
+            feathering: 1.5,
+            history: VecDeque::new(),
         }
     }
 }
@@ -43,14 +125,33 @@ impl super::View for FractalClock {
         "fractal clock"
     }
 
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "A clock whose hands carry smaller copies of the minute and second hands.\n\
+             every hand ends in two hands rotated and scaled like the minute and second hands",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "the hands show the current time, each branch repeats the minute and second hands\n\
+             Home: reset the settings",
+        )
+    }
+
     fn is_dynamic(&self) -> bool {
-        true
+        !self.paused
+    }
+
+    fn requires_gl(&self) -> bool {
+        false
     }
 
     fn ui(&mut self, ui: &mut Ui) {
         if !self.paused {
             self.time = self.time();
-            ui.ctx().request_repaint();
+            let (dt, now) = (ui.input().unstable_dt, ui.input().time);
+            self.fit_depth(dt, now);
         }
 
         let painter = Painter::new(
@@ -61,18 +162,11 @@ impl super::View for FractalClock {
         // Make sure we allocate what we used (everything)
         ui.expand_to_include_rect(painter.clip_rect());
 
-        Frame::popup(ui.style())
-            .stroke(Stroke::none())
-            .show(ui, |ui| {
-                ui.set_max_width(250.0);
-                CollapsingHeader::new("Settings").show(ui, |ui| self.options_ui(ui));
-            });
+        super::settings_popup(ui, self, None);
 
         self.paint(&painter);
     }
-}
 
-impl FractalClock {
     fn options_ui(&mut self, ui: &mut Ui) {
         ui.label(format!(
             "time: {:02}:{:02}:{:02}.{:03}",
@@ -84,6 +178,16 @@ impl FractalClock {
         ui.label(format!("Painted line count: {}", self.line_count));
 
         ui.checkbox(&mut self.paused, "Paused");
+        if self.paused {
+            // unpausing picks up the wall clock again in `ui`
+            const DAY: f64 = 24.0 * 60.0 * 60.0;
+            self.time = self.time.rem_euclid(DAY);
+            ui.add(
+                Slider::new(&mut self.time, 0.0..=DAY)
+                    .text("time (s)")
+                    .step_by(1.0),
+            );
+        }
         ui.horizontal(|ui| {
             ui.label("TimeZone :");
             ui.add(
@@ -111,14 +215,102 @@ impl FractalClock {
         });
         ui.add(Slider::new(&mut self.zoom, 0.0..=1.0).text("zoom"));
         ui.add(Slider::new(&mut self.start_line_width, 0.0..=5.0).text("Start line width"));
-        ui.add(Slider::new(&mut self.depth, 0..=14).text("depth"));
-        ui.add(Slider::new(&mut self.length_factor, 0.0..=1.0).text("length factor"));
+        ui.checkbox(&mut self.auto_depth, "fit depth to framerate")
+            .on_hover_text("go as deep as the clock can while keeping 60 frames per second");
+        if self.auto_depth {
+            ui.label(format!("frame time: {:.1} ms", self.frame_time * 1000.0));
+        }
+        ui.horizontal(|ui| {
+            ui.add_enabled(
+                !self.auto_depth,
+                Slider::new(&mut self.depth, 0.0..=MAX_DEPTH).text("depth"),
+            );
+            if self.effective_depth < self.depth.ceil() as usize {
+                ui.label(format!("effective depth: {}", self.effective_depth))
+                    .on_hover_text("the deeper branches are too dark, raise the luminance floor");
+            }
+        });
+        ui.add(Slider::new(&mut self.hand_lengths[0], 0.0..=1.0).text("second hand length"));
+        ui.add(Slider::new(&mut self.hand_lengths[1], 0.0..=1.0).text("minute hand length"));
+        ui.add(Slider::new(&mut self.hand_lengths[2], 0.0..=1.0).text("hour hand length"));
+        ui.checkbox(&mut self.hour_branch, "branch from the hour hand");
+        ui.horizontal(|ui| {
+            ui.label("color :");
+            ui.color_edit_button_rgb(&mut self.base_color);
+            ui.checkbox(&mut self.rainbow, "color by depth");
+        });
         ui.add(Slider::new(&mut self.luminance_factor, 0.0..=1.0).text("luminance factor"));
+        ui.add(Slider::new(&mut self.luminance_floor, 0..=32).text("luminance floor"));
+        ui.add(Slider::new(&mut self.gamma, 1.0..=4.0).text("gamma"));
         ui.add(Slider::new(&mut self.width_factor, 0.0..=1.0).text("width factor"));
+        ui.checkbox(&mut self.trail, "trail");
+        ui.add_enabled(
+            self.trail,
+            Slider::new(&mut self.fade, 0.05..=0.5).text("fade (s)"),
+        );
+        ui.checkbox(&mut self.ambient, "ambient background")
+            .on_hover_text("night, dawn, day and dusk colors following the time of the clock");
+        ui.checkbox(&mut self.antialias, "antialias")
+            .on_hover_text("softer edges and no flicker of the lines thinner than a pixel");
+        ui.add_enabled(
+            self.antialias,
+            Slider::new(&mut self.feathering, 0.5..=4.0).text("feathering (px)"),
+        );
 
         eframe::egui::reset_button(ui, self);
     }
 
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn debug_stats(&self) -> Option<String> {
+        Some(format!("lines: {}", self.line_count))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+}
+
+impl FractalClock {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+
+    /// Step the depth with `auto_depth`: one level shallower while the average frame takes
+    /// longer than [`LAG_FRAME`], one level deeper while it keeps up with [`TARGET_FRAME`].
+    /// Going deeper stops once the deeper branches are too dark to be drawn.
+    fn fit_depth(&mut self, dt: f32, now: f64) {
+        if !self.auto_depth {
+            return;
+        }
+        // the first frame after the app was idle says nothing about the painting
+        let dt = dt.min(0.1);
+        self.frame_time += (dt - self.frame_time) * FRAME_TIME_SMOOTHING;
+        if now - self.depth_changed_at < DEPTH_STEP {
+            return;
+        }
+        let retry = self.lagged_at.map_or(true, |at| now - at >= DEPTH_RETRY);
+        if self.frame_time > LAG_FRAME && self.depth > 0.0 {
+            self.depth = (self.depth - 1.0).max(0.0);
+            self.depth_changed_at = now;
+            self.lagged_at = Some(now);
+        } else if self.frame_time < TARGET_FRAME * 1.1
+            && retry
+            && self.depth < MAX_DEPTH
+            && self.effective_depth == self.depth.ceil() as usize
+        {
+            self.depth = (self.depth + 1.0).min(MAX_DEPTH);
+            self.depth_changed_at = now;
+        }
+    }
+
     fn paint(&mut self, painter: &Painter) {
         struct Hand {
             length: f32,
@@ -141,11 +333,11 @@ impl FractalClock {
 
         let hands = [
             // Second hand:
-            Hand::from_length_angle(self.length_factor, angle_from_period(60.0)),
+            Hand::from_length_angle(self.hand_lengths[0], angle_from_period(60.0)),
             // Minute hand:
-            Hand::from_length_angle(self.length_factor, angle_from_period(60.0 * 60.0)),
+            Hand::from_length_angle(self.hand_lengths[1], angle_from_period(60.0 * 60.0)),
             // Hour hand:
-            Hand::from_length_angle(0.5, angle_from_period(12.0 * 60.0 * 60.0)),
+            Hand::from_length_angle(self.hand_lengths[2], angle_from_period(12.0 * 60.0 * 60.0)),
         ];
 
         let mut shapes: Vec<Shape> = Vec::new();
@@ -188,8 +380,8 @@ impl FractalClock {
         for (i, hand) in hands.iter().enumerate() {
             let center = pos2(0.0, 0.0);
             let end = center + hand.vec;
-            paint_line([center, end], Color32::from_additive_luminance(255), width);
-            if i < 2 {
+            paint_line([center, end], additive(self.base_color, 1.0), width);
+            if i < 2 || self.hour_branch {
                 nodes.push(Node {
                     pos: end,
                     dir: hand.vec,
@@ -197,20 +389,39 @@ impl FractalClock {
             }
         }
 
-        let mut luminance = 0.7; // Start dimmer than main hands
+        let mut luminance: f32 = 0.7; // Start dimmer than main hands
 
         let mut new_nodes = Vec::new();
-        for _ in 0..self.depth {
+        self.effective_depth = 0;
+        // the level after the full ones is drawn dimmed by the fractional part of the depth,
+        // so that dragging the slider fades it in instead of popping it up
+        let full = self.depth.floor() as usize;
+        let fade = self.depth.fract();
+        for depth in 0..self.depth.ceil() as usize {
             new_nodes.clear();
             new_nodes.reserve(nodes.len() * 2);
 
             luminance *= self.luminance_factor;
             width *= self.width_factor;
 
-            let luminance_u8 = (255.0 * luminance).round() as u8;
+            // the additive blend sums the encoded values, so the curve decides how soon
+            // overlapping branches saturate
+            let shown = luminance
+                .powf(self.gamma / 2.2)
+                .max(self.luminance_floor as f32 / 255.0);
+            let luminance_u8 = (255.0 * shown).round() as u8;
             if luminance_u8 == 0 {
                 break;
             }
+            self.effective_depth = depth + 1;
+            let shown = if depth == full { shown * fade } else { shown };
+            let color = if self.rainbow {
+                let hue = depth as f32 / self.depth;
+                let rgb = Color32::from(color::Hsva::new(hue, 0.8, 1.0, 1.0));
+                additive([rgb.r(), rgb.g(), rgb.b()].map(|c| c as f32 / 255.0), shown)
+            } else {
+                additive(self.base_color, shown)
+            };
 
             for &rotor in &hand_rotors {
                 for a in &nodes {
@@ -219,11 +430,7 @@ impl FractalClock {
                         pos: a.pos + new_dir,
                         dir: new_dir,
                     };
-                    paint_line(
-                        [a.pos, b.pos],
-                        Color32::from_additive_luminance(luminance_u8),
-                        width,
-                    );
+                    paint_line([a.pos, b.pos], color, width);
                     new_nodes.push(b);
                 }
             }
@@ -231,7 +438,49 @@ impl FractalClock {
             std::mem::swap(&mut nodes, &mut new_nodes);
         }
         self.line_count = shapes.len();
-        painter.extend(shapes);
+        let trail = if self.trail {
+            self.faded_trail(&shapes, painter.ctx().input().time)
+        } else {
+            self.history.clear();
+            Vec::new()
+        };
+        if self.ambient {
+            painter.rect_filled(rect, 0.0, ambient_color(self.time));
+        }
+        let lines = trail.into_iter().chain(shapes);
+        if self.antialias {
+            let pixels_per_point = painter.ctx().pixels_per_point();
+            painter.add(feathered_lines(lines, self.feathering, pixels_per_point));
+        } else {
+            painter.extend(lines);
+        }
+    }
+
+    /// The lines of the previous frames, fading out by `exp(-age / fade)` so that the trail
+    /// lasts as long at any frame rate. `now` is the time of the frame in seconds.
+    fn faded_trail(&mut self, shapes: &[Shape], now: f64) -> Vec<Shape> {
+        // Stop once the oldest frame is too dark to see
+        let max_age = self.fade as f64 * 255.0f64.ln();
+        while let Some(&(time, _)) = self.history.front() {
+            if now - time < max_age {
+                break;
+            }
+            self.history.pop_front();
+        }
+        let mut faded = Vec::new();
+        for (time, frame) in &self.history {
+            let factor = (-(now - time) as f32 / self.fade).exp();
+            for shape in frame {
+                if let Shape::LineSegment { points, stroke } = shape {
+                    faded.push(Shape::line_segment(
+                        *points,
+                        (stroke.width, stroke.color.linear_multiply(factor)),
+                    ));
+                }
+            }
+        }
+        self.history.push_back((now, shapes.to_vec()));
+        faded
     }
 
     // This is ugly, but it works.
@@ -278,3 +527,65 @@ impl FractalClock {
         }
     }
 }
+
+/// `color` dimmed to `luminance`, with alpha 0 so overlapping lines add up like
+/// [`Color32::from_additive_luminance`].
+fn additive(color: [f32; 3], luminance: f32) -> Color32 {
+    let [r, g, b] = color.map(|c| (c * luminance * 255.0).round() as u8);
+    Color32::from_rgba_premultiplied(r, g, b, 0)
+}
+
+/// Color of the sky at `time`, in seconds since midnight, blended between [`AMBIENT_COLORS`].
+fn ambient_color(time: f64) -> Color32 {
+    let hour = (time.rem_euclid(24.0 * 60.0 * 60.0) / 3600.0) as f32;
+    let next = AMBIENT_COLORS
+        .iter()
+        .position(|&(h, _)| h > hour)
+        .unwrap_or(AMBIENT_COLORS.len() - 1);
+    let (h0, c0) = AMBIENT_COLORS[next - 1];
+    let (h1, c1) = AMBIENT_COLORS[next];
+    let t = ((hour - h0) / (h1 - h0)).clamp(0.0, 1.0);
+    let [r, g, b] = [0, 1, 2].map(|i| (c0[i] as f32 + (c1[i] as f32 - c0[i] as f32) * t) as u8);
+    Color32::from_rgb(r, g, b)
+}
+
+/// One mesh of the line segments in `shapes`, with edges fading out over `feathering` pixels
+/// instead of the single pixel of egui. Lines thinner than a pixel are drawn one pixel wide and
+/// dimmed by their width, so they keep their brightness instead of shimmering as they move
+/// across pixel boundaries.
+fn feathered_lines(
+    shapes: impl IntoIterator<Item = Shape>,
+    feathering: f32,
+    pixels_per_point: f32,
+) -> Shape {
+    let pixel = 1.0 / pixels_per_point;
+    let feather = feathering * pixel;
+    let mut mesh = Mesh::default();
+    for shape in shapes {
+        let (a, b, stroke) = match shape {
+            Shape::LineSegment {
+                points: [a, b],
+                stroke,
+            } if a != b => (a, b, stroke),
+            _ => continue,
+        };
+        let normal = (b - a).normalized().rot90();
+        let half = stroke.width.max(pixel) / 2.0;
+        let color = stroke
+            .color
+            .linear_multiply((stroke.width / pixel).min(1.0));
+        // four vertices across each end: faded edge, core, core, faded edge
+        let first = mesh.vertices.len() as u32;
+        for end in [a, b] {
+            mesh.colored_vertex(end - normal * (half + feather), Color32::TRANSPARENT);
+            mesh.colored_vertex(end - normal * half, color);
+            mesh.colored_vertex(end + normal * half, color);
+            mesh.colored_vertex(end + normal * (half + feather), Color32::TRANSPARENT);
+        }
+        for i in first..first + 3 {
+            mesh.add_triangle(i, i + 1, i + 4);
+            mesh.add_triangle(i + 1, i + 5, i + 4);
+        }
+    }
+    Shape::mesh(mesh)
+}
@@ -0,0 +1,190 @@
+//! Reuse of the last frame of a static shader view
+//!
+//! The escape time shaders run their whole loop for every pixel of every frame, even when
+//! nothing changed. After a frame is drawn its viewport is copied into a texture, and the
+//! following frames only draw that texture until the parameters or the viewport change.
+//! No framebuffer is bound, so it works whatever egui paints into.
+
+use std::mem::size_of;
+
+const VERTICES: &[f32] = &[-1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0];
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+void main() {
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+uniform sampler2D image;
+// bottom left corner of the viewport in the window
+uniform ivec2 origin;
+out vec4 out_color;
+void main() {
+    out_color = texelFetch(image, ivec2(gl_FragCoord.xy) - origin, 0);
+}
+"#;
+
+#[derive(Debug)]
+pub struct FrameCache<P> {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+    texture: glow::Texture,
+    /// Allocated size of `texture`
+    size: (i32, i32),
+    /// Viewport and parameters of the image in `texture`, `None` before the first frame
+    key: Option<([i32; 4], P)>,
+}
+
+impl<P: PartialEq> FrameCache<P> {
+    pub fn new(gl: &glow::Context) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let verts_slice = std::slice::from_raw_parts(
+                VERTICES.as_ptr() as *const u8,
+                VERTICES.len() * size_of::<f32>(),
+            );
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::STATIC_DRAW);
+
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            // texelFetch ignores filtering, but the texture is incomplete with mipmaps
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            Ok(Self {
+                program,
+                vao,
+                vbo,
+                texture,
+                size: (0, 0),
+                key: None,
+            })
+        }
+    }
+
+    pub fn destroy(self, gl: &glow::Context) {
+        use glow::HasContext as _;
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+            gl.delete_texture(self.texture);
+        }
+    }
+
+    /// Draw the cached frame into the current viewport if it was rendered with `params` at the
+    /// same place and size. Returns `false` when the caller must draw the frame itself.
+    pub fn draw(&self, gl: &glow::Context, params: &P) -> bool {
+        use glow::HasContext as _;
+        let viewport = viewport(gl);
+        match &self.key {
+            Some((v, p)) if *v == viewport && p == params => {}
+            _ => return false,
+        }
+        unsafe {
+            // the texture holds the bytes of the framebuffer, write them back untouched
+            let blend = gl.is_enabled(glow::BLEND);
+            gl.disable(glow::BLEND);
+            #[cfg(not(target_arch = "wasm32"))]
+            let srgb = gl.is_enabled(glow::FRAMEBUFFER_SRGB);
+            #[cfg(not(target_arch = "wasm32"))]
+            gl.disable(glow::FRAMEBUFFER_SRGB);
+
+            gl.use_program(Some(self.program));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            gl.uniform_1_i32(gl.get_uniform_location(self.program, "image").as_ref(), 0);
+            gl.uniform_2_i32(
+                gl.get_uniform_location(self.program, "origin").as_ref(),
+                viewport[0],
+                viewport[1],
+            );
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            if blend {
+                gl.enable(glow::BLEND);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if srgb {
+                gl.enable(glow::FRAMEBUFFER_SRGB);
+            }
+        }
+        true
+    }
+
+    /// Copy the frame just drawn into the current viewport, to be reused while `params` hold.
+    pub fn store(&mut self, gl: &glow::Context, params: P) {
+        use glow::HasContext as _;
+        let viewport = viewport(gl);
+        let size = (viewport[2], viewport[3]);
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            if self.size != size {
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::RGBA8 as i32,
+                    size.0,
+                    size.1,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    None,
+                );
+                self.size = size;
+            }
+            gl.copy_tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                0,
+                viewport[0],
+                viewport[1],
+                size.0,
+                size.1,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+        self.key = Some((viewport, params));
+    }
+}
+
+fn viewport(gl: &glow::Context) -> [i32; 4] {
+    use glow::HasContext as _;
+    let mut viewport = [0; 4];
+    unsafe { gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport) };
+    viewport
+}
@@ -0,0 +1,145 @@
+//! Undo and redo of the settings of a view
+//!
+//! The view passes its settings to [`History::track`] every frame. A change is only recorded
+//! once the settings stayed the same for [`History::SETTLE`] seconds, so dragging a slider or
+//! a zoom animation makes one step instead of one per frame.
+
+#[derive(Debug)]
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    /// Settings of the last recorded step, `None` before the first frame
+    settled: Option<T>,
+    /// Settings of the last frame and the time they changed
+    last: Option<(T, f64)>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            settled: None,
+            last: None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    const SETTLE: f64 = 0.5;
+    /// Older steps are dropped
+    const LIMIT: usize = 100;
+
+    /// Follow the settings `current` at `InputState::time` `now`.
+    pub fn track(&mut self, current: &T, now: f64) {
+        if self.last.as_ref().map_or(true, |(last, _)| last != current) {
+            self.last = Some((current.clone(), now));
+        }
+        if self.settled.is_none() {
+            self.settled = Some(current.clone());
+            return;
+        }
+        let settle = match (&self.settled, &self.last) {
+            (Some(settled), Some((last, since))) => last != settled && now - since >= Self::SETTLE,
+            _ => false,
+        };
+        if settle {
+            self.record(current.clone());
+        }
+    }
+
+    fn record(&mut self, state: T) {
+        if let Some(settled) = self.settled.replace(state) {
+            self.undo.push(settled);
+            if self.undo.len() > Self::LIMIT {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+    }
+
+    /// The settings before `current`, recording `current` first if it did not settle yet.
+    pub fn undo(&mut self, current: &T) -> Option<T> {
+        if self.settled.as_ref() != Some(current) {
+            self.record(current.clone());
+        }
+        let previous = self.undo.pop()?;
+        self.redo.extend(self.settled.replace(previous.clone()));
+        self.last = None;
+        Some(previous)
+    }
+
+    /// The settings undone last, if nothing changed since.
+    pub fn redo(&mut self, current: &T) -> Option<T> {
+        if self.settled.as_ref() != Some(current) {
+            return None;
+        }
+        let next = self.redo.pop()?;
+        self.undo.extend(self.settled.replace(next.clone()));
+        self.last = None;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Track `value` long enough to settle, from `now`
+    fn settle(history: &mut History<i32>, value: i32, now: f64) {
+        history.track(&value, now);
+        history.track(&value, now + History::<i32>::SETTLE);
+    }
+
+    #[test]
+    fn a_drag_is_one_step() {
+        let mut history = History::default();
+        history.track(&0, 0.0);
+        for (i, value) in [1, 2, 3].into_iter().enumerate() {
+            history.track(&value, 0.1 * (i + 1) as f64);
+        }
+        history.track(&3, 0.9);
+        assert_eq!(history.undo(&3), Some(0));
+        history.track(&0, 1.0);
+        assert_eq!(history.undo(&0), None);
+        assert_eq!(history.redo(&0), Some(3));
+        assert_eq!(history.redo(&3), None);
+    }
+
+    #[test]
+    fn undo_records_an_unsettled_change() {
+        let mut history = History::default();
+        history.track(&0, 0.0);
+        history.track(&5, 0.1);
+        assert_eq!(history.undo(&5), Some(0));
+        assert_eq!(history.redo(&0), Some(5));
+    }
+
+    #[test]
+    fn a_change_drops_the_redo_steps() {
+        let mut history = History::default();
+        history.track(&0, 0.0);
+        settle(&mut history, 5, 1.0);
+        assert_eq!(history.undo(&5), Some(0));
+        // changed since the undo, but not settled yet
+        assert_eq!(history.redo(&7), None);
+        history.track(&0, 2.0);
+        settle(&mut history, 9, 3.0);
+        assert_eq!(history.redo(&9), None);
+        assert_eq!(history.undo(&9), Some(0));
+    }
+
+    #[test]
+    fn old_steps_are_dropped() {
+        let mut history = History::default();
+        history.track(&0, 0.0);
+        for value in 1..=150 {
+            settle(&mut history, value, value as f64);
+        }
+        let mut current = 150;
+        while let Some(previous) = history.undo(&current) {
+            current = previous;
+        }
+        assert_eq!(current, 150 - History::<i32>::LIMIT as i32);
+    }
+}
@@ -1,41 +1,283 @@
+#[cfg(not(target_arch = "wasm32"))]
+use super::animation;
+use super::{
+    bookmark, export, frame_cache::FrameCache, history::History, mandelbrot_shader, plane,
+    rng::Rng, JuliaSeed, View, ViewArgs,
+};
 use eframe::egui::{self, *};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use std::{mem::size_of, sync::Arc};
+use std::{f32::consts::TAU, mem::size_of, sync::Arc};
+
+/// Highest exponent `m`, past it the sets are close to a disk with a fringe of `m - 1` spikes
+const MAX_M: i32 = 32;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct JuliaSetShader {
+    #[cfg_attr(feature = "serde", serde(skip))]
     gl: OnceCell<Arc<Mutex<Context>>>,
-    center: (f32, f32),
-    ratio: f32,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    /// Draws the Mandelbrot set behind the `c` picker, `None` if its shaders failed
+    #[cfg_attr(feature = "serde", serde(skip))]
+    map_gl: OnceCell<Arc<Mutex<mandelbrot_shader::Context>>>,
+    /// When the view was last brought back in range, for [`plane::clamp_warning`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clamped_at: Option<f64>,
+    center: (f64, f64),
+    ratio: f64,
     step: f32,
     c: (f32, f32),
+    /// Exponent of `z^m + c`, the same `m` as in the Mandelbrot view so a seed picked there
+    /// opens the matching Julia set
     m: i32,
+    iterations: i32,
+    smooth: bool,
+    /// An orbit escapes once `|z|²` exceeds this
+    escape_radius: f32,
+    palette: Palette,
+    /// Color of the points inside the set, `None` shows the canvas through them
+    background: Option<[f32; 3]>,
+    aa: bool,
+    show_grid: bool,
+    /// Draw in the square in the middle of the canvas, whatever the shape of the window
+    aspect_lock: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    seed: JuliaSeed,
+    animate: bool,
+    anim_speed: f32,
+    anim_radius: f32,
+    /// `c` moves around this point while animating
+    anim_center: (f32, f32),
+    anim_phase: f32,
+    /// `c` follows the pointer over the canvas, see [`follow_c`]
+    follow_mouse: bool,
+    /// Whether `c` followed the pointer in the last frame
+    #[cfg_attr(feature = "serde", serde(skip))]
+    following: bool,
+    bookmarks: Vec<bookmark::Bookmark>,
+    /// Running move to a restored bookmark or to the whole set of a new seed
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tween: Option<plane::Tween>,
+    /// Look for detail around the center in the next paint, see [`Context::find_detail`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    find_detail: bool,
+    /// Position of the fingers while pinching on a touch screen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pinch: Option<Pos2>,
+    /// Running change of `c` to a randomized value, as `(from, to, start time)`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    morph: Option<((f32, f32), (f32, f32), f64)>,
+    /// Generator of the "Randomize C" button, seeded on the first click
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rng: Option<Rng>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: History<Params>,
+    #[cfg(not(target_arch = "wasm32"))]
+    animation: animation::Animation,
 }
 
+/// `c` of a fresh view, also where it falls back to if it is not a number
+const DEFAULT_C: (f32, f32) = (0.3, 0.5);
+
 impl Default for JuliaSetShader {
     fn default() -> Self {
         Self {
             gl: Default::default(),
+            error: None,
+            map_gl: Default::default(),
+            clamped_at: None,
             center: (0.0, 0.0),
             ratio: 1.0,
             step: 0.1,
-            c: (0.3, 0.5),
+            c: DEFAULT_C,
             m: 2,
+            iterations: 128,
+            smooth: true,
+            escape_radius: 256.0,
+            palette: Palette::Hsv,
+            background: None,
+            aa: false,
+            show_grid: false,
+            aspect_lock: false,
+            seed: Default::default(),
+            animate: false,
+            anim_speed: 0.5,
+            anim_radius: 0.05,
+            anim_center: DEFAULT_C,
+            anim_phase: 0.0,
+            follow_mouse: false,
+            following: false,
+            bookmarks: Vec::new(),
+            tween: None,
+            find_detail: false,
+            pinch: None,
+            morph: None,
+            rng: None,
+            history: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            animation: Default::default(),
+        }
+    }
+}
+
+/// Color gradient used for the escape time, must match `palette_color` in the fragment shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Palette {
+    Hsv,
+    Fire,
+    Ocean,
+    Grayscale,
+}
+
+impl Palette {
+    const ALL: [Palette; 4] = [Self::Hsv, Self::Fire, Self::Ocean, Self::Grayscale];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hsv => "HSV",
+            Self::Fire => "Fire",
+            Self::Ocean => "Ocean",
+            Self::Grayscale => "Grayscale",
         }
     }
 }
 
 impl super::View for JuliaSetShader {
     fn name(&self) -> &'static str {
-        "Julia Set (Shader)"
+        Self::NAME
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "The points z whose orbit stays bounded for a fixed c.\n\
+             z -> z^m + c",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "drag: pan\n\
+             scroll: zoom at the cursor\n\
+             pinch: zoom and pan on a touch screen\n\
+             map in the settings: click or drag to pick c\n\
+             follow mouse: c follows the pointer, from -1 to 1 across the canvas\n\
+             snap to detail: move to the most detailed boundary near the center\n\
+             Ctrl+Z / Ctrl+Y: undo / redo a change of the settings or view\n\
+             keys, while the settings are open:\n\
+             - arrows: pan\n\
+             - W / S: move c up / down by step × 0.1 per second\n\
+             - A / D: move c left / right by step × 0.1 per second\n\
+             - Enter / PageDown: zoom in\n\
+             - Backspace / PageUp: zoom out\n\
+             - Escape or Home: reset the view",
+        )
     }
 
     fn is_dynamic(&self) -> bool {
-        false
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.animation.running() {
+            return true;
+        }
+        self.animate || self.following || self.tween.is_some() || self.morph.is_some()
+    }
+
+    fn debug_stats(&self) -> Option<String> {
+        Some(format!("iterations: {}", self.iterations))
+    }
+
+    fn status_text(&self) -> Option<String> {
+        Some(plane::status(self.center, self.ratio))
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let context = self
+            .gl
+            .get_or_try_init(|| Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context))));
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+        let map = self.map_gl.get_or_try_init(|| {
+            mandelbrot_shader::Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context)))
+        });
+        if let Err(err) = map {
+            tracing::warn!(%err, "Cannot build the Mandelbrot map of the c picker");
+        }
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let pixels = render_offscreen(gl, &self.params(), size)?;
+        Some(export::to_image(size, &pixels))
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        Some(("iterations", super::BENCHMARK_ITERATIONS.to_vec()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        let params = Params {
+            iterations: value as i32,
+            ..self.params()
+        };
+        // the shaders of the view, so the timings leave out their compilation
+        let pixels = self.gl.get()?.lock().render_offscreen(gl, &params, size)?;
+        Some(export::to_image(size, &pixels))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
     }
 
     fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let now = ui.input().time;
+        if let Some(c) = self.seed.take() {
+            self.c = c;
+            self.anim_center = self.loop_center(c);
+            self.morph = None;
+            self.follow_mouse = false;
+            // back out to the whole set of the new c
+            let to = ((0.0, 0.0), 1.0);
+            self.tween = Some(plane::Tween::new((self.center, self.ratio), to, now));
+        }
+        if let Some(detail) = self.gl.get().and_then(|gl| gl.lock().detail.take()) {
+            self.tween = Some(plane::Tween::new(
+                (self.center, self.ratio),
+                (detail, self.ratio),
+                now,
+            ));
+        }
+        plane::fly(&mut self.tween, now, &mut self.center, &mut self.ratio);
+        if let Some((from, to, start)) = self.morph {
+            let x = ((now - start) / Self::MORPH_DURATION).clamp(0.0, 1.0) as f32;
+            let s = x * x * (3.0 - 2.0 * x);
+            self.c = (from.0 + (to.0 - from.0) * s, from.1 + (to.1 - from.1) * s);
+            if x >= 1.0 {
+                self.morph = None;
+            }
+        }
+        if self.animate {
+            self.anim_phase = (self.anim_phase + ui.input().unstable_dt * self.anim_speed) % TAU;
+            self.c = (
+                self.anim_center.0 + self.anim_radius * self.anim_phase.cos(),
+                self.anim_center.1 + self.anim_radius * self.anim_phase.sin(),
+            );
+        }
+        self.history.track(&self.params(), now);
         let painter = Painter::new(
             ui.ctx().clone(),
             ui.layer_id(),
@@ -43,77 +285,150 @@ impl super::View for JuliaSetShader {
         );
         let rect = painter.clip_rect();
         ui.expand_to_include_rect(rect);
+        // with the aspect locked, the fractal takes only the middle square of the canvas
+        let rect = if self.aspect_lock {
+            plane::square(rect)
+        } else {
+            rect
+        };
+        let painter = painter.with_clip_rect(rect);
+        // Reserve a slot below the settings popup, the callback is filled in after input handling
+        let canvas = painter.add(Shape::Noop);
+        let grid = painter.add(Shape::Noop);
+
+        let hover = ui
+            .input()
+            .pointer
+            .hover_pos()
+            .filter(|pos| rect.contains(*pos));
+        let coordinates =
+            hover.map(|pos| plane::format(plane::to_complex(rect, pos, self.center, self.ratio)));
+        let popup = super::settings_popup(ui, self, coordinates);
+        self.following = false;
+        if self.follow_mouse {
+            if let Some(pos) = hover.filter(|pos| !popup.contains(*pos)) {
+                self.c = follow_c(rect, pos);
+                self.following = true;
+            }
+        }
+
+        // The popup widgets are registered first, so they get the drag before the canvas does
+        let response = ui.interact(rect, ui.id().with("canvas"), Sense::drag());
+        let from_popup = ui
+            .input()
+            .pointer
+            .press_origin()
+            .map_or(false, |pos| popup.contains(pos));
+        let pinching = plane::pinch(
+            &ui.input(),
+            rect,
+            &mut self.pinch,
+            &mut self.center,
+            &mut self.ratio,
+        );
+        if response.dragged() && !from_popup && !pinching {
+            plane::pan(rect, response.drag_delta(), &mut self.center, self.ratio);
+        }
+        if let Some(pos) = response.hover_pos() {
+            let scroll = ui.input().scroll_delta.y;
+            if scroll != 0.0 && !popup.contains(pos) {
+                let factor = 1.1f64.powf(scroll as f64 / 50.0);
+                plane::zoom_at(rect, pos, &mut self.center, &mut self.ratio, factor);
+            }
+        }
+
+        let mut clamped = plane::sanitize(&mut self.center, &mut self.ratio);
+        if !(self.c.0.is_finite() && self.c.1.is_finite()) {
+            self.c = DEFAULT_C;
+            clamped = true;
+        }
+        plane::clamp_warning(&painter, rect, clamped, &mut self.clamped_at, now);
 
         let gl = self.gl.clone();
         let ppp = ui.ctx().pixels_per_point();
         let (width, height) = (rect.width() * ppp, rect.height() * ppp);
-        let margin = rect.left() * ppp + 0.5;
-        let center = self.center;
-        let ratio = self.ratio;
-        let c = self.c;
-        let m = self.m;
+        let margin = plane::margin(rect, ui.ctx().input().screen_rect(), ppp);
+        let params = self.params();
+        #[cfg(not(target_arch = "wasm32"))]
+        let job = self.animation.job();
+        let find_detail = std::mem::take(&mut self.find_detail);
+        if find_detail {
+            // the next frame picks up the point found
+            ui.ctx().request_repaint();
+        }
 
         let callback = egui::PaintCallback {
             rect,
             callback: std::sync::Arc::new(move |_info, render_ctx| {
                 if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
                     let mut gl = gl.get().unwrap().lock();
-                    gl.paint(painter.gl(), (width, height), center, ratio, margin, c, m);
+                    super::revive(&mut *gl, painter.gl(), Context::new);
+                    if find_detail {
+                        gl.detail = gl.find_detail(painter.gl(), &params);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Some(job) = &job {
+                        job.lock().step(|center, ratio, size| {
+                            let params = Params {
+                                center,
+                                ratio,
+                                ..params
+                            };
+                            gl.render_offscreen(painter.gl(), &params, size)
+                        });
+                    }
+                    gl.paint_cached(painter.gl(), (width, height), margin, &params);
                 } else {
                     eprintln!("Can't do custom painting because we are not using a glow context");
                 }
             }),
         };
-        painter.add(callback);
-        Frame::popup(ui.style())
-            .stroke(Stroke::none())
-            .show(ui, |ui| {
-                ui.set_max_width(250.0);
-                CollapsingHeader::new("Settings").show(ui, |ui| self.options_ui(ui));
-            });
+        painter.set(canvas, callback);
+        if self.show_grid {
+            let shapes = plane::grid(&painter, rect, self.center, self.ratio);
+            painter.set(grid, Shape::Vec(shapes));
+        }
     }
-}
 
-impl JuliaSetShader {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let default = Self::default();
-        default
-            .gl
-            .get_or_init(|| Arc::new(Mutex::new(Context::new(&cc.gl))));
-        default
-    }
     fn options_ui(&mut self, ui: &mut Ui) {
-        if ui.input().key_pressed(Key::ArrowLeft) {
-            self.center.0 -= 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::ArrowRight) {
-            self.center.0 += 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::ArrowDown) {
-            self.center.1 -= 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::ArrowUp) {
-            self.center.1 += 0.1 / self.ratio;
-        }
-
-        if ui.input().key_pressed(Key::A) {
-            self.c.0 -= 0.01 * self.step;
-        }
-        if ui.input().key_pressed(Key::D) {
-            self.c.0 += 0.01 * self.step;
-        }
-        if ui.input().key_pressed(Key::S) {
-            self.c.1 -= 0.01 * self.step;
-        }
-        if ui.input().key_pressed(Key::W) {
-            self.c.1 += 0.01 * self.step;
-        }
+        // keys typed into a field are not shortcuts
+        let shortcuts = super::shortcuts_enabled(ui);
+        if shortcuts {
+            let input = ui.input();
+            // held keys move at a fixed speed per second, whatever the frame rate
+            let dt = input.unstable_dt.min(0.1);
+            let axis = |minus: Key, plus: Key| {
+                (input.key_down(plus) as i32 - input.key_down(minus) as i32) as f32 * dt
+            };
+            let pan = (
+                axis(Key::ArrowLeft, Key::ArrowRight),
+                axis(Key::ArrowDown, Key::ArrowUp),
+            );
+            self.center.0 += pan.0 as f64 / self.ratio;
+            self.center.1 += pan.1 as f64 / self.ratio;
+            self.c.0 += axis(Key::A, Key::D) * 0.1 * self.step;
+            self.c.1 += axis(Key::S, Key::W) * 0.1 * self.step;
+            let zoom = axis(Key::Backspace, Key::Enter) + axis(Key::PageUp, Key::PageDown);
+            self.ratio *= 2f64.powf(zoom as f64);
 
-        if ui.input().key_pressed(Key::Enter) || ui.input().key_pressed(Key::PageDown) {
-            self.ratio *= 1.2;
-        }
-        if ui.input().key_pressed(Key::Backspace) || ui.input().key_pressed(Key::PageUp) {
-            self.ratio /= 1.2;
+            let held = [
+                Key::ArrowLeft,
+                Key::ArrowRight,
+                Key::ArrowDown,
+                Key::ArrowUp,
+                Key::A,
+                Key::D,
+                Key::S,
+                Key::W,
+                Key::Enter,
+                Key::Backspace,
+                Key::PageDown,
+                Key::PageUp,
+            ];
+            if held.iter().any(|key| input.key_down(*key)) {
+                drop(input);
+                ui.ctx().request_repaint();
+            }
         }
         ui.horizontal(|ui| {
             ui.label("center :");
@@ -128,10 +443,46 @@ impl JuliaSetShader {
             ui.label("+");
             ui.add(DragValue::new(&mut self.c.1).speed(0.01).suffix("i"));
         });
+        let map = self.map_gl.get().cloned();
+        if c_picker(ui, &mut self.c, self.m, map).changed() {
+            self.animate = false;
+            self.follow_mouse = false;
+            self.morph = None;
+        }
+        if ui
+            .button("Randomize C")
+            .on_hover_text("random c and m near the edge of the Mandelbrot set")
+            .clicked()
+        {
+            self.randomize(ui.input().time);
+        }
+        if ui
+            .checkbox(&mut self.follow_mouse, "follow mouse")
+            .on_hover_text("c follows the pointer over the canvas")
+            .changed()
+            && self.follow_mouse
+        {
+            self.animate = false;
+            self.morph = None;
+        }
+        if ui.checkbox(&mut self.animate, "animate C").changed() && self.animate {
+            self.follow_mouse = false;
+            self.morph = None;
+            self.anim_phase = 0.0;
+            self.anim_center = self.loop_center(self.c);
+        }
+        if self.animate {
+            ui.add(Slider::new(&mut self.anim_speed, 0.0..=5.0).text("speed"));
+            ui.add(Slider::new(&mut self.anim_radius, 0.0..=0.5).text("radius"));
+        }
         ui.horizontal(|ui| {
-            ui.label("m :");
-            ui.add(DragValue::new(&mut self.m).speed(1.0).clamp_range(2..=9));
-            if ui.button("+").clicked() && self.m < 10 {
+            ui.label("m :").on_hover_text("exponent of z^m + c");
+            ui.add(
+                DragValue::new(&mut self.m)
+                    .speed(1.0)
+                    .clamp_range(2..=MAX_M),
+            );
+            if ui.button("+").clicked() && self.m < MAX_M {
                 self.m += 1;
             }
             if ui.button("-").clicked() && self.m > 2 {
@@ -139,13 +490,58 @@ impl JuliaSetShader {
             }
         });
         ui.horizontal(|ui| {
-            ui.label("ratio :");
+            ui.label("iterations :");
             ui.add(
-                DragValue::new(&mut self.ratio)
-                    .speed(0.5)
-                    .clamp_range(1.0..=f32::MAX),
+                DragValue::new(&mut self.iterations)
+                    .speed(4.0)
+                    .clamp_range(16..=2048),
             );
         });
+        ui.horizontal(|ui| {
+            ui.label("escape limit (|z|²) :")
+                .on_hover_text("the square of the escape radius, 4 is a radius of 2");
+            ui.add(
+                DragValue::new(&mut self.escape_radius)
+                    .speed(1.0)
+                    .clamp_range(4.0..=1024.0),
+            );
+        });
+        ui.checkbox(&mut self.smooth, "smooth coloring");
+        ComboBox::from_label("palette")
+            .selected_text(self.palette.name())
+            .show_ui(ui, |ui| {
+                for palette in Palette::ALL {
+                    ui.selectable_value(&mut self.palette, palette, palette.name());
+                }
+            });
+        super::background_ui(ui, &mut self.background);
+        plane::zoom_ui(ui, &mut self.ratio);
+        if ui
+            .button("Snap to detail")
+            .on_hover_text("move to the most detailed boundary near the center")
+            .clicked()
+        {
+            self.find_detail = true;
+        }
+        let current = bookmark::Bookmark {
+            center: self.center,
+            ratio: self.ratio,
+            m: self.m,
+            c: Some(self.c),
+            iterations: Some(self.iterations),
+        };
+        if let Some(bookmark) = bookmark::ui(ui, &mut self.bookmarks, current) {
+            self.tween = Some(plane::Tween::new(
+                (self.center, self.ratio),
+                (bookmark.center, bookmark.ratio),
+                ui.input().time,
+            ));
+            self.m = bookmark.m;
+            self.c = bookmark.c.unwrap_or(self.c);
+            self.iterations = bookmark.iterations.unwrap_or(self.iterations);
+            self.animate = false;
+            self.follow_mouse = false;
+        }
         ui.horizontal(|ui| {
             ui.label("step :");
             ui.add(
@@ -154,19 +550,289 @@ impl JuliaSetShader {
                     .clamp_range(0.05..=1.0),
             );
         });
-        if ui.button("reset").clicked() || ui.input().key_pressed(Key::Escape) {
-            self.center = (0.0, 0.0);
-            self.ratio = 1.0;
+        ui.checkbox(&mut self.aa, "anti-aliasing (2x2)");
+        ui.checkbox(&mut self.show_grid, "grid");
+        ui.checkbox(&mut self.aspect_lock, "aspect lock")
+            .on_hover_text("draw in a square in the middle of the canvas");
+        #[cfg(not(target_arch = "wasm32"))]
+        self.animation.ui(ui, self.center, self.ratio);
+        if ui.button("reset").clicked() || (shortcuts && ui.input().key_pressed(Key::Escape)) {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.center = (0.0, 0.0);
+        self.ratio = 1.0;
+    }
+
+    fn undo(&mut self) {
+        if let Some(params) = self.history.undo(&self.params()) {
+            self.set_params(&params);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(params) = self.history.redo(&self.params()) {
+            self.set_params(&params);
+        }
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        self.center = args.center.unwrap_or(self.center);
+        self.ratio = args.zoom.unwrap_or(self.ratio);
+        if let Some(c) = args.c {
+            self.c = c;
+            self.animate = false;
+            self.follow_mouse = false;
         }
+        if let Some(m) = args.m {
+            self.m = m.clamp(2, MAX_M);
+        }
+    }
+
+    fn bookmarks(&self) -> &[bookmark::Bookmark] {
+        &self.bookmarks
+    }
+
+    fn import_bookmarks(&mut self, bookmarks: Vec<bookmark::Bookmark>) {
+        bookmark::merge(&mut self.bookmarks, bookmarks);
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            center: Some(self.center),
+            zoom: Some(self.ratio),
+            c: Some(self.c),
+            m: Some(self.m),
+            ..Default::default()
+        }
+    }
+}
+
+impl JuliaSetShader {
+    pub const NAME: &'static str = "Julia Set (Shader)";
+
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>, seed: JuliaSeed) -> Self {
+        let mut view = Self {
+            seed,
+            ..super::restore(cc)
+        };
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+    /// Center of the loop of the animation that passes through `c` at the current phase, so
+    /// that `c` doesn't jump when the animation starts from it.
+    fn loop_center(&self, c: (f32, f32)) -> (f32, f32) {
+        (
+            c.0 - self.anim_radius * self.anim_phase.cos(),
+            c.1 - self.anim_radius * self.anim_phase.sin(),
+        )
+    }
+
+    fn params(&self) -> Params {
+        Params {
+            center: self.center,
+            ratio: self.ratio,
+            c: self.c,
+            m: self.m,
+            iterations: self.iterations,
+            smooth: self.smooth,
+            escape_radius: self.escape_radius,
+            palette: self.palette,
+            background: self.background,
+            samples: if self.aa { 2 } else { 1 },
+        }
+    }
+    /// Time taken by the move of `c` to a randomized value, in seconds
+    const MORPH_DURATION: f64 = 1.0;
+
+    /// Move to a random `m` and a random `c` close to the edge of the main component of the
+    /// Mandelbrot set for that `m`, where the Julia sets are connected but not a plain blob.
+    fn randomize(&mut self, now: f64) {
+        // the time of the first click is the only entropy that also exists on the web
+        let rng = self.rng.get_or_insert_with(|| Rng::new(now.to_bits()));
+        self.m = 2 + (rng.next_f32() * 4.0) as i32;
+        let t = rng.next_f32() * TAU;
+        let c = if self.m == 2 && rng.next_f32() < 0.3 {
+            // edge of the period 2 bulb, -1 + e^it / 4
+            (-1.0 + 0.25 * t.cos(), 0.25 * t.sin())
+        } else {
+            // edge of the main component: the fixed point z of z^m + c is neutral,
+            // m z^(m-1) = e^it, and c = z - z^m
+            let n = (self.m - 1) as f32;
+            let (r, a) = ((1.0 / self.m as f32).powf(1.0 / n), t / n);
+            let (rm, am) = (r.powi(self.m), a * self.m as f32);
+            (r * a.cos() - rm * am.cos(), r * a.sin() - rm * am.sin())
+        };
+        // a little off the edge, on either side, so two clicks rarely look alike
+        let (offset, angle) = (0.03 * rng.next_f32(), rng.next_f32() * TAU);
+        let to = (c.0 + offset * angle.cos(), c.1 + offset * angle.sin());
+
+        self.animate = false;
+        self.follow_mouse = false;
+        self.anim_center = to;
+        self.morph = Some((self.c, to, now));
+        self.tween = Some(plane::Tween::new(
+            (self.center, self.ratio),
+            ((0.0, 0.0), 1.0),
+            now,
+        ));
+    }
+
+    /// Take the settings of `params`, the inverse of [`Self::params`].
+    fn set_params(&mut self, params: &Params) {
+        self.tween = None;
+        self.morph = None;
+        self.animate = false;
+        self.follow_mouse = false;
+        self.center = params.center;
+        self.ratio = params.ratio;
+        self.c = params.c;
+        self.m = params.m;
+        self.iterations = params.iterations;
+        self.smooth = params.smooth;
+        self.escape_radius = params.escape_radius;
+        self.palette = params.palette;
+        self.background = params.background;
+        self.aa = params.samples > 1;
     }
 }
 
+/// `c` under the pointer at `pos` while following the mouse: -1 to 1 across the width and
+/// height of `rect`, whatever the zoom of the view, so every interesting `c` is in reach.
+fn follow_c(rect: Rect, pos: Pos2) -> (f32, f32) {
+    let offset = pos - rect.center();
+    (
+        offset.x / rect.width() * 2.0,
+        -offset.y / rect.height() * 2.0,
+    )
+}
+
+/// Map of the Mandelbrot set for the exponent `m` with a dot at `c`, click or drag in it to
+/// move `c`. Inside the set the Julia set is connected, outside it is dust. The set is drawn by
+/// `map`, only its axes without one.
+fn c_picker(
+    ui: &mut Ui,
+    c: &mut (f32, f32),
+    m: i32,
+    map: Option<Arc<Mutex<mandelbrot_shader::Context>>>,
+) -> Response {
+    let size = Vec2::splat(ui.available_width().min(160.0));
+    let mut response = ui.allocate_response(size, Sense::click_and_drag());
+    let rect = response.rect;
+    // the sets of higher powers are centered on the origin
+    let center = if m == 2 {
+        mandelbrot_shader::DEFAULT_CENTER
+    } else {
+        (0.0, 0.0)
+    };
+    let ratio = 1.0;
+    if let Some(pos) = response.interact_pointer_pos() {
+        let z = plane::to_complex(rect, rect.clamp(pos), center, ratio);
+        let z = (z.0 as f32, z.1 as f32);
+        if z != *c {
+            *c = z;
+            response.mark_changed();
+        }
+    }
+
+    let visuals = ui.style().interact(&response);
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    if let Some(map) = map {
+        let ppp = ui.ctx().pixels_per_point();
+        let view = (rect.width() * ppp, rect.height() * ppp);
+        let margin = plane::margin(rect, ui.ctx().input().screen_rect(), ppp);
+        let params = mandelbrot_shader::Params {
+            center,
+            ratio,
+            samples: 1,
+            high_precision: false,
+            perturbation: false,
+            m,
+            escape_radius: 4.0,
+            iterations: 128,
+            coloring: mandelbrot_shader::Coloring::Escape,
+            outline_width: mandelbrot_shader::DEFAULT_OUTLINE_WIDTH,
+            color_interior: false,
+            background: None,
+            color_offset: 0.0,
+        };
+        painter.add(egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut map = map.lock();
+                    super::revive(&mut *map, painter.gl(), mandelbrot_shader::Context::new);
+                    map.paint_cached(painter.gl(), view, margin, &params);
+                }
+            }),
+        });
+    }
+    let axis = Stroke::new(1.0, ui.visuals().weak_text_color());
+    let origin = plane::to_screen(rect, (0.0, 0.0), center, ratio);
+    painter.line_segment(
+        [pos2(origin.x, rect.top()), pos2(origin.x, rect.bottom())],
+        axis,
+    );
+    painter.line_segment(
+        [pos2(rect.left(), origin.y), pos2(rect.right(), origin.y)],
+        axis,
+    );
+    painter.rect_stroke(rect, 0.0, visuals.bg_stroke);
+    // c outside of the map sticks to its border
+    let dot = plane::to_screen(rect, (c.0 as f64, c.1 as f64), center, ratio);
+    painter.circle(
+        rect.clamp(dot),
+        4.0,
+        visuals.fg_stroke.color,
+        Stroke::new(1.0, Color32::BLACK),
+    );
+    response
+}
+
+/// Everything the shader reads besides the viewport, so a frame can be rendered without the
+/// view or egui
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub center: (f64, f64),
+    pub ratio: f64,
+    pub c: (f32, f32),
+    pub m: i32,
+    pub iterations: i32,
+    pub smooth: bool,
+    pub escape_radius: f32,
+    pub palette: Palette,
+    /// Cleared behind the frame, `None` leaves what is already there
+    pub background: Option<[f32; 3]>,
+    /// Samples per pixel along each axis
+    pub samples: i32,
+}
+
+/// Render `params` at `size` pixels into RGBA8 rows from top to bottom.
+///
+/// The GL resources are created for this call and deleted afterwards, so the only
+/// requirement is a current GL context.
+pub fn render_offscreen(gl: &glow::Context, params: &Params, size: (u32, u32)) -> Option<Vec<u8>> {
+    let mut context = Context::new(gl).ok()?;
+    let pixels = context.render_offscreen(gl, params, size);
+    context.destroy(gl);
+    pixels
+}
+
 #[derive(Debug)]
-struct Context {
+pub(super) struct Context {
     program: glow::Program,
     vao: glow::VertexArray,
-    _vbo: glow::Buffer,
-    _ebo: glow::Buffer,
+    vbo: glow::Buffer,
+    ebo: glow::Buffer,
+    /// Last on-screen frame, redrawn while the parameters stay the same
+    cache: FrameCache<Params>,
+    /// Point found by [`Self::find_detail`] in a paint callback, for the view to move to
+    detail: Option<(f64, f64)>,
 }
 
 const VERTICES: &[f32] = &[-1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0];
@@ -181,16 +847,24 @@ void main() {
 
 // hsv2rgb: https://stackoverflow.com/questions/15095909/from-rgb-to-hsv-in-opengl-glsl
 const FRAGMENT_SHADER: &str = r#"
+// the escape loop needs more than mediump, which is all some mobile GPUs give by default
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
 precision mediump float;
+#endif
 uniform vec2 viewport;
 uniform vec2 min;
 uniform vec2 max;
-uniform float margin;
+uniform vec2 margin;
 uniform vec2 c;
 uniform int m;
+uniform int max_iter;
+uniform bool smooth_coloring;
+uniform int palette;
+uniform int samples;
 out vec4 out_color;
-const float MAX = 128.0;
-const float LIMIT = 4.0;
+uniform float limit;
 
 vec3 hsv2rgb(vec3 c) {
     vec4 K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
@@ -198,82 +872,110 @@ vec3 hsv2rgb(vec3 c) {
     return c.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), c.y);
 }
 
-vec3 run() {
+const vec3 FIRE[5] = vec3[5](
+    vec3(0.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(1.0, 0.5, 0.0),
+    vec3(1.0, 1.0, 0.0),
+    vec3(1.0, 1.0, 1.0)
+);
+const vec3 OCEAN[5] = vec3[5](
+    vec3(0.0, 0.0, 0.0),
+    vec3(0.0, 0.0, 0.5),
+    vec3(0.0, 0.2, 1.0),
+    vec3(0.0, 1.0, 1.0),
+    vec3(1.0, 1.0, 1.0)
+);
+
+// linear interpolation between evenly spaced gradient stops
+vec3 ramp(vec3 stops[5], float t) {
+    float x = clamp(t, 0.0, 1.0) * 4.0;
+    int i = int(min(x, 3.0));
+    return mix(stops[i], stops[i + 1], x - float(i));
+}
+
+vec3 palette_color(float t, float v) {
+    if (palette == 1) {
+        return ramp(FIRE, t);
+    } else if (palette == 2) {
+        return ramp(OCEAN, t);
+    } else if (palette == 3) {
+        return vec3(t, t, t);
+    }
+    return hsv2rgb(vec3(t, 0.9, v));
+}
+
+vec3 run(vec2 frag) {
     float count;
-    vec2 z = mix(min, max, (gl_FragCoord.xy - vec2(margin, margin)) / viewport);
-    for (count = 0.0; count < MAX; count+=1.0) {
-        for (int n = 1; n < m; n++) {
-            float r = z.x * z.x - z.y * z.y;
-            float i = 2.0 * z.x * z.y;
-            z = vec2(r, i);
+    vec2 z = mix(min, max, (frag - margin) / viewport);
+    for (count = 0.0; count < float(max_iter); count+=1.0) {
+        // p = z^m by squaring, a handful of steps even for large m. The bound is the uniform
+        // m, so the compiler can't unroll the loop whatever its limits
+        vec2 p = vec2(1.0, 0.0);
+        vec2 b = z;
+        for (int e = m; e > 0; e >>= 1) {
+            if ((e & 1) == 1) {
+                p = vec2(p.x * b.x - p.y * b.y, p.x * b.y + p.y * b.x);
+            }
+            b = vec2(b.x * b.x - b.y * b.y, 2.0 * b.x * b.y);
         }
-        z.x += c.x;
-        z.y += c.y;
-        if (z.x * z.x + z.y * z.y > LIMIT) break;
+        z = p + c;
+        if (z.x * z.x + z.y * z.y > limit) break;
     }
     return vec3(z, count);
 }
 
-void main() {
-    vec3 r = run();
-    if (r.z == MAX) {
-        out_color = vec4(0.0, 0.0, 0.0, 0.0);
+vec4 color_at(vec2 frag) {
+    vec3 r = run(frag);
+    if (r.z == float(max_iter)) {
+        return vec4(0.0, 0.0, 0.0, 0.0);
     } else if (r.z == 0.0) {
-        out_color = vec4(1.0, 1.0, 1.0, 1.0);
+        return vec4(1.0, 1.0, 1.0, 1.0);
     } else {
-        float c = r.z / MAX;
+        float n = r.z;
+        float len = length(r.xy);
+        // normalized iteration count, log(log(|z|)) is undefined for |z| <= 1
+        if (smooth_coloring && len > 1.0) {
+            n = n + 1.0 - log(log(len)) / log(float(m));
+        }
+        float c = n / float(max_iter);
         float sum = r.x * r.x + r.y * r.y;
-        vec3 color = hsv2rgb(vec3(c , 0.9, sum / 4.0));
-        out_color = vec4(color, 1.0);
+        vec3 color = palette_color(c, sum / 4.0);
+        return vec4(color, 1.0);
     }
 }
+
+void main() {
+    // average a samples x samples grid of sub-pixel positions
+    vec4 sum = vec4(0.0);
+    for (int i = 0; i < samples; i++) {
+        for (int j = 0; j < samples; j++) {
+            vec2 offset = (vec2(float(i), float(j)) + 0.5) / float(samples) - 0.5;
+            sum += color_at(gl_FragCoord.xy + offset);
+        }
+    }
+    out_color = sum / float(samples * samples);
+}
 "#;
 
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+}
+
 impl Context {
-    fn new(gl: &glow::Context) -> Self {
+    pub(super) fn new(gl: &glow::Context) -> Result<Self, String> {
         use glow::HasContext as _;
 
-        let shader_version = if cfg!(target_arch = "wasm32") {
-            // in/out
-            "#version 300 es"
-        } else {
-            // location
-            "#version 330"
-        };
-
         unsafe {
-            let program = gl.create_program().expect("Cannot create program");
-
-            let shader_sources = [
-                (glow::VERTEX_SHADER, VERTEX_SHADER),
-                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
-            ];
-
-            let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader = gl
-                        .create_shader(*shader_type)
-                        .expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
-                    gl.compile_shader(shader);
-                    if !gl.get_shader_compile_status(shader) {
-                        panic!("{}", gl.get_shader_info_log(shader));
-                    }
-                    gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
-
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
-            }
-
-            for shader in shaders {
-                gl.detach_shader(program, shader);
-                gl.delete_shader(shader);
-            }
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
 
             let vao = gl.create_vertex_array().unwrap();
             gl.bind_vertex_array(Some(vao));
@@ -303,30 +1005,92 @@ impl Context {
             gl.enable_vertex_attrib_array(0);
             gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
 
-            Self {
+            Ok(Self {
                 program,
                 vao,
-                _vbo: vbo,
-                _ebo: ebo,
-            }
+                vbo,
+                ebo,
+                cache: FrameCache::new(gl)?,
+                detail: None,
+            })
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn paint(
+    fn destroy(self, gl: &glow::Context) {
+        use glow::HasContext as _;
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+            gl.delete_buffer(self.ebo);
+        }
+        self.cache.destroy(gl);
+    }
+
+    /// Render `params` at `size` pixels into RGBA8 rows from top to bottom.
+    fn render_offscreen(
+        &mut self,
+        gl: &glow::Context,
+        params: &Params,
+        size: (u32, u32),
+    ) -> Option<Vec<u8>> {
+        let view = (size.0 as f32, size.1 as f32);
+        export::render_pixels(gl, size, |gl| self.paint(gl, view, (0.5, 0.5), params))
+    }
+
+    /// The most detailed boundary near `params.center`, see [`plane::steepest`]. There is no
+    /// escape count pass here, so the counts are read back from the grayscale palette, whose
+    /// brightness is the escape count over `iterations`, and the transparent inside of the set.
+    pub(super) fn find_detail(
+        &mut self,
+        gl: &glow::Context,
+        params: &Params,
+    ) -> Option<(f64, f64)> {
+        let patch = Params {
+            ratio: params.ratio * plane::DETAIL_ZOOM,
+            smooth: false,
+            palette: Palette::Grayscale,
+            background: None,
+            samples: 1,
+            ..*params
+        };
+        let n = plane::DETAIL_PATCH;
+        let pixels = self.render_offscreen(gl, &patch, (n, n))?;
+        let iterations = params.iterations.max(1) as u32;
+        // back to the rows from the bottom of OpenGL
+        let counts: Vec<u32> = pixels
+            .chunks_exact(n as usize * 4)
+            .rev()
+            .flat_map(|row| row.chunks_exact(4))
+            .map(|pixel| match pixel[3] {
+                0 => iterations,
+                _ => (pixel[0] as u32 * iterations + 127) / 255,
+            })
+            .collect();
+        plane::steepest(&counts, params.center, params.ratio)
+    }
+
+    /// `paint`, unless the last frame drawn in this viewport had the same `params`
+    pub(super) fn paint_cached(
         &mut self,
         gl: &glow::Context,
         view: (f32, f32),
-        center: (f32, f32),
-        ratio: f32,
-        margin: f32,
-        c: (f32, f32),
-        m: i32,
+        margin: (f32, f32),
+        params: &Params,
     ) {
+        if !self.cache.draw(gl, params) {
+            self.paint(gl, view, margin, params);
+            self.cache.store(gl, *params);
+        }
+    }
+
+    fn paint(&mut self, gl: &glow::Context, view: (f32, f32), margin: (f32, f32), params: &Params) {
         use glow::HasContext as _;
-        let wh = view.0 / view.1;
-        let min = (center.0 - 1.5 / ratio * wh, center.1 - 1.5 / ratio);
-        let max = (center.0 + 1.5 / ratio * wh, center.1 + 1.5 / ratio);
+        let (min, max) = plane::bounds(view, params.center, params.ratio);
+        let (min, max) = ((min.0 as f32, min.1 as f32), (max.0 as f32, max.1 as f32));
+        if let Some(color) = params.background {
+            super::clear_viewport(gl, color);
+        }
         unsafe {
             gl.use_program(Some(self.program));
             gl.bind_vertex_array(Some(self.vao));
@@ -345,16 +1109,41 @@ impl Context {
                 max.0,
                 max.1,
             );
-            gl.uniform_1_f32(
+            gl.uniform_2_f32(
                 gl.get_uniform_location(self.program, "margin").as_ref(),
-                margin,
+                margin.0,
+                margin.1,
             );
             gl.uniform_2_f32(
                 gl.get_uniform_location(self.program, "c").as_ref(),
-                c.0,
-                c.1,
+                params.c.0,
+                params.c.1,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "m").as_ref(),
+                params.m,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "max_iter").as_ref(),
+                params.iterations,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "smooth_coloring")
+                    .as_ref(),
+                params.smooth as i32,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "limit").as_ref(),
+                params.escape_radius,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "palette").as_ref(),
+                params.palette as i32,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "samples").as_ref(),
+                params.samples,
             );
-            gl.uniform_1_i32(gl.get_uniform_location(self.program, "m").as_ref(), m);
             gl.draw_elements(glow::TRIANGLES, INDICES.len() as i32, glow::UNSIGNED_INT, 0);
         }
     }
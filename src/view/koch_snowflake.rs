@@ -1,3 +1,4 @@
+use super::{export, levels::Levels, View, ViewArgs};
 use eframe::{
     egui::{self, containers::*, *},
     emath::{pos2, Pos2},
@@ -10,37 +11,146 @@ use std::{
 };
 
 const DEFAULT_DEPTH: u32 = 6;
-const MAX_DEPTH: u32 = 10;
+/// Line colors on a dark and on a light canvas, unless the user picked one
+const DARK_COLOR: [f32; 3] = [0.7, 0.7, 0.7];
+const LIGHT_COLOR: [f32; 3] = [0.25, 0.25, 0.25];
+/// Seconds the spikes of a new depth take to grow out of the edges
+const MORPH_DURATION: f64 = 0.3;
 
+/// The Koch snowflake, or with `ANTI` the antisnowflake with its spikes pointing inwards.
+///
+/// `OPEN` draws a single Koch curve grown from one segment instead of the closed snowflake.
 #[derive(Debug)]
-pub struct KochSnowFlake<const ANTI: bool> {
-    gl: OnceCell<Arc<Mutex<Context<ANTI>>>>,
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct KochSnowFlake<const ANTI: bool, const OPEN: bool> {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context<ANTI, OPEN>>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
     depth: u32,
+    /// Deepest depth within the vertex budget
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_depth: u32,
+    /// `None` follows the theme
+    color: Option<[f32; 3]>,
+    /// Theme of the last frame, so an export uses the colors on screen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dark_mode: bool,
+    /// In points
+    line_width: f32,
+    /// Grow the spikes of a new depth out of the edges instead of showing them at once
+    smooth_depth: bool,
 }
 
-impl<const ANTI: bool> Default for KochSnowFlake<ANTI> {
+impl<const ANTI: bool, const OPEN: bool> Default for KochSnowFlake<ANTI, OPEN> {
     fn default() -> Self {
         Self {
             gl: Default::default(),
+            error: None,
             depth: DEFAULT_DEPTH,
+            max_depth: super::max_depth(1, vertex_count::<OPEN>),
+            color: None,
+            dark_mode: true,
+            line_width: 1.0,
+            smooth_depth: false,
         }
     }
 }
 
-impl<const ANTI: bool> super::View for KochSnowFlake<ANTI> {
+impl<const ANTI: bool, const OPEN: bool> super::View for KochSnowFlake<ANTI, OPEN> {
     fn name(&self) -> &'static str {
-        if ANTI {
-            "Koch Antisnowflake"
+        match (ANTI, OPEN) {
+            (false, false) => "Koch Snowflake",
+            (true, false) => "Koch Antisnowflake",
+            (false, true) => "Koch Curve",
+            (true, true) => "Koch Anticurve",
+        }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        if OPEN {
+            Some(
+                "A segment whose middle third grows a spike, then every new edge again.\n\
+                 every edge -> 4 edges of a third, the middle two forming a spike",
+            )
+        } else if ANTI {
+            Some(
+                "A triangle whose edges get a notch cut into their middle third, again and again.\n\
+                 every edge -> 4 edges of a third, the middle two pointing inwards",
+            )
         } else {
-            "Koch Snowflake"
+            Some(
+                "A triangle whose edges grow a spike on their middle third, again and again.\n\
+                 every edge -> 4 edges of a third, the middle two pointing outwards",
+            )
+        }
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "+ / - buttons: change the depth\n\
+             smooth depth: grow the new spikes out of the edges\n\
+             line width: past the driver's limit the outline is drawn as triangles\n\
+             Home: reset the settings",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        self.depth = self.depth.min(self.max_depth);
+        let max_depth = self.max_depth;
+        let context = self.gl.get_or_try_init(|| {
+            Context::new(&cc.gl, max_depth).map(|context| Arc::new(Mutex::new(context)))
+        });
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
         }
     }
 
-    fn is_dynamic(&self) -> bool {
-        false
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        self.depth = self.depth.min(self.max_depth);
+        let mut context = Context::new(gl, self.max_depth)?;
+        // `levels[d - 1]` is the outline of depth `d`
+        context.levels.wait(self.depth as usize - 1);
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, self.depth, self.color(), self.line_width, size)
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        // only the depths computed so far, a deeper one would draw the deepest ready instead
+        // `levels[d - 1]` is the outline of depth `d`
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        Some(("depth", (1..=ready.min(self.max_depth)).collect()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, value, self.color(), self.line_width, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
     }
 
     fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        self.dark_mode = ui.visuals().dark_mode;
         let painter = Painter::new(
             ui.ctx().clone(),
             ui.layer_id(),
@@ -49,23 +159,32 @@ impl<const ANTI: bool> super::View for KochSnowFlake<ANTI> {
         let rect = painter.clip_rect();
         ui.expand_to_include_rect(rect);
 
-        Frame::popup(ui.style())
-            .stroke(Stroke::none())
-            .show(ui, |ui| {
-                ui.set_max_width(250.0);
-                CollapsingHeader::new("Settings").show(ui, |ui| self.options_ui(ui));
-            });
+        super::settings_popup(ui, self, None);
 
         let gl = self.gl.clone();
+        let max_depth = self.max_depth;
         let depth = self.depth;
+        let color = self.color();
+        let line_width = self.line_width * ui.ctx().pixels_per_point();
         let ratio = rect.height() / rect.width();
+        let now = self.smooth_depth.then(|| ui.input().time);
+        if self.computing().is_some() {
+            // keep painting until the requested depth is ready
+            ui.ctx().request_repaint();
+        }
+        if let Some(now) = now {
+            if self.gl.get().unwrap().lock().morphing(depth, now) {
+                ui.ctx().request_repaint();
+            }
+        }
 
         let callback = egui::PaintCallback {
             rect,
             callback: std::sync::Arc::new(move |_info, render_ctx| {
                 if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
                     let mut gl = gl.get().unwrap().lock();
-                    gl.paint(painter.gl(), depth, ratio);
+                    super::revive(&mut *gl, painter.gl(), |gl| Context::new(gl, max_depth));
+                    gl.paint(painter.gl(), depth, color, line_width, ratio, now);
                 } else {
                     eprintln!("Can't do custom painting because we are not using a glow context");
                 }
@@ -73,223 +192,550 @@ impl<const ANTI: bool> super::View for KochSnowFlake<ANTI> {
         };
         painter.add(callback);
     }
-}
 
-impl<const ANTI: bool> KochSnowFlake<ANTI> {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let default = Self::default();
-        default
-            .gl
-            .get_or_init(|| Arc::new(Mutex::new(Context::new(&cc.gl))));
-        default
-    }
     fn options_ui(&mut self, ui: &mut Ui) {
         ui.label(format!(
             "Painted line count: {}",
-            3 * 4usize.pow(self.depth - 1)
+            line_count::<OPEN>(self.depth)
         ));
+        if let Some(depth) = self.computing() {
+            ui.label(format!("computing depth {}...", depth));
+        }
         ui.horizontal(|ui| {
             ui.label("Depth :");
             ui.add(
                 DragValue::new(&mut self.depth)
                     .speed(1.0)
-                    .clamp_range(1..=MAX_DEPTH),
+                    .clamp_range(1..=self.max_depth),
             );
-            if ui.button("+").clicked() && self.depth < MAX_DEPTH {
+            if ui.button("+").clicked() && self.depth < self.max_depth {
                 self.depth += 1;
             }
             if ui.button("-").clicked() && self.depth > 1 {
                 self.depth -= 1;
             }
         });
+        ui.horizontal(|ui| {
+            ui.label("Color :");
+            let mut color = self.color();
+            if ui.color_edit_button_rgb(&mut color).changed() {
+                self.color = Some(color);
+            }
+        });
+        ui.add(Slider::new(&mut self.line_width, 1.0..=10.0).text("line width"));
+        ui.checkbox(&mut self.smooth_depth, "smooth depth")
+            .on_hover_text("grow the spikes out of the edges when the depth goes up by one");
+        if let Some(max) = self.gl.get().map(|gl| gl.lock().max_line_width) {
+            let path = if self.line_width * ui.ctx().pixels_per_point() <= max {
+                "GL lines"
+            } else {
+                "triangles, wider than the driver supports"
+            };
+            ui.label(format!("Drawn as {}", path));
+        }
+        if ui
+            .add_enabled(self.computing().is_none(), Button::new("Save SVG"))
+            .clicked()
+        {
+            if let Some(svg) = self.to_svg() {
+                export::save_svg(&export::file_name(self.name(), "svg"), &svg);
+            }
+        }
         if ui.button("reset").clicked() {
-            self.depth = DEFAULT_DEPTH;
+            self.reset();
         }
     }
+
+    fn reset(&mut self) {
+        self.depth = DEFAULT_DEPTH;
+        self.color = None;
+        self.line_width = 1.0;
+        self.smooth_depth = false;
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        if let Some(depth) = args.depth {
+            self.depth = depth.clamp(1, self.max_depth);
+        }
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            depth: Some(self.depth),
+            ..Default::default()
+        }
+    }
+}
+
+impl<const ANTI: bool, const OPEN: bool> KochSnowFlake<ANTI, OPEN> {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+    fn color(&self) -> [f32; 3] {
+        let theme = if self.dark_mode {
+            DARK_COLOR
+        } else {
+            LIGHT_COLOR
+        };
+        self.color.unwrap_or(theme)
+    }
+    /// Depth being computed in the background, if the requested one is not ready yet.
+    fn computing(&self) -> Option<u32> {
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        (ready < self.depth).then(|| ready + 1)
+    }
+    /// The outline at the current depth as an SVG document, `None` until it is computed.
+    fn to_svg(&self) -> Option<String> {
+        let outline = self.gl.get()?.lock().levels.get(self.depth as usize - 1)?;
+        let mut svg = export::Svg::default();
+        svg.polyline(&outline, !OPEN, self.color(), self.line_width);
+        Some(svg.finish())
+    }
+}
+
+/// Points of the outline at `depth`. Each one starts a line of the closed loop, while the
+/// open curve has one more point than lines.
+fn vertex_count<const OPEN: bool>(depth: u32) -> usize {
+    if OPEN {
+        4usize.pow(depth - 1) + 1
+    } else {
+        3 * 4usize.pow(depth - 1)
+    }
+}
+
+fn line_count<const OPEN: bool>(depth: u32) -> usize {
+    vertex_count::<OPEN>(depth) - OPEN as usize
+}
+
+/// Where each point of `outline`, the outline at `depth`, sits on the outline one depth
+/// above: the tip of every spike starts at the middle of its edge.
+fn parents(outline: &[Pos2], depth: u32) -> Vec<Pos2> {
+    let mut parents = outline.to_vec();
+    if depth > 1 {
+        for spike in parents.chunks_exact_mut(4) {
+            spike[2] = pos2(
+                (spike[1].x + spike[3].x) / 2.0,
+                (spike[1].y + spike[3].y) / 2.0,
+            );
+        }
+    }
+    parents
+}
+
+/// Replace the middle third of every edge of the outline `prev` with a spike. The last point
+/// closes the loop back to the first one unless `OPEN`.
+fn next_level<const ANTI: bool, const OPEN: bool>(prev: &[Pos2]) -> Vec<Pos2> {
+    let len = prev.len();
+    let mut new = Vec::with_capacity(len * 4);
+    let closing = (!OPEN).then(|| (len - 1, 0));
+    let iter = (1..len)
+        .map(|i| (i - 1, i))
+        .chain(closing)
+        .map(|(s, e)| (prev[s], prev[e]));
+    for (s, e) in iter {
+        // s---l\   /r---e
+        //       \ /
+        //        m
+        let l = pos2((e.x + 2.0 * s.x) / 3.0, (e.y + 2.0 * s.y) / 3.0);
+        let r = pos2((s.x + 2.0 * e.x) / 3.0, (s.y + 2.0 * e.y) / 3.0);
+        #[allow(clippy::collapsible_else_if)]
+        let m = if ANTI {
+            if s.y == e.y {
+                pos2((s.x + e.x) / 2.0, s.y - (s.x - e.x) / (2.0 * 3.0f32.sqrt()))
+            } else {
+                pos2(
+                    (s.x + e.x) / 2.0 - (e.y - s.y) / (2.0 * 3.0f32.sqrt()),
+                    (s.y + e.y) / 2.0 - (s.x - e.x) / (2.0 * 3.0f32.sqrt()),
+                )
+            }
+        } else {
+            if s.y == e.y {
+                // s-e
+                pos2((s.x + e.x) / 2.0, s.y + (s.x - e.x) / (2.0 * 3.0f32.sqrt()))
+            } else {
+                // e
+                //  \ r
+                //   ----m
+                //      /
+                //   l /
+                //     \ s
+                // (e.x-s.x)x + (e.y-s.y)y = 0
+                // ==>
+                // x = e.y - s.y
+                // y = s.x - e.x
+                pos2(
+                    (s.x + e.x) / 2.0 + (e.y - s.y) / (2.0 * 3.0f32.sqrt()),
+                    (s.y + e.y) / 2.0 + (s.x - e.x) / (2.0 * 3.0f32.sqrt()),
+                )
+            }
+        };
+        new.extend_from_slice(&[s, l, m, r]);
+    }
+    if OPEN {
+        new.push(prev[len - 1]);
+    }
+    tracing::debug!(len = new.len());
+    new
 }
 
 #[derive(Debug)]
-struct Context<const ANTI: bool> {
+struct Context<const ANTI: bool, const OPEN: bool> {
     program: glow::Program,
+    /// Draws each segment as two triangles, for widths `glLineWidth` can't do
+    program_wide: glow::Program,
     vao: glow::VertexArray,
+    /// Reads consecutive points of `vbo` as the ends of one instanced segment
+    vao_wide: glow::VertexArray,
     vbo: glow::Buffer,
-    vertices: Vec<Vec<Pos2>>,
-    depth: u32,
+    /// Position of each point of `vbo` on the outline one depth above
+    parents_vbo: glow::Buffer,
+    /// Upper bound of `ALIASED_LINE_WIDTH_RANGE`, often 1 in core profiles and WebGL
+    max_line_width: f32,
+    /// `levels[d]` is the outline of depth `d + 1`, `levels[0]` is the initial triangle or
+    /// segment
+    levels: Levels<Vec<Pos2>>,
+    /// Depth of the outline in the GPU buffer, `None` before the first upload
+    depth: Option<u32>,
+    /// Time the buffer went one depth deeper with the smooth depth on, while the spikes grow
+    grown_at: Option<f64>,
 }
 
 const VERTEX_SHADER: &str = r#"
 layout (location = 0) in vec2 in_pos;
-uniform float uni_ratio;
+layout (location = 1) in vec2 in_parent;
+uniform vec2 uni_scale;
+// 0 draws the outline one depth above, 1 the outline of the buffer
+uniform float uni_morph;
 void main() {
-    gl_Position = vec4(in_pos, 0.0, 1.0);
-    gl_Position.x *= uni_ratio;
+    gl_Position = vec4(mix(in_parent, in_pos, uni_morph), 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+}
+"#;
+
+/// One instance per segment, 6 vertices for the 2 triangles of a quad around it
+const WIDE_VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_start;
+layout (location = 1) in vec2 in_end;
+layout (location = 2) in vec2 in_parent_start;
+layout (location = 3) in vec2 in_parent_end;
+uniform vec2 uni_scale;
+uniform float uni_morph;
+uniform vec2 uni_viewport;
+uniform float uni_width;
+const float ALONG[6] = float[6](0.0, 0.0, 1.0, 0.0, 1.0, 1.0);
+const float SIDE[6] = float[6](1.0, -1.0, 1.0, -1.0, -1.0, 1.0);
+void main() {
+    vec2 start = mix(in_parent_start, in_start, uni_morph) * uni_scale;
+    vec2 end = mix(in_parent_end, in_end, uni_morph) * uni_scale;
+    // the normal is taken in pixels so the width is the same in every direction
+    vec2 dir = (end - start) * uni_viewport;
+    vec2 normal = normalize(vec2(-dir.y, dir.x)) * uni_width / uni_viewport;
+    vec2 pos = mix(start, end, ALONG[gl_VertexID]) + normal * SIDE[gl_VertexID];
+    gl_Position = vec4(pos, 0.0, 1.0);
 }
 "#;
 
 const FRAGMENT_SHADER: &str = r#"
 precision mediump float;
+uniform vec3 uni_color;
 out vec4 out_color;
 void main() {
-    out_color = vec4(0.7, 0.7, 0.7, 1.0);
+    out_color = vec4(uni_color, 1.0);
 }
 "#;
 
-impl<const ANTI: bool> Context<ANTI> {
-    fn new(gl: &glow::Context) -> Self {
-        use glow::HasContext as _;
+impl<const ANTI: bool, const OPEN: bool> super::GlResources for Context<ANTI, OPEN> {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
 
-        let shader_version = if cfg!(target_arch = "wasm32") {
-            // in/out
-            "#version 300 es"
-        } else {
-            // location
-            "#version 330"
-        };
+    fn keep_from(&mut self, lost: Self) {
+        self.levels = lost.levels;
+    }
+}
 
-        unsafe {
-            let program = gl.create_program().expect("Cannot create program");
-
-            let shader_sources = [
-                (glow::VERTEX_SHADER, VERTEX_SHADER),
-                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
-            ];
-
-            let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader = gl
-                        .create_shader(*shader_type)
-                        .expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
-                    gl.compile_shader(shader);
-                    if !gl.get_shader_compile_status(shader) {
-                        panic!("{}", gl.get_shader_info_log(shader));
-                    }
-                    gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
-
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
-            }
+impl<const ANTI: bool, const OPEN: bool> Context<ANTI, OPEN> {
+    fn new(gl: &glow::Context, max_depth: u32) -> Result<Self, String> {
+        use glow::HasContext as _;
 
-            for shader in shaders {
-                gl.detach_shader(program, shader);
-                gl.delete_shader(shader);
-            }
+        unsafe {
+            let program = create_program(gl, VERTEX_SHADER)?;
+            let program_wide = create_program(gl, WIDE_VERTEX_SHADER)?;
+            let mut range = [1.0f32; 2];
+            gl.get_parameter_f32_slice(glow::ALIASED_LINE_WIDTH_RANGE, &mut range);
 
-            Self {
+            Ok(Self {
                 program,
+                program_wide,
                 vao: gl.create_vertex_array().unwrap(),
+                vao_wide: gl.create_vertex_array().unwrap(),
                 vbo: gl.create_buffer().unwrap(),
-                vertices: vec![vec![
-                    pos2(-0.8, -0.8 / 3.0_f32.sqrt()),
-                    pos2(0.8, -0.8 / 3.0_f32.sqrt()),
-                    pos2(0.0, 1.6 / 3.0_f32.sqrt()),
-                ]],
-                depth: 1,
-            }
-        }
-    }
-
-    fn calc(&mut self, depth: u32) {
-        if self.vertices.len() > depth as usize - 1 {
-            return;
-        }
-        for d in self.vertices.len()..depth as usize {
-            let len = self.vertices[d - 1].len();
-            let mut new = Vec::with_capacity(len * 4);
-            let iter = (1..len)
-                .map(|i| (i - 1, i))
-                .chain([(len - 1, 0)])
-                .map(|(s, e)| (self.vertices[d - 1][s], self.vertices[d - 1][e]));
-            for (s, e) in iter {
-                // s---l\   /r---e
-                //       \ /
-                //        m
-                let l = pos2((e.x + 2.0 * s.x) / 3.0, (e.y + 2.0 * s.y) / 3.0);
-                let r = pos2((s.x + 2.0 * e.x) / 3.0, (s.y + 2.0 * e.y) / 3.0);
-                #[allow(clippy::collapsible_else_if)]
-                let m = if ANTI {
-                    if s.y == e.y {
-                        pos2((s.x + e.x) / 2.0, s.y - (s.x - e.x) / (2.0 * 3.0f32.sqrt()))
-                    } else {
-                        pos2(
-                            (s.x + e.x) / 2.0 - (e.y - s.y) / (2.0 * 3.0f32.sqrt()),
-                            (s.y + e.y) / 2.0 - (s.x - e.x) / (2.0 * 3.0f32.sqrt()),
-                        )
-                    }
-                } else {
-                    if s.y == e.y {
-                        // s-e
-                        pos2((s.x + e.x) / 2.0, s.y + (s.x - e.x) / (2.0 * 3.0f32.sqrt()))
-                    } else {
-                        // e
-                        //  \ r
-                        //   ----m
-                        //      /
-                        //   l /
-                        //     \ s
-                        // (e.x-s.x)x + (e.y-s.y)y = 0
-                        // ==>
-                        // x = e.y - s.y
-                        // y = s.x - e.x
-                        pos2(
-                            (s.x + e.x) / 2.0 + (e.y - s.y) / (2.0 * 3.0f32.sqrt()),
-                            (s.y + e.y) / 2.0 + (s.x - e.x) / (2.0 * 3.0f32.sqrt()),
-                        )
-                    }
-                };
-                new.extend_from_slice(&[s, l, m, r]);
-            }
-            tracing::debug!(depth = d + 1, len = new.len(), verts = self.vertices.len());
-            self.vertices.push(new);
+                parents_vbo: gl.create_buffer().unwrap(),
+                max_line_width: range[1],
+                levels: Levels::new(
+                    initial::<ANTI, OPEN>(),
+                    max_depth as usize,
+                    |prev: &Vec<Pos2>| next_level::<ANTI, OPEN>(prev),
+                ),
+                depth: None,
+                grown_at: None,
+            })
         }
     }
 
-    unsafe fn update_vertices(&mut self, gl: &glow::Context) {
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, outline: &[Pos2], depth: u32) {
         use glow::HasContext as _;
 
+        let mut vbo = upload::<OPEN>(gl, outline);
+        let mut parents_vbo = upload::<OPEN>(gl, &parents(outline, depth));
+
+        let stride = size_of::<Pos2>() as i32;
         let mut vao = gl.create_vertex_array().unwrap();
         gl.bind_vertex_array(Some(vao));
-
-        let verts_slice = self.vertices[self.depth as usize - 1].as_slice();
-        let verts_slice = std::slice::from_raw_parts(
-            verts_slice.as_ptr() as *const u8,
-            verts_slice.len() * size_of::<Pos2>(),
-        );
-
-        let mut vbo = gl.create_buffer().unwrap();
         gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
-
         gl.enable_vertex_attrib_array(0);
-        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, stride, 0);
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(parents_vbo));
+        gl.enable_vertex_attrib_array(1);
+        gl.vertex_attrib_pointer_f32(1, 2, glow::FLOAT, false, stride, 0);
+
+        let mut vao_wide = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao_wide));
+        for (buffer, first) in [(vbo, 0), (parents_vbo, 2)] {
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+            gl.enable_vertex_attrib_array(first);
+            gl.vertex_attrib_pointer_f32(first, 2, glow::FLOAT, false, stride, 0);
+            gl.vertex_attrib_divisor(first, 1);
+            gl.enable_vertex_attrib_array(first + 1);
+            gl.vertex_attrib_pointer_f32(first + 1, 2, glow::FLOAT, false, stride, stride);
+            gl.vertex_attrib_divisor(first + 1, 1);
+        }
+
         swap(&mut self.vao, &mut vao);
+        swap(&mut self.vao_wide, &mut vao_wide);
         swap(&mut self.vbo, &mut vbo);
+        swap(&mut self.parents_vbo, &mut parents_vbo);
         gl.delete_vertex_array(vao);
+        gl.delete_vertex_array(vao_wide);
         gl.delete_buffer(vbo);
+        gl.delete_buffer(parents_vbo);
+    }
+
+    /// Whether the outline at `depth` is not in the buffer yet or its spikes are still growing
+    /// at `now`, so the view has to be painted again.
+    fn morphing(&self, depth: u32, now: f64) -> bool {
+        self.depth != Some(depth) || self.grown_at.map_or(false, |at| now - at < MORPH_DURATION)
+    }
+
+    fn export(
+        &mut self,
+        gl: &glow::Context,
+        depth: u32,
+        color: [f32; 3],
+        line_width: f32,
+        size: (u32, u32),
+    ) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| {
+            self.paint(gl, depth, color, line_width, ratio, None)
+        })
     }
 
-    fn paint(&mut self, gl: &glow::Context, mut depth: u32, ratio: f32) {
+    /// Draw the outline, with GL lines if the driver supports `line_width` pixels, otherwise
+    /// with the triangles of `program_wide`. With the time `now`, the spikes of a depth one
+    /// deeper than the last painted grow out of the edges; `None` draws them at once.
+    fn paint(
+        &mut self,
+        gl: &glow::Context,
+        mut depth: u32,
+        color: [f32; 3],
+        line_width: f32,
+        ratio: f32,
+        now: Option<f64>,
+    ) {
         use glow::HasContext as _;
-        depth = depth.min(MAX_DEPTH);
-        depth = depth.max(1);
-        if self.depth != depth {
-            self.calc(depth);
-            self.depth = depth;
-            unsafe { self.update_vertices(gl) };
+        self.levels.step();
+        depth = shown_depth(depth, self.levels.ready());
+        if self.depth != Some(depth) {
+            let outline = self.levels.get(depth as usize - 1).unwrap();
+            let grown = self.depth.map_or(false, |last| depth == last + 1);
+            self.grown_at = now.filter(|_| grown);
+            self.depth = Some(depth);
+            unsafe { self.update_vertices(gl, &outline, depth) };
         }
+        let morph = match (now, self.grown_at) {
+            (Some(now), Some(at)) => ((now - at) / MORPH_DURATION).min(1.0) as f32,
+            _ => 1.0,
+        };
+        let count = vertex_count::<OPEN>(depth) as i32;
+        let wide = line_width > self.max_line_width;
+        let program = if wide {
+            self.program_wide
+        } else {
+            self.program
+        };
         unsafe {
-            gl.use_program(Some(self.program));
-            gl.bind_vertex_array(Some(self.vao));
+            gl.use_program(Some(program));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            gl.uniform_3_f32(
+                gl.get_uniform_location(program, "uni_color").as_ref(),
+                color[0],
+                color[1],
+                color[2],
+            );
             gl.uniform_1_f32(
-                gl.get_uniform_location(self.program, "uni_ratio").as_ref(),
-                ratio,
+                gl.get_uniform_location(program, "uni_morph").as_ref(),
+                morph,
             );
-            gl.draw_arrays(glow::LINE_LOOP, 0, 3 * 4i32.pow(depth - 1));
+            if wide {
+                let mut viewport = [0; 4];
+                gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+                gl.uniform_2_f32(
+                    gl.get_uniform_location(program, "uni_viewport").as_ref(),
+                    viewport[2] as f32,
+                    viewport[3] as f32,
+                );
+                gl.uniform_1_f32(
+                    gl.get_uniform_location(program, "uni_width").as_ref(),
+                    line_width,
+                );
+                gl.bind_vertex_array(Some(self.vao_wide));
+                gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, line_count::<OPEN>(depth) as i32);
+            } else {
+                gl.bind_vertex_array(Some(self.vao));
+                gl.line_width(line_width);
+                let mode = if OPEN {
+                    glow::LINE_STRIP
+                } else {
+                    glow::LINE_LOOP
+                };
+                gl.draw_arrays(mode, 0, count);
+                gl.line_width(1.0);
+            }
+        }
+    }
+}
+
+/// The depth drawn for `depth` once `ready` levels are built: the deepest ready one until the
+/// requested one is computed. `levels[d - 1]` is the outline of depth `d`.
+fn shown_depth(depth: u32, ready: usize) -> u32 {
+    depth.max(1).min(ready as u32)
+}
+
+/// The outline at depth 1: an equilateral triangle, or its bottom edge for the open curve. The
+/// edge runs right to left so the spikes point up, and sits low enough to center them.
+fn initial<const ANTI: bool, const OPEN: bool>() -> Vec<Pos2> {
+    if OPEN {
+        let y = if ANTI { 0.26 } else { -0.26 };
+        vec![pos2(0.9, y), pos2(-0.9, y)]
+    } else {
+        vec![
+            pos2(-0.8, -0.8 / 3.0_f32.sqrt()),
+            pos2(0.8, -0.8 / 3.0_f32.sqrt()),
+            pos2(0.0, 1.6 / 3.0_f32.sqrt()),
+        ]
+    }
+}
+
+/// Upload `outline` to a new buffer. A closed loop gets the first point again at the end so
+/// the wide path can close it.
+unsafe fn upload<const OPEN: bool>(gl: &glow::Context, outline: &[Pos2]) -> glow::Buffer {
+    use glow::HasContext as _;
+
+    let mut closed = Vec::with_capacity(outline.len() + 1);
+    closed.extend_from_slice(outline);
+    if !OPEN {
+        closed.push(outline[0]);
+    }
+    let verts_slice = std::slice::from_raw_parts(
+        closed.as_ptr() as *const u8,
+        closed.len() * size_of::<Pos2>(),
+    );
+
+    let vbo = gl.create_buffer().unwrap();
+    gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+    gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
+    vbo
+}
+
+/// The outline program with `vertex_shader`, which differs between thin and wide lines.
+unsafe fn create_program(gl: &glow::Context, vertex_shader: &str) -> Result<glow::Program, String> {
+    super::create_program(
+        gl,
+        &[
+            (glow::VERTEX_SHADER, vertex_shader),
+            (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walk the depth up to 10 and back down to 1 while the levels are still being built,
+    /// then again once they all are, like `Context::paint` does frame after frame.
+    fn check<const ANTI: bool, const OPEN: bool>() {
+        let mut levels = Levels::new(initial::<ANTI, OPEN>(), 10, |prev: &Vec<Pos2>| {
+            next_level::<ANTI, OPEN>(prev)
+        });
+        let depths: Vec<u32> = (1..=10).chain((1..10).rev()).collect();
+        for round in 0..2 {
+            let mut shown = None;
+            for &depth in &depths {
+                levels.step();
+                let ready = levels.ready();
+                let drawn = shown_depth(depth, ready);
+                assert!(
+                    (1..=depth).contains(&drawn),
+                    "depth {} drew {}",
+                    depth,
+                    drawn
+                );
+                // the deepest level that is ready, never one that is still computing
+                assert_eq!(drawn as usize, (depth as usize).min(ready));
+                if shown != Some(drawn) {
+                    shown = Some(drawn);
+                    let outline = levels.get(drawn as usize - 1).unwrap();
+                    assert_eq!(
+                        outline.len(),
+                        vertex_count::<OPEN>(drawn),
+                        "depth {}",
+                        drawn
+                    );
+                }
+            }
+            if round == 0 {
+                levels.wait(9);
+                assert_eq!(levels.ready(), 10);
+            }
+        }
+        // asking for more than was built draws the last level
+        assert_eq!(shown_depth(12, levels.ready()), 10);
+        assert_eq!(shown_depth(0, levels.ready()), 1);
+    }
+
+    #[test]
+    fn vertex_count_of_every_depth() {
+        check::<false, false>();
+        check::<true, false>();
+        check::<false, true>();
+        check::<true, true>();
+    }
+
+    #[test]
+    fn snowflake_vertex_count() {
+        for depth in 1..=10 {
+            assert_eq!(vertex_count::<false>(depth), 3 * 4usize.pow(depth - 1));
         }
     }
 }
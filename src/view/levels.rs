@@ -0,0 +1,117 @@
+//! Geometry of every depth of a recursive fractal, computed off the render path
+//!
+//! Natively the levels are built by a background thread. Threads are not available on wasm,
+//! there one level is built per [`Levels::step`], so per frame.
+
+use parking_lot::Mutex;
+use std::{fmt, sync::Arc};
+
+pub struct Levels<T> {
+    /// `levels[d]` is built from `levels[d - 1]`, the first one is given
+    levels: Arc<Mutex<Vec<Arc<T>>>>,
+    count: usize,
+    #[cfg(target_arch = "wasm32")]
+    next: Box<dyn FnMut(&T) -> T + Send>,
+}
+
+impl<T: Send + Sync + 'static> Levels<T> {
+    /// Start building `count` levels, each one computed by `next` from the previous one.
+    pub fn new(first: T, count: usize, next: impl FnMut(&T) -> T + Send + 'static) -> Self {
+        let levels = Arc::new(Mutex::new(vec![Arc::new(first)]));
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            // the thread stops once the levels are dropped, e.g. replaced after a setting changed
+            let weak = Arc::downgrade(&levels);
+            let mut last = levels.lock()[0].clone();
+            let mut next = next;
+            std::thread::spawn(move || {
+                for _ in 1..count {
+                    // the lock is only held to publish, never while computing
+                    last = Arc::new(next(&last));
+                    match weak.upgrade() {
+                        Some(levels) => levels.lock().push(last.clone()),
+                        None => return,
+                    }
+                }
+            });
+        }
+        Self {
+            levels,
+            count,
+            #[cfg(target_arch = "wasm32")]
+            next: Box::new(next),
+        }
+    }
+
+    /// Build the next level when there is no background thread doing it.
+    pub fn step(&mut self) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let last = self.levels.lock().last().unwrap().clone();
+            if self.ready() < self.count {
+                let level = Arc::new((self.next)(&last));
+                self.levels.lock().push(level);
+            }
+        }
+    }
+
+    /// Block until `level` is built, or the last level if there are fewer, for rendering
+    /// without a window where there is no next frame to wait for.
+    pub fn wait(&mut self, level: usize) {
+        let level = level.min(self.count.max(1) - 1);
+        while self.ready() <= level {
+            #[cfg(target_arch = "wasm32")]
+            self.step();
+            #[cfg(not(target_arch = "wasm32"))]
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    /// Number of levels built so far, at least 1.
+    pub fn ready(&self) -> usize {
+        self.levels.lock().len()
+    }
+
+    pub fn get(&self, level: usize) -> Option<Arc<T>> {
+        self.levels.lock().get(level).cloned()
+    }
+}
+
+impl<T> fmt::Debug for Levels<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Levels")
+            .field("ready", &self.levels.lock().len())
+            .finish()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn dropped_levels_stop_building() {
+        // every level waits for the test to take it, so the thread can't run ahead
+        let (sender, receiver) = mpsc::sync_channel(0);
+        let levels = Levels::new(0u32, 100, move |last| {
+            sender.send(()).unwrap();
+            last + 1
+        });
+        receiver.recv().unwrap();
+        drop(levels);
+        // at most the level started before the drop was seen, then the thread ends
+        assert!(receiver.iter().count() <= 1);
+    }
+
+    #[test]
+    fn wait_builds_up_to_the_level() {
+        let mut levels = Levels::new(1u64, 10, |last| last * 2);
+        levels.wait(5);
+        assert!(levels.ready() > 5);
+        assert_eq!(*levels.get(5).unwrap(), 32);
+        // past the last level, it waits for the last one
+        levels.wait(100);
+        assert_eq!(levels.ready(), 10);
+    }
+}
@@ -0,0 +1,624 @@
+use super::{export, View, ViewArgs};
+use eframe::{
+    egui::{self, containers::*, *},
+    emath::{pos2, Pos2},
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    mem::{size_of, swap},
+    sync::Arc,
+};
+
+const MAX_DEPTH: u32 = 12;
+/// The expansion stops growing past this many symbols, deep rulesets explode quickly
+const MAX_SYMBOLS: usize = 2_000_000;
+
+/// A ruleset with the turtle settings that show it well
+struct Preset {
+    name: &'static str,
+    axiom: &'static str,
+    rules: &'static str,
+    angle: f32,
+    heading: f32,
+    depth: u32,
+}
+
+const PRESETS: [Preset; 3] = [
+    Preset {
+        name: "Koch",
+        axiom: "F--F--F",
+        rules: "F=F+F--F+F",
+        angle: 60.0,
+        heading: 0.0,
+        depth: 4,
+    },
+    Preset {
+        name: "Plant",
+        axiom: "X",
+        rules: "X=F+[[X]-X]-F[-F]+X\nF=FF",
+        angle: 25.0,
+        heading: 65.0,
+        depth: 5,
+    },
+    Preset {
+        name: "Sierpinski arrowhead",
+        axiom: "F",
+        rules: "F=G-F-G\nG=F+G+F",
+        angle: 60.0,
+        heading: 0.0,
+        depth: 6,
+    },
+];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LSystem {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    axiom: String,
+    /// One production per line, `F=F+F`
+    rules: String,
+    /// Turn of `+` and `-`, in degrees
+    angle: f32,
+    /// Direction the turtle starts in, in degrees counterclockwise from the x axis
+    heading: f32,
+    depth: u32,
+    /// Segments of the current settings, `None` when they changed since the last expansion
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lines: Option<Arc<Lines>>,
+    /// Theme of the last frame, so an export uses the colors on screen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dark_mode: bool,
+}
+
+impl Default for LSystem {
+    fn default() -> Self {
+        let mut view = Self {
+            gl: Default::default(),
+            error: None,
+            axiom: String::new(),
+            rules: String::new(),
+            angle: 0.0,
+            heading: 0.0,
+            depth: 0,
+            lines: None,
+            dark_mode: true,
+        };
+        view.load(&PRESETS[0]);
+        view
+    }
+}
+
+impl super::View for LSystem {
+    fn name(&self) -> &'static str {
+        "L-system"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "A string rewritten by rules, then drawn by a turtle.\n\
+             every symbol X with a rule X=... is replaced at each depth",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "rules: one per line, like F=F+F--F+F\n\
+             F / G: move forward and draw\n\
+             + / -: turn left / right by the angle\n\
+             [ / ]: save / restore the position and heading\n\
+             other symbols are only rewritten by the rules\n\
+             Home: load the Koch preset",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let context = self
+            .gl
+            .get_or_try_init(|| Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context))));
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        let context = Context::new(gl)?;
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let lines = self
+            .lines
+            .clone()
+            .unwrap_or_else(|| Arc::new(self.expand()));
+        self.gl
+            .get()?
+            .lock()
+            .export(gl, lines, self.dark_mode, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        self.dark_mode = ui.visuals().dark_mode;
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+
+        super::settings_popup(ui, self, None);
+
+        let gl = self.gl.clone();
+        let lines = self.lines().clone();
+        let dark_mode = self.dark_mode;
+        let ratio = rect.height() / rect.width();
+
+        let callback = egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut gl = gl.get().unwrap().lock();
+                    super::revive(&mut *gl, painter.gl(), Context::new);
+                    gl.paint(painter.gl(), &lines, dark_mode, ratio);
+                } else {
+                    eprintln!("Can't do custom painting because we are not using a glow context");
+                }
+            }),
+        };
+        painter.add(callback);
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        let lines = self.lines().clone();
+        if let Some(error) = &lines.error {
+            ui.colored_label(Color32::RED, error.as_str());
+        } else {
+            ui.label(format!("Painted line count: {}", lines.vertices.len() / 2));
+        }
+        if lines.truncated {
+            ui.colored_label(
+                Color32::YELLOW,
+                format!("stopped expanding at {} symbols", MAX_SYMBOLS),
+            );
+        }
+        ui.horizontal_wrapped(|ui| {
+            for preset in &PRESETS {
+                if ui.button(preset.name).clicked() {
+                    self.load(preset);
+                }
+            }
+        });
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Axiom :");
+            changed |= ui.text_edit_singleline(&mut self.axiom).changed();
+        });
+        ui.label("Rules :");
+        changed |= ui
+            .add(
+                TextEdit::multiline(&mut self.rules)
+                    .code_editor()
+                    .desired_rows(3),
+            )
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Angle :");
+            changed |= ui
+                .add(DragValue::new(&mut self.angle).speed(0.5).suffix("°"))
+                .changed();
+            ui.label("Heading :");
+            changed |= ui
+                .add(DragValue::new(&mut self.heading).speed(1.0).suffix("°"))
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Depth :");
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.depth)
+                        .speed(1.0)
+                        .clamp_range(0..=MAX_DEPTH),
+                )
+                .changed();
+            if ui.button("+").clicked() && self.depth < MAX_DEPTH {
+                self.depth += 1;
+                changed = true;
+            }
+            if ui.button("-").clicked() && self.depth > 0 {
+                self.depth -= 1;
+                changed = true;
+            }
+        });
+        if changed {
+            self.lines = None;
+        }
+        if ui
+            .add_enabled(!lines.vertices.is_empty(), Button::new("Save SVG"))
+            .clicked()
+        {
+            let svg = self.to_svg();
+            export::save_svg(&export::file_name(self.name(), "svg"), &svg);
+        }
+        if ui.button("reset").clicked() {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.load(&PRESETS[0]);
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        if let Some(depth) = args.depth {
+            self.depth = depth.min(MAX_DEPTH);
+            self.lines = None;
+        }
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            depth: Some(self.depth),
+            ..Default::default()
+        }
+    }
+}
+
+impl LSystem {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+
+    fn load(&mut self, preset: &Preset) {
+        self.axiom = preset.axiom.to_owned();
+        self.rules = preset.rules.to_owned();
+        self.angle = preset.angle;
+        self.heading = preset.heading;
+        self.depth = preset.depth;
+        self.lines = None;
+    }
+
+    /// Segments of the current settings, expanded again if they changed.
+    fn lines(&mut self) -> &Arc<Lines> {
+        if self.lines.is_none() {
+            self.lines = Some(Arc::new(self.expand()));
+        }
+        self.lines.as_ref().unwrap()
+    }
+
+    fn expand(&self) -> Lines {
+        match parse_rules(&self.rules) {
+            Ok(rules) => {
+                let (symbols, truncated) = expand(&self.axiom, &rules, self.depth);
+                let vertices = turtle(&symbols, self.angle, self.heading);
+                tracing::debug!(symbols = symbols.len(), verts = vertices.len());
+                Lines {
+                    vertices,
+                    truncated,
+                    error: None,
+                }
+            }
+            Err(error) => Lines {
+                vertices: Vec::new(),
+                truncated: false,
+                error: Some(error),
+            },
+        }
+    }
+
+    /// The turtle path as an SVG document, segments that continue each other are joined
+    /// into one polyline.
+    fn to_svg(&mut self) -> String {
+        let color = if self.dark_mode {
+            DARK_COLOR
+        } else {
+            LIGHT_COLOR
+        };
+        let lines = self.lines().clone();
+        let mut svg = export::Svg::default();
+        let mut path: Vec<Pos2> = Vec::new();
+        for segment in lines.vertices.chunks_exact(2) {
+            if path.last() != Some(&segment[0]) {
+                if path.len() > 1 {
+                    svg.polyline(&path, false, color, 1.0);
+                }
+                path.clear();
+                path.push(segment[0]);
+            }
+            path.push(segment[1]);
+        }
+        if path.len() > 1 {
+            svg.polyline(&path, false, color, 1.0);
+        }
+        svg.finish()
+    }
+}
+
+/// Turtle path of an expanded L-system, as pairs of points for `LINES`
+#[derive(Debug)]
+struct Lines {
+    vertices: Vec<Pos2>,
+    /// The expansion hit [`MAX_SYMBOLS`] before reaching the requested depth
+    truncated: bool,
+    /// Why the rules could not be parsed
+    error: Option<String>,
+}
+
+/// Parse one `symbol=replacement` production per line, `->` is accepted in place of `=`.
+fn parse_rules(text: &str) -> Result<HashMap<char, String>, String> {
+    let mut rules = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (symbol, replacement) = line
+            .split_once("->")
+            .or_else(|| line.split_once('='))
+            .ok_or_else(|| format!("rule {}: expected symbol=replacement", i + 1))?;
+        let mut symbol = symbol.trim().chars();
+        match (symbol.next(), symbol.next()) {
+            (Some(symbol), None) => {
+                rules.insert(symbol, replacement.trim().replace(' ', ""));
+            }
+            _ => return Err(format!("rule {}: rewrite a single symbol", i + 1)),
+        }
+    }
+    Ok(rules)
+}
+
+/// Rewrite `axiom` `depth` times, stopping early once it grows past [`MAX_SYMBOLS`].
+fn expand(axiom: &str, rules: &HashMap<char, String>, depth: u32) -> (String, bool) {
+    let mut current = axiom.to_owned();
+    for _ in 0..depth {
+        let mut next = String::with_capacity(current.len() * 2);
+        for symbol in current.chars() {
+            match rules.get(&symbol) {
+                Some(replacement) => next.push_str(replacement),
+                None => next.push(symbol),
+            }
+            if next.len() > MAX_SYMBOLS {
+                return (current, true);
+            }
+        }
+        current = next;
+    }
+    (current, false)
+}
+
+/// Walk the turtle over `symbols` and scale the path into the unit square.
+fn turtle(symbols: &str, angle: f32, heading: f32) -> Vec<Pos2> {
+    let turn = angle.to_radians();
+    let mut pos = pos2(0.0, 0.0);
+    let mut dir = heading.to_radians();
+    let mut stack = Vec::new();
+    let mut vertices = Vec::new();
+    for symbol in symbols.chars() {
+        match symbol {
+            'F' | 'G' => {
+                let next = pos + vec2(dir.cos(), dir.sin());
+                vertices.extend([pos, next]);
+                pos = next;
+            }
+            '+' => dir += turn,
+            '-' => dir -= turn,
+            '[' => stack.push((pos, dir)),
+            ']' => {
+                if let Some((p, d)) = stack.pop() {
+                    pos = p;
+                    dir = d;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let bounds = Rect::from_points(&vertices);
+    let size = bounds.width().max(bounds.height());
+    if size > 0.0 {
+        let center = bounds.center();
+        for v in &mut vertices {
+            *v = pos2(0.0, 0.0) + (*v - center) * (1.8 / size);
+        }
+    }
+    vertices
+}
+
+#[derive(Debug)]
+struct Context {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+    /// Segments in the GPU buffer, `None` before the first upload
+    lines: Option<Arc<Lines>>,
+}
+
+/// Line colors on a dark and on a light canvas
+const DARK_COLOR: [f32; 3] = [0.6, 0.85, 0.5];
+const LIGHT_COLOR: [f32; 3] = [0.2, 0.45, 0.15];
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+uniform vec2 uni_scale;
+void main() {
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+uniform vec3 uni_color;
+out vec4 out_color;
+void main() {
+    out_color = vec4(uni_color, 1.0);
+}
+"#;
+
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+}
+
+impl Context {
+    fn new(gl: &glow::Context) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            Ok(Self {
+                program,
+                vao: gl.create_vertex_array().unwrap(),
+                vbo: gl.create_buffer().unwrap(),
+                lines: None,
+            })
+        }
+    }
+
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, vertices: &[Pos2]) {
+        use glow::HasContext as _;
+
+        let mut vao = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao));
+
+        let verts_slice = std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            vertices.len() * size_of::<Pos2>(),
+        );
+
+        let mut vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
+
+        gl.enable_vertex_attrib_array(0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+        swap(&mut self.vao, &mut vao);
+        swap(&mut self.vbo, &mut vbo);
+        gl.delete_vertex_array(vao);
+        gl.delete_buffer(vbo);
+    }
+
+    fn export(
+        &mut self,
+        gl: &glow::Context,
+        lines: Arc<Lines>,
+        dark_mode: bool,
+        size: (u32, u32),
+    ) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| self.paint(gl, &lines, dark_mode, ratio))
+    }
+
+    fn paint(&mut self, gl: &glow::Context, lines: &Arc<Lines>, dark_mode: bool, ratio: f32) {
+        use glow::HasContext as _;
+        if !matches!(&self.lines, Some(uploaded) if Arc::ptr_eq(uploaded, lines)) {
+            unsafe { self.update_vertices(gl, &lines.vertices) };
+            self.lines = Some(lines.clone());
+        }
+        if lines.vertices.is_empty() {
+            return;
+        }
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.bind_vertex_array(Some(self.vao));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            let color = if dark_mode { DARK_COLOR } else { LIGHT_COLOR };
+            gl.uniform_3_f32(
+                gl.get_uniform_location(self.program, "uni_color").as_ref(),
+                color[0],
+                color[1],
+                color[2],
+            );
+            gl.draw_arrays(glow::LINES, 0, lines.vertices.len() as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_are_parsed() {
+        let rules = parse_rules(" X -> F+[X] \n\nF = F F\n").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[&'X'], "F+[X]");
+        assert_eq!(rules[&'F'], "FF");
+        assert_eq!(parse_rules("").unwrap().len(), 0);
+        assert_eq!(
+            parse_rules("F=FF\nFF").unwrap_err(),
+            "rule 2: expected symbol=replacement"
+        );
+        assert_eq!(
+            parse_rules("FG=F").unwrap_err(),
+            "rule 1: rewrite a single symbol"
+        );
+        assert!(parse_rules("=F").is_err());
+    }
+
+    #[test]
+    fn expansion_rewrites_every_symbol() {
+        let rules = parse_rules("F=G-F-G\nG=F+G+F").unwrap();
+        assert_eq!(expand("F", &rules, 0), ("F".to_owned(), false));
+        assert_eq!(expand("F", &rules, 1), ("G-F-G".to_owned(), false));
+        assert_eq!(
+            expand("F", &rules, 2),
+            ("F+G+F-G-F-G-F+G+F".to_owned(), false)
+        );
+        // symbols without a rule are kept
+        assert_eq!(expand("[F]", &rules, 1), ("[G-F-G]".to_owned(), false));
+    }
+
+    #[test]
+    fn every_preset_expands() {
+        for preset in &PRESETS {
+            let rules = parse_rules(preset.rules).unwrap();
+            let (symbols, truncated) = expand(preset.axiom, &rules, preset.depth);
+            assert!(!truncated && !symbols.is_empty(), "{}", preset.name);
+        }
+    }
+
+    #[test]
+    fn expansion_stops_at_the_symbol_limit() {
+        let rules = parse_rules("F=FF").unwrap();
+        let (symbols, truncated) = expand("F", &rules, 40);
+        assert!(truncated);
+        // the last whole level that fits
+        assert_eq!(symbols.len(), MAX_SYMBOLS.next_power_of_two() / 2);
+    }
+}
@@ -0,0 +1,385 @@
+//! The Mandelbrot set next to the Julia set of the point under the cursor
+//!
+//! Both halves reuse the GL contexts of the Mandelbrot and Julia views, each half is a plane
+//! of its own that pans and zooms independently.
+
+use super::{export, juliaset_shader, mandelbrot_shader, plane, View};
+use eframe::egui::{self, *};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MandelbrotJuliaSplit {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    mandelbrot_gl: OnceCell<Arc<Mutex<mandelbrot_shader::Context>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    julia_gl: OnceCell<Arc<Mutex<juliaset_shader::Context>>>,
+    /// Why the GL contexts could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    /// When a half was last brought back in range, for [`plane::clamp_warning`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clamped_at: Option<f64>,
+    mandelbrot_center: (f64, f64),
+    mandelbrot_ratio: f64,
+    julia_center: (f64, f64),
+    julia_ratio: f64,
+    /// The point of the Mandelbrot half shown as a Julia set
+    c: (f32, f32),
+    /// Keep `c` when the cursor moves, toggled by a click on the Mandelbrot half
+    locked: bool,
+    /// Exponent of `z^m + c` in both halves
+    m: i32,
+    iterations: i32,
+    /// Fraction of the width taken by the Mandelbrot half
+    split: f32,
+    /// Whether the cursor picks `c` this frame
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hovering: bool,
+}
+
+/// `c` of a fresh view, also where it falls back to if it is not a number
+const DEFAULT_C: (f32, f32) = (-0.8, 0.156);
+
+impl Default for MandelbrotJuliaSplit {
+    fn default() -> Self {
+        Self {
+            mandelbrot_gl: Default::default(),
+            julia_gl: Default::default(),
+            error: None,
+            clamped_at: None,
+            mandelbrot_center: mandelbrot_shader::DEFAULT_CENTER,
+            mandelbrot_ratio: 1.0,
+            julia_center: (0.0, 0.0),
+            julia_ratio: 1.0,
+            c: DEFAULT_C,
+            locked: false,
+            m: 2,
+            iterations: 128,
+            split: 0.5,
+            hovering: false,
+        }
+    }
+}
+
+impl super::View for MandelbrotJuliaSplit {
+    fn name(&self) -> &'static str {
+        "Mandelbrot and Julia"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "The Mandelbrot set next to the Julia set of the point under the cursor.\n\
+             z -> z^m + c",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "hover the Mandelbrot set: show the Julia set of that c\n\
+             click the Mandelbrot set: lock or unlock c\n\
+             drag: pan the half under the cursor\n\
+             scroll: zoom at the cursor\n\
+             drag the bar in the middle: resize the halves\n\
+             Escape or Home: reset the views",
+        )
+    }
+
+    fn is_dynamic(&self) -> bool {
+        self.hovering
+    }
+
+    fn status_text(&self) -> Option<String> {
+        Some(format!(
+            "c = {}{}",
+            plane::format((self.c.0 as f64, self.c.1 as f64)),
+            if self.locked { " (locked)" } else { "" }
+        ))
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let mandelbrot = self.mandelbrot_gl.get_or_try_init(|| {
+            mandelbrot_shader::Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context)))
+        });
+        let julia = self.julia_gl.get_or_try_init(|| {
+            juliaset_shader::Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context)))
+        });
+        if let Err(err) = mandelbrot.and(julia) {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        // each half needs a column at least
+        if size.0 < 2 {
+            return None;
+        }
+        let left = ((size.0 as f32 * self.split) as u32).clamp(1, size.0 - 1);
+        let right = size.0 - left;
+        let mandelbrot =
+            mandelbrot_shader::render_offscreen(gl, &self.mandelbrot_params(), (left, size.1))?;
+        let julia = juliaset_shader::render_offscreen(gl, &self.julia_params(), (right, size.1))?;
+        let pixels: Vec<u8> = mandelbrot
+            .chunks(left as usize * 4)
+            .zip(julia.chunks(right as usize * 4))
+            .flat_map(|(left, right)| left.iter().chain(right))
+            .copied()
+            .collect();
+        Some(export::to_image(size, &pixels))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+        // Reserve slots below the settings popup, the callbacks are filled in after input handling
+        let mandelbrot_canvas = painter.add(Shape::Noop);
+        let julia_canvas = painter.add(Shape::Noop);
+        let marker = painter.add(Shape::Noop);
+        let divider = painter.add(Shape::Noop);
+
+        let popup = super::settings_popup(ui, self, None);
+        let over_popup = |pos: Pos2| popup.contains(pos);
+
+        // The bar between the halves, dragged to resize them
+        let split_x = rect.left() + rect.width() * self.split;
+        let bar = Rect::from_x_y_ranges(split_x - 3.0..=split_x + 3.0, rect.y_range());
+        let bar_response = ui.interact(bar, ui.id().with("split"), Sense::drag());
+        if bar_response.hovered() || bar_response.dragged() {
+            ui.output().cursor_icon = CursorIcon::ResizeHorizontal;
+        }
+        if bar_response.dragged() {
+            self.split += bar_response.drag_delta().x / rect.width();
+            self.split = self.split.clamp(0.2, 0.8);
+        }
+        let split_x = rect.left() + rect.width() * self.split;
+        let left = Rect::from_x_y_ranges(rect.left()..=split_x - 3.0, rect.y_range());
+        let right = Rect::from_x_y_ranges(split_x + 3.0..=rect.right(), rect.y_range());
+        painter.set(
+            divider,
+            Shape::rect_filled(
+                Rect::from_x_y_ranges(split_x - 1.0..=split_x + 1.0, rect.y_range()),
+                0.0,
+                ui.visuals().widgets.noninteractive.bg_stroke.color,
+            ),
+        );
+
+        let response = ui.interact(left, ui.id().with("mandelbrot"), Sense::click_and_drag());
+        pan_zoom(
+            ui,
+            &response,
+            left,
+            &over_popup,
+            &mut self.mandelbrot_center,
+            &mut self.mandelbrot_ratio,
+        );
+        let hover = response.hover_pos().filter(|pos| !over_popup(*pos));
+        let pick = |pos| {
+            let z = plane::to_complex(left, pos, self.mandelbrot_center, self.mandelbrot_ratio);
+            (z.0 as f32, z.1 as f32)
+        };
+        if let Some(pos) = hover {
+            if response.clicked() {
+                self.locked = !self.locked;
+                self.c = pick(pos);
+            } else if !self.locked && !response.dragged() {
+                self.c = pick(pos);
+            }
+        }
+        self.hovering = hover.is_some() && !self.locked;
+
+        let response = ui.interact(right, ui.id().with("julia"), Sense::drag());
+        pan_zoom(
+            ui,
+            &response,
+            right,
+            &over_popup,
+            &mut self.julia_center,
+            &mut self.julia_ratio,
+        );
+
+        let mut clamped = plane::sanitize(&mut self.mandelbrot_center, &mut self.mandelbrot_ratio);
+        clamped |= plane::sanitize(&mut self.julia_center, &mut self.julia_ratio);
+        if !(self.c.0.is_finite() && self.c.1.is_finite()) {
+            self.c = DEFAULT_C;
+            clamped = true;
+        }
+        plane::clamp_warning(
+            &painter,
+            rect,
+            clamped,
+            &mut self.clamped_at,
+            ui.input().time,
+        );
+
+        let ppp = ui.ctx().pixels_per_point();
+        let screen = ui.ctx().input().screen_rect();
+        let gl = self.mandelbrot_gl.clone();
+        let params = self.mandelbrot_params();
+        let view = (left.width() * ppp, left.height() * ppp);
+        let margin = plane::margin(left, screen, ppp);
+        painter.set(
+            mandelbrot_canvas,
+            egui::PaintCallback {
+                rect: left,
+                callback: std::sync::Arc::new(move |_info, render_ctx| {
+                    if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                        let mut gl = gl.get().unwrap().lock();
+                        super::revive(&mut *gl, painter.gl(), mandelbrot_shader::Context::new);
+                        gl.paint_cached(painter.gl(), view, margin, &params);
+                    } else {
+                        eprintln!(
+                            "Can't do custom painting because we are not using a glow context"
+                        );
+                    }
+                }),
+            },
+        );
+        let gl = self.julia_gl.clone();
+        let params = self.julia_params();
+        let view = (right.width() * ppp, right.height() * ppp);
+        let margin = plane::margin(right, screen, ppp);
+        painter.set(
+            julia_canvas,
+            egui::PaintCallback {
+                rect: right,
+                callback: std::sync::Arc::new(move |_info, render_ctx| {
+                    if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                        let mut gl = gl.get().unwrap().lock();
+                        super::revive(&mut *gl, painter.gl(), juliaset_shader::Context::new);
+                        gl.paint_cached(painter.gl(), view, margin, &params);
+                    } else {
+                        eprintln!(
+                            "Can't do custom painting because we are not using a glow context"
+                        );
+                    }
+                }),
+            },
+        );
+
+        let c = (self.c.0 as f64, self.c.1 as f64);
+        let pos = plane::to_screen(left, c, self.mandelbrot_center, self.mandelbrot_ratio);
+        if left.contains(pos) {
+            painter.set(
+                marker,
+                Shape::circle_stroke(pos, 4.0, Stroke::new(1.5, Color32::WHITE)),
+            );
+        }
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        let shortcuts = super::shortcuts_enabled(ui);
+        ui.horizontal(|ui| {
+            ui.label("C :");
+            ui.add(DragValue::new(&mut self.c.0).speed(0.01));
+            ui.label("+");
+            ui.add(DragValue::new(&mut self.c.1).speed(0.01).suffix("i"));
+        });
+        ui.checkbox(&mut self.locked, "lock C");
+        ui.horizontal(|ui| {
+            ui.label("m :").on_hover_text("exponent of z^m + c");
+            ui.add(DragValue::new(&mut self.m).speed(1.0).clamp_range(2..=9));
+        });
+        ui.horizontal(|ui| {
+            ui.label("iterations :");
+            ui.add(
+                DragValue::new(&mut self.iterations)
+                    .speed(4.0)
+                    .clamp_range(16..=2048),
+            );
+        });
+        if ui.button("reset").clicked() || (shortcuts && ui.input().key_pressed(Key::Escape)) {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        let default = Self::default();
+        self.mandelbrot_center = default.mandelbrot_center;
+        self.mandelbrot_ratio = default.mandelbrot_ratio;
+        self.julia_center = default.julia_center;
+        self.julia_ratio = default.julia_ratio;
+    }
+}
+
+impl MandelbrotJuliaSplit {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+
+    fn mandelbrot_params(&self) -> mandelbrot_shader::Params {
+        mandelbrot_shader::Params {
+            center: self.mandelbrot_center,
+            ratio: self.mandelbrot_ratio,
+            samples: 1,
+            high_precision: false,
+            perturbation: false,
+            m: self.m,
+            escape_radius: 4.0,
+            iterations: self.iterations,
+            coloring: mandelbrot_shader::Coloring::Escape,
+            outline_width: mandelbrot_shader::DEFAULT_OUTLINE_WIDTH,
+            color_interior: false,
+            background: None,
+            color_offset: 0.0,
+        }
+    }
+
+    fn julia_params(&self) -> juliaset_shader::Params {
+        juliaset_shader::Params {
+            center: self.julia_center,
+            ratio: self.julia_ratio,
+            c: self.c,
+            m: self.m,
+            iterations: self.iterations,
+            smooth: true,
+            escape_radius: 256.0,
+            palette: juliaset_shader::Palette::Hsv,
+            background: None,
+            samples: 1,
+        }
+    }
+}
+
+/// Pan `center` by dragging `rect` and zoom `ratio` by scrolling over it, except over the popup.
+fn pan_zoom(
+    ui: &Ui,
+    response: &Response,
+    rect: Rect,
+    over_popup: impl Fn(Pos2) -> bool,
+    center: &mut (f64, f64),
+    ratio: &mut f64,
+) {
+    let from_popup = ui.input().pointer.press_origin().map_or(false, &over_popup);
+    if response.dragged() && !from_popup {
+        plane::pan(rect, response.drag_delta(), center, *ratio);
+    }
+    if let Some(pos) = response.hover_pos() {
+        let scroll = ui.input().scroll_delta.y;
+        if scroll != 0.0 && !over_popup(pos) {
+            let factor = 1.1f64.powf(scroll as f64 / 50.0);
+            plane::zoom_at(rect, pos, center, ratio, factor);
+        }
+    }
+}
@@ -1,21 +1,177 @@
+#[cfg(not(target_arch = "wasm32"))]
+use super::animation;
+use super::{
+    bookmark, export, frame_cache::FrameCache, history::History, plane, JuliaSeed, View, ViewArgs,
+};
 use eframe::egui::{self, *};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use std::{mem::size_of, sync::Arc};
 
+/// Adaptive quality lowers the iterations while frames take longer than this, in seconds
+const SLOW_FRAME: f32 = 1.0 / 30.0;
+/// and raises them back while frames take less than this
+const FAST_FRAME: f32 = 1.0 / 50.0;
+/// Seconds the view stays still before it is drawn with all the iterations again
+const SETTLE: f64 = 0.3;
+/// Weight of the last frame in the moving average of the frame time
+const FRAME_TIME_SMOOTHING: f32 = 0.2;
+const MIN_ITERATIONS: i32 = 16;
+/// Middle of the set of z² + c, which spans -2..0.47 on the real axis, so the whole set is
+/// framed with a margin on both sides
+pub const DEFAULT_CENTER: (f64, f64) = (-0.75, 0.0);
+/// Width of the glow of distance estimation, in pixels
+pub const DEFAULT_OUTLINE_WIDTH: f32 = 4.0;
+/// The escape counts of histogram equalization are rendered at `1 / COUNT_SCALE` of the
+/// viewport size, which is plenty to know how they are distributed
+const COUNT_SCALE: f32 = 4.0;
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct MandelbrotShader {
+    #[cfg_attr(feature = "serde", serde(skip))]
     gl: OnceCell<Arc<Mutex<Context>>>,
-    center: (f32, f32),
-    ratio: f32,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    /// When the view was last brought back in range, for [`plane::clamp_warning`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clamped_at: Option<f64>,
+    center: (f64, f64),
+    ratio: f64,
+    aa: bool,
+    show_grid: bool,
+    /// Draw in the square in the middle of the canvas, whatever the shape of the window
+    aspect_lock: bool,
+    high_precision: bool,
+    /// Draw with the perturbation shader, see [`Params::perturbation`]
+    perturbation: bool,
+    /// Exponent of `z^m + c`, 2 is the Mandelbrot set and higher ones are multibrot sets
+    m: i32,
+    /// An orbit escapes once `|z|²` exceeds this
+    escape_radius: f32,
+    /// Most iterations of an orbit, adaptive quality stays at or below it
+    iterations: i32,
+    /// Draw fewer iterations while the view moves and the frames are slow
+    adaptive: bool,
+    /// Iterations drawn in the last frame
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_iterations: i32,
+    /// Moving average of the frame time in seconds, followed by adaptive quality
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_time: f32,
+    /// Settings of the last frame and the time they changed, to tell when the view stopped
+    #[cfg_attr(feature = "serde", serde(skip))]
+    moved: Option<(Params, f64)>,
+    coloring: Coloring,
+    /// Width of the glow of distance estimation, in pixels
+    outline_width: f32,
+    /// Shade the points inside the set by how strongly their orbit is attracted
+    color_interior: bool,
+    /// Color of the points inside the set, `None` shows the canvas through them
+    background: Option<[f32; 3]>,
+    /// Turn the palette over time
+    cycle_colors: bool,
+    /// Turns of the palette per second while cycling
+    cycle_speed: f32,
+    /// Current turn of the palette, in `[0, 1)`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    color_offset: f32,
+    bookmarks: Vec<bookmark::Bookmark>,
+    /// Running move to a restored bookmark
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tween: Option<plane::Tween>,
+    /// Look for detail around the center in the next paint, see [`Context::find_detail`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    find_detail: bool,
+    /// Position of the fingers while pinching on a touch screen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pinch: Option<Pos2>,
+    /// Clicks pick the ends of the ruler instead of a Julia seed while it is `Some`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ruler: Option<plane::Ruler>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: History<Params>,
+    #[cfg(not(target_arch = "wasm32"))]
+    animation: animation::Animation,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    julia_seed: JuliaSeed,
 }
 
 impl Default for MandelbrotShader {
     fn default() -> Self {
         Self {
             gl: Default::default(),
-            center: (0.0, 0.0),
+            error: None,
+            clamped_at: None,
+            center: DEFAULT_CENTER,
             ratio: 1.0,
+            aa: false,
+            show_grid: false,
+            aspect_lock: false,
+            high_precision: false,
+            perturbation: false,
+            m: 2,
+            escape_radius: 4.0,
+            iterations: 128,
+            adaptive: false,
+            frame_iterations: 128,
+            frame_time: 0.0,
+            moved: None,
+            coloring: Coloring::Escape,
+            outline_width: DEFAULT_OUTLINE_WIDTH,
+            color_interior: false,
+            background: None,
+            cycle_colors: false,
+            cycle_speed: 0.1,
+            color_offset: 0.0,
+            bookmarks: Vec::new(),
+            tween: None,
+            find_detail: false,
+            pinch: None,
+            ruler: None,
+            history: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            animation: Default::default(),
+            julia_seed: Default::default(),
+        }
+    }
+}
+
+/// How a pixel is colored, must match `coloring` in the fragment shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Coloring {
+    /// Iteration count at which the orbit escapes
+    Escape,
+    /// Closest distance of the orbit to the origin
+    OrbitTrapPoint,
+    /// Closest distance of the orbit to the real and imaginary axes
+    OrbitTrapCross,
+    /// Iteration count at which the orbit escapes, with the palette spread so that every hue
+    /// covers about as many pixels
+    Histogram,
+    /// Estimated distance to the set, a glow along the boundary `outline_width` pixels wide
+    Distance,
+}
+
+impl Coloring {
+    const ALL: [Coloring; 5] = [
+        Self::Escape,
+        Self::OrbitTrapPoint,
+        Self::OrbitTrapCross,
+        Self::Histogram,
+        Self::Distance,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Escape => "escape time",
+            Self::OrbitTrapPoint => "orbit trap (point)",
+            Self::OrbitTrapCross => "orbit trap (cross)",
+            Self::Histogram => "histogram equalized",
+            Self::Distance => "distance estimation",
         }
     }
 }
@@ -25,11 +181,108 @@ impl super::View for MandelbrotShader {
         "Mandelbrot Set (Shader)"
     }
 
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "The points c whose orbit of z = 0 stays bounded.\n\
+             z -> z^m + c, m = 2 is the Mandelbrot set and higher m the multibrot sets",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "click: open the Julia set of that point\n\
+             scroll: zoom at the cursor\n\
+             pinch: zoom and pan on a touch screen\n\
+             measure: click two points to read their distance, a third click clears them\n\
+             snap to detail: move to the most detailed boundary near the center\n\
+             Ctrl+Z / Ctrl+Y: undo / redo a change of the settings or view\n\
+             keys, while the settings are open:\n\
+             - arrows or WASD: pan\n\
+             - Enter / PageDown: zoom in\n\
+             - Backspace / PageUp: zoom out\n\
+             - Escape or Home: reset the view",
+        )
+    }
+
     fn is_dynamic(&self) -> bool {
-        false
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.animation.running() {
+            return true;
+        }
+        self.tween.is_some() || self.cycle_colors
+    }
+
+    fn status_text(&self) -> Option<String> {
+        Some(plane::status(self.center, self.ratio))
+    }
+
+    fn debug_stats(&self) -> Option<String> {
+        Some(format!(
+            "iterations: {} / {}\nframe time (average): {:.1} ms",
+            self.frame_iterations,
+            self.iterations,
+            self.frame_time * 1e3
+        ))
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let context = self
+            .gl
+            .get_or_try_init(|| Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context))));
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let params = Params {
+            color_offset: self.color_offset,
+            ..self.params()
+        };
+        let pixels = render_offscreen(gl, &params, size)?;
+        Some(export::to_image(size, &pixels))
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        Some(("iterations", super::BENCHMARK_ITERATIONS.to_vec()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        let params = Params {
+            iterations: value as i32,
+            ..self.params()
+        };
+        // the shaders of the view, so the timings leave out their compilation
+        let pixels = self.gl.get()?.lock().render_offscreen(gl, &params, size)?;
+        Some(export::to_image(size, &pixels))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
     }
 
     fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let now = ui.input().time;
+        if let Some(detail) = self.gl.get().and_then(|gl| gl.lock().detail.take()) {
+            self.tween = Some(plane::Tween::new(
+                (self.center, self.ratio),
+                (detail, self.ratio),
+                now,
+            ));
+        }
+        plane::fly(&mut self.tween, now, &mut self.center, &mut self.ratio);
+        self.history.track(&self.params(), now);
         let painter = Painter::new(
             ui.ctx().clone(),
             ui.layer_id(),
@@ -37,61 +290,144 @@ impl super::View for MandelbrotShader {
         );
         let rect = painter.clip_rect();
         ui.expand_to_include_rect(rect);
+        // with the aspect locked, the fractal takes only the middle square of the canvas
+        let rect = if self.aspect_lock {
+            plane::square(rect)
+        } else {
+            rect
+        };
+        let painter = painter.with_clip_rect(rect);
+        // Reserve a slot below the settings popup, the callback is filled in after input handling
+        let canvas = painter.add(Shape::Noop);
+        let grid = painter.add(Shape::Noop);
+        let ruler = painter.add(Shape::Noop);
+
+        let hover = ui
+            .input()
+            .pointer
+            .hover_pos()
+            .filter(|pos| rect.contains(*pos));
+        let coordinates =
+            hover.map(|pos| plane::format(plane::to_complex(rect, pos, self.center, self.ratio)));
+        let popup = super::settings_popup(ui, self, coordinates);
+
+        let response = ui.interact(rect, ui.id().with("canvas"), Sense::click());
+        let pinching = plane::pinch(
+            &ui.input(),
+            rect,
+            &mut self.pinch,
+            &mut self.center,
+            &mut self.ratio,
+        );
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.clicked() && !popup.contains(pos) && !pinching {
+                let c = plane::to_complex(rect, pos, self.center, self.ratio);
+                match &mut self.ruler {
+                    Some(ruler) => ruler.click(c),
+                    None => self.julia_seed.set(Some((c.0 as f32, c.1 as f32))),
+                }
+            }
+        }
+        if let Some(pos) = response.hover_pos() {
+            let scroll = ui.input().scroll_delta.y;
+            if scroll != 0.0 && !popup.contains(pos) {
+                let factor = 1.1f64.powf(scroll as f64 / 50.0);
+                plane::zoom_at(rect, pos, &mut self.center, &mut self.ratio, factor);
+            }
+        }
+
+        let clamped = plane::sanitize(&mut self.center, &mut self.ratio);
+        plane::clamp_warning(&painter, rect, clamped, &mut self.clamped_at, now);
 
         let gl = self.gl.clone();
         let ppp = ui.ctx().pixels_per_point();
         let (width, height) = (rect.width() * ppp, rect.height() * ppp);
-        let margin = rect.left() * ppp + 0.5;
-        let center = self.center;
-        let ratio = self.ratio;
+        let margin = plane::margin(rect, ui.ctx().input().screen_rect(), ppp);
+        let iterations = self.adapt_iterations(ui.input().unstable_dt, now);
+        if iterations < self.iterations {
+            // keep painting until the view settles back to all the iterations
+            ui.ctx().request_repaint();
+        }
+        if self.cycle_colors {
+            let dt = ui.input().unstable_dt.min(0.1);
+            self.color_offset = (self.color_offset + dt * self.cycle_speed).fract();
+        }
+        let params = Params {
+            iterations,
+            color_offset: self.color_offset,
+            ..self.params()
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let job = self.animation.job();
+        #[cfg(not(target_arch = "wasm32"))]
+        let max_iterations = self.iterations;
+        // all the iterations, the adapted count of a moving view blurs the boundary
+        let find_detail = std::mem::take(&mut self.find_detail).then(|| self.params());
+        if find_detail.is_some() {
+            // the next frame picks up the point found
+            ui.ctx().request_repaint();
+        }
 
         let callback = egui::PaintCallback {
             rect,
             callback: std::sync::Arc::new(move |_info, render_ctx| {
                 if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
                     let mut gl = gl.get().unwrap().lock();
-                    gl.paint(painter.gl(), (width, height), center, ratio, margin);
+                    super::revive(&mut *gl, painter.gl(), Context::new);
+                    if let Some(params) = &find_detail {
+                        gl.detail = gl.find_detail(painter.gl(), params);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Some(job) = &job {
+                        job.lock().step(|center, ratio, size| {
+                            let params = Params {
+                                center,
+                                ratio,
+                                iterations: max_iterations,
+                                ..params
+                            };
+                            gl.render_offscreen(painter.gl(), &params, size)
+                        });
+                    }
+                    gl.paint_cached(painter.gl(), (width, height), margin, &params);
                 } else {
                     eprintln!("Can't do custom painting because we are not using a glow context");
                 }
             }),
         };
-        painter.add(callback);
-        Frame::popup(ui.style())
-            .stroke(Stroke::none())
-            .show(ui, |ui| {
-                ui.set_max_width(250.0);
-                CollapsingHeader::new("Settings").show(ui, |ui| self.options_ui(ui));
-            });
+        painter.set(canvas, callback);
+        if self.show_grid {
+            let shapes = plane::grid(&painter, rect, self.center, self.ratio);
+            painter.set(grid, Shape::Vec(shapes));
+        }
+        if let Some(measure) = &self.ruler {
+            let shapes = measure.shapes(&painter, rect, self.center, self.ratio);
+            painter.set(ruler, Shape::Vec(shapes));
+        }
     }
-}
 
-impl MandelbrotShader {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let default = Self::default();
-        default
-            .gl
-            .get_or_init(|| Arc::new(Mutex::new(Context::new(&cc.gl))));
-        default
-    }
     fn options_ui(&mut self, ui: &mut Ui) {
-        if ui.input().key_pressed(Key::ArrowLeft) || ui.input().key_pressed(Key::A) {
-            self.center.0 -= 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::ArrowRight) || ui.input().key_pressed(Key::D) {
-            self.center.0 += 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::ArrowDown) || ui.input().key_pressed(Key::S) {
-            self.center.1 -= 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::ArrowUp) || ui.input().key_pressed(Key::W) {
-            self.center.1 += 0.1 / self.ratio;
-        }
-        if ui.input().key_pressed(Key::Enter) || ui.input().key_pressed(Key::PageDown) {
-            self.ratio *= 1.2;
-        }
-        if ui.input().key_pressed(Key::Backspace) || ui.input().key_pressed(Key::PageUp) {
-            self.ratio /= 1.2;
+        // keys typed into a field are not shortcuts
+        let shortcuts = super::shortcuts_enabled(ui);
+        if shortcuts {
+            if ui.input().key_pressed(Key::ArrowLeft) || ui.input().key_pressed(Key::A) {
+                self.center.0 -= 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::ArrowRight) || ui.input().key_pressed(Key::D) {
+                self.center.0 += 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::ArrowDown) || ui.input().key_pressed(Key::S) {
+                self.center.1 -= 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::ArrowUp) || ui.input().key_pressed(Key::W) {
+                self.center.1 += 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::Enter) || ui.input().key_pressed(Key::PageDown) {
+                self.ratio *= 1.2;
+            }
+            if ui.input().key_pressed(Key::Backspace) || ui.input().key_pressed(Key::PageUp) {
+                self.ratio /= 1.2;
+            }
         }
         ui.horizontal(|ui| {
             ui.label("center :");
@@ -100,27 +436,321 @@ impl MandelbrotShader {
             ui.label("y:");
             ui.add(DragValue::new(&mut self.center.1).speed(0.01));
         });
+        plane::zoom_ui(ui, &mut self.ratio);
+        if ui
+            .button("Snap to detail")
+            .on_hover_text("move to the most detailed boundary near the center")
+            .clicked()
+        {
+            self.find_detail = true;
+        }
+        let current = bookmark::Bookmark {
+            center: self.center,
+            ratio: self.ratio,
+            m: self.m,
+            c: None,
+            iterations: None,
+        };
+        if let Some(bookmark) = bookmark::ui(ui, &mut self.bookmarks, current) {
+            self.tween = Some(plane::Tween::new(
+                (self.center, self.ratio),
+                (bookmark.center, bookmark.ratio),
+                ui.input().time,
+            ));
+            self.m = bookmark.m;
+        }
+        ui.horizontal(|ui| {
+            ui.label("m :");
+            ui.add(DragValue::new(&mut self.m).speed(1.0).clamp_range(2..=9));
+            if ui.button("+").clicked() && self.m < 9 {
+                self.m += 1;
+            }
+            if ui.button("-").clicked() && self.m > 2 {
+                self.m -= 1;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("iterations :");
+            ui.add(
+                DragValue::new(&mut self.iterations)
+                    .speed(4.0)
+                    .clamp_range(MIN_ITERATIONS..=2048),
+            );
+        });
+        ui.checkbox(&mut self.adaptive, "adaptive quality")
+            .on_hover_text("fewer iterations while moving if the frames get slow");
         ui.horizontal(|ui| {
-            ui.label("ratio :");
+            ui.label("escape limit (|z|²) :")
+                .on_hover_text("the square of the escape radius, 4 is a radius of 2");
             ui.add(
-                DragValue::new(&mut self.ratio)
-                    .speed(0.5)
-                    .clamp_range(1.0..=f32::MAX),
+                DragValue::new(&mut self.escape_radius)
+                    .speed(1.0)
+                    .clamp_range(4.0..=1024.0),
             );
         });
-        if ui.button("reset").clicked() || ui.input().key_pressed(Key::Escape) {
-            self.center = (0.0, 0.0);
-            self.ratio = 1.0;
+        ComboBox::from_label("coloring")
+            .selected_text(self.coloring.name())
+            .show_ui(ui, |ui| {
+                for coloring in Coloring::ALL {
+                    ui.selectable_value(&mut self.coloring, coloring, coloring.name());
+                }
+            });
+        if self.coloring == Coloring::Distance {
+            ui.add(
+                Slider::new(&mut self.outline_width, 0.5..=32.0)
+                    .logarithmic(true)
+                    .text("outline width (px)"),
+            );
+        }
+        ui.add_enabled(
+            matches!(
+                self.coloring,
+                Coloring::Escape | Coloring::Histogram | Coloring::Distance
+            ),
+            Checkbox::new(&mut self.color_interior, "color the interior"),
+        );
+        super::background_ui(ui, &mut self.background);
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.cycle_colors, "cycle colors");
+            ui.add_enabled(
+                self.cycle_colors,
+                Slider::new(&mut self.cycle_speed, 0.01..=2.0)
+                    .logarithmic(true)
+                    .text("turns/s"),
+            );
+        });
+        ui.checkbox(&mut self.aa, "anti-aliasing (2x2)");
+        ui.checkbox(&mut self.show_grid, "grid");
+        ui.checkbox(&mut self.aspect_lock, "aspect lock")
+            .on_hover_text("draw in a square in the middle of the canvas");
+        if ui
+            .selectable_label(self.ruler.is_some(), "measure")
+            .on_hover_text("click two points of the set to read their distance")
+            .clicked()
+        {
+            self.ruler = match self.ruler {
+                Some(_) => None,
+                None => Some(plane::Ruler::default()),
+            };
+        }
+        let fp64 = self
+            .gl
+            .get()
+            .map_or(false, |gl| gl.lock().program_f64.is_some());
+        ui.add_enabled(
+            fp64,
+            Checkbox::new(&mut self.high_precision, "double precision"),
+        )
+        .on_disabled_hover_text("GL_ARB_gpu_shader_fp64 is not supported");
+        let perturbation = self
+            .gl
+            .get()
+            .map_or(false, |gl| gl.lock().program_perturbation.is_some());
+        ui.add_enabled(
+            perturbation && self.m == 2,
+            Checkbox::new(&mut self.perturbation, "perturbation (deep zoom)"),
+        )
+        .on_hover_text("offsets from a reference orbit computed on the CPU, no GPU doubles needed")
+        .on_disabled_hover_text(if perturbation {
+            "only for m = 2"
+        } else {
+            "the perturbation shader failed to build"
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        self.animation.ui(ui, self.center, self.ratio);
+        if ui.button("reset").clicked() || (shortcuts && ui.input().key_pressed(Key::Escape)) {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.center = DEFAULT_CENTER;
+        self.ratio = 1.0;
+    }
+
+    fn undo(&mut self) {
+        if let Some(params) = self.history.undo(&self.params()) {
+            self.set_params(&params);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(params) = self.history.redo(&self.params()) {
+            self.set_params(&params);
+        }
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        self.center = args.center.unwrap_or(self.center);
+        self.ratio = args.zoom.unwrap_or(self.ratio);
+        if let Some(m) = args.m {
+            self.m = m.clamp(2, 9);
+        }
+    }
+
+    fn bookmarks(&self) -> &[bookmark::Bookmark] {
+        &self.bookmarks
+    }
+
+    fn import_bookmarks(&mut self, bookmarks: Vec<bookmark::Bookmark>) {
+        bookmark::merge(&mut self.bookmarks, bookmarks);
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            center: Some(self.center),
+            zoom: Some(self.ratio),
+            m: Some(self.m),
+            ..Default::default()
         }
     }
 }
 
+impl MandelbrotShader {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>, julia_seed: JuliaSeed) -> Self {
+        let mut view = Self {
+            julia_seed,
+            ..super::restore(cc)
+        };
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+    /// The settings to draw, undo and redo. The turn of the cycling palette is left at 0,
+    /// otherwise every frame would be a change to undo.
+    fn params(&self) -> Params {
+        Params {
+            center: self.center,
+            ratio: self.ratio,
+            samples: if self.aa { 2 } else { 1 },
+            high_precision: self.high_precision,
+            perturbation: self.perturbation,
+            m: self.m,
+            escape_radius: self.escape_radius,
+            iterations: self.iterations,
+            coloring: self.coloring,
+            outline_width: self.outline_width,
+            color_interior: self.color_interior,
+            background: self.background,
+            color_offset: 0.0,
+        }
+    }
+    /// Take the settings of `params`, the inverse of [`Self::params`].
+    fn set_params(&mut self, params: &Params) {
+        self.tween = None;
+        self.center = params.center;
+        self.ratio = params.ratio;
+        self.aa = params.samples > 1;
+        self.high_precision = params.high_precision;
+        self.perturbation = params.perturbation;
+        self.m = params.m;
+        self.escape_radius = params.escape_radius;
+        self.iterations = params.iterations;
+        self.coloring = params.coloring;
+        self.outline_width = params.outline_width;
+        self.color_interior = params.color_interior;
+        self.background = params.background;
+    }
+    /// Iterations to draw this frame. With adaptive quality they drop while the average frame
+    /// takes longer than [`SLOW_FRAME`] and come back once frames are fast again, or all at
+    /// once when the view stayed still for [`SETTLE`] seconds.
+    fn adapt_iterations(&mut self, dt: f32, now: f64) -> i32 {
+        let params = self.params();
+        if self
+            .moved
+            .as_ref()
+            .map_or(true, |(last, _)| *last != params)
+        {
+            self.moved = Some((params, now));
+        }
+        if !self.adaptive {
+            self.frame_iterations = self.iterations;
+            return self.iterations;
+        }
+        // the first frame after the app was idle says nothing about the rendering
+        let dt = dt.min(0.1);
+        self.frame_time += (dt - self.frame_time) * FRAME_TIME_SMOOTHING;
+        let still = self.moved.map_or(true, |(_, at)| now - at >= SETTLE);
+        let last = self.frame_iterations.min(self.iterations);
+        self.frame_iterations = if still {
+            self.iterations
+        } else if self.frame_time > SLOW_FRAME {
+            (last * 4 / 5).max(MIN_ITERATIONS)
+        } else if self.frame_time < FAST_FRAME {
+            (last * 5 / 4).min(self.iterations)
+        } else {
+            last
+        };
+        self.frame_iterations
+    }
+}
+
+/// Everything the shader reads besides the viewport, so a frame can be rendered without the
+/// view or egui
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub center: (f64, f64),
+    pub ratio: f64,
+    /// Samples per pixel along each axis
+    pub samples: i32,
+    /// Only honored when the driver supports double precision
+    pub high_precision: bool,
+    /// Iterate in single precision as offsets from a reference orbit at `center`, computed in
+    /// double precision on the CPU. Zooms as deep as `high_precision` without GPU doubles,
+    /// only honored for `m = 2` and takes over `high_precision`.
+    pub perturbation: bool,
+    pub m: i32,
+    pub escape_radius: f32,
+    pub iterations: i32,
+    pub coloring: Coloring,
+    /// In pixels, only read by [`Coloring::Distance`]
+    pub outline_width: f32,
+    pub color_interior: bool,
+    /// Cleared behind the frame, `None` leaves what is already there
+    pub background: Option<[f32; 3]>,
+    /// Added to the hue of the palette, in turns
+    pub color_offset: f32,
+}
+
+/// Render `params` at `size` pixels into RGBA8 rows from top to bottom.
+///
+/// The GL resources are created for this call and deleted afterwards, so the only
+/// requirement is a current GL context.
+pub fn render_offscreen(gl: &glow::Context, params: &Params, size: (u32, u32)) -> Option<Vec<u8>> {
+    let mut context = Context::new(gl).ok()?;
+    let pixels = context.render_offscreen(gl, params, size);
+    context.destroy(gl);
+    pixels
+}
+
 #[derive(Debug)]
-struct Context {
+pub(super) struct Context {
     program: glow::Program,
+    /// `program` with the escape loop in double precision, if the driver supports it
+    program_f64: Option<glow::Program>,
     vao: glow::VertexArray,
-    _vbo: glow::Buffer,
-    _ebo: glow::Buffer,
+    vbo: glow::Buffer,
+    ebo: glow::Buffer,
+    /// Escape count pass of histogram equalization, `None` if it failed to build
+    program_count: Option<glow::Program>,
+    /// `program_count` in double precision
+    program_count_f64: Option<glow::Program>,
+    /// `program` iterating offsets from `orbit`, see [`Params::perturbation`]
+    program_perturbation: Option<glow::Program>,
+    /// `program_count` iterating offsets from `orbit`
+    program_count_perturbation: Option<glow::Program>,
+    /// Reference orbit of the perturbation programs, one texel per iteration in rows of
+    /// [`ORBIT_WIDTH`]
+    orbit: glow::Texture,
+    /// Share of the escaping pixels below each escape count, one texel per count
+    histogram: glow::Texture,
+    /// Width of `histogram`, 0 when it holds nothing usable
+    histogram_size: i32,
+    /// Last on-screen frame, redrawn while the parameters stay the same
+    cache: FrameCache<Params>,
+    /// Point found by [`Self::find_detail`] in a paint callback, for the view to move to
+    detail: Option<(f64, f64)>,
 }
 
 const VERTICES: &[f32] = &[-1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0];
@@ -133,16 +763,73 @@ void main() {
 }
 "#;
 
+/// Prepended to `FRAGMENT_SHADER` to build the double precision variant
+const HIGH_PRECISION: &str =
+    "#extension GL_ARB_gpu_shader_fp64 : require\n#define HIGH_PRECISION\n";
+/// Prepended to `FRAGMENT_SHADER` to build the variant writing the escape count of each pixel
+/// into an unsigned integer target, for histogram equalization
+const COUNT_PASS: &str = "#define COUNT_PASS\n";
+/// Prepended to `FRAGMENT_SHADER` to build the variant iterating offsets from a reference
+/// orbit, see [`Params::perturbation`]
+const PERTURBATION: &str = "#define PERTURBATION\n";
+/// Texels per row of the reference orbit, well within the `MAX_TEXTURE_SIZE` of 2048 that
+/// GLES 3.0 and WebGL 2 guarantee. Must match `ORBIT_WIDTH` in the shader.
+const ORBIT_WIDTH: usize = 1024;
+
 // hsv2rgb: https://stackoverflow.com/questions/15095909/from-rgb-to-hsv-in-opengl-glsl
 const FRAGMENT_SHADER: &str = r#"
+// the escape loop needs more than mediump, which is all some mobile GPUs give by default
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
 precision mediump float;
+#endif
+#ifdef HIGH_PRECISION
+#define real double
+#define real2 dvec2
+#else
+#define real float
+#define real2 vec2
+#endif
 uniform vec2 viewport;
+// the corners are split into a high and a low part to carry double precision
 uniform vec2 min;
 uniform vec2 max;
-uniform float margin;
+uniform vec2 min_lo;
+uniform vec2 max_lo;
+uniform vec2 margin;
+// max - min, without the cancellation of subtracting the float corners
+uniform vec2 span;
+uniform int samples;
+// 0: escape time, 1: orbit trap at the origin, 2: orbit trap on the axes,
+// 3: escape time through `histogram`, 4: distance estimation
+uniform int coloring;
+// width of the glow of distance estimation, in pixels
+uniform float outline_width;
+// shade the points that don't escape instead of leaving them black
+uniform bool interior;
+// the hue of each escape count with histogram equalization
+uniform sampler2D histogram;
+#ifdef PERTURBATION
+// Z(n) of the reference orbit at the center of the view, from Z(0) = 0
+uniform sampler2D orbit;
+uniform int orbit_len;
+const int ORBIT_WIDTH = 1024;
+
+vec2 orbit_at(int n) {
+    return texelFetch(orbit, ivec2(n % ORBIT_WIDTH, n / ORBIT_WIDTH), 0).xy;
+}
+#endif
+#ifdef COUNT_PASS
+out uvec4 out_count;
+#else
 out vec4 out_color;
-const float MAX = 128.0;
-const float LIMIT = 4.0;
+#endif
+uniform int max_iter;
+uniform int m;
+uniform float limit;
+// turn of the palette, hsv2rgb wraps the hue around
+uniform float color_offset;
 
 vec3 hsv2rgb(vec3 c) {
     vec4 K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
@@ -150,79 +837,210 @@ vec3 hsv2rgb(vec3 c) {
     return c.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), c.y);
 }
 
-vec3 run() {
+vec3 run(vec2 frag, out float trap, out float min_dz, out float dc_len) {
     float count;
-    vec2 c = mix(min, max, (gl_FragCoord.xy - vec2(margin, margin)) / viewport);
-    vec2 z = c;
-    for (count = 0.0; count < MAX; count+=1.0) {
-        float r = z.x * z.x - z.y * z.y + c.x;
-        float i = 2.0 * z.x * z.y + c.y;
-        if (r * r + i * i > LIMIT) break;
-        z = vec2(r, i);
-    }
-    return vec3(z, count);
+    trap = 1e20;
+    // derivative of the orbit with respect to c, for the distance to the set
+    vec2 dc = vec2(1.0, 0.0);
+    // derivative of the orbit, it shrinks towards 0 inside an attracting bulb
+    vec2 dz = vec2(1.0, 0.0);
+    min_dz = 1e20;
+#ifdef PERTURBATION
+    // c - C and z - Z(at) of the reference, small enough for floats at any zoom
+    vec2 dc0 = ((frag - margin) / viewport - 0.5) * span;
+    vec2 d = dc0;
+    int at = 1;
+    real2 z = orbit_at(at) + d;
+#else
+    real2 lo = real2(min) + real2(min_lo);
+    real2 hi = real2(max) + real2(max_lo);
+    real2 c = mix(lo, hi, real2((frag - margin) / viewport));
+    real2 z = c;
+#endif
+    for (count = 0.0; count < float(max_iter); count+=1.0) {
+        // q = z^(m-1), p = z^m
+        real2 q = real2(1.0, 0.0);
+        for (int n = 1; n < m; n++) {
+            q = real2(q.x * z.x - q.y * z.y, q.x * z.y + q.y * z.x);
+        }
+        real2 p = real2(q.x * z.x - q.y * z.y, q.x * z.y + q.y * z.x);
+        if (interior) {
+            // dz = m z^(m-1) dz
+            vec2 d = float(m) * vec2(q);
+            dz = vec2(d.x * dz.x - d.y * dz.y, d.x * dz.y + d.y * dz.x);
+            min_dz = min(min_dz, length(dz));
+        }
+        if (coloring == 4) {
+            // dc = m z^(m-1) dc + 1
+            vec2 d = float(m) * vec2(q);
+            dc = vec2(d.x * dc.x - d.y * dc.y, d.x * dc.y + d.y * dc.x) + vec2(1.0, 0.0);
+        }
+#ifdef PERTURBATION
+        // z² + c - Z(at)² - C = 2 Z(at) d + d² + dc0, only z² has a perturbation shader
+        vec2 zn = orbit_at(at);
+        d = vec2(
+            2.0 * (zn.x * d.x - zn.y * d.y) + d.x * d.x - d.y * d.y,
+            2.0 * (zn.x * d.y + zn.y * d.x) + 2.0 * d.x * d.y
+        ) + dc0;
+        at++;
+        vec2 next = orbit_at(at) + d;
+        real r = next.x;
+        real i = next.y;
+#else
+        real r = p.x + c.x;
+        real i = p.y + c.y;
+#endif
+        vec2 w = vec2(float(r), float(i));
+        if (coloring == 1) {
+            trap = min(trap, length(w));
+        } else if (coloring == 2) {
+            trap = min(trap, min(abs(w.x), abs(w.y)));
+        }
+        if (r * r + i * i > real(limit)) break;
+        z = real2(r, i);
+#ifdef PERTURBATION
+        // restart from Z(0) = 0 once z is closer to 0 than to the reference, or the reference
+        // ends, so that d stays small: the glitches of a single reference never show up
+        if (dot(next, next) < dot(d, d) || at + 1 >= orbit_len) {
+            d = next;
+            at = 0;
+        }
+#endif
+    }
+    dc_len = length(dc);
+    return vec3(vec2(z), count);
 }
 
-void main() {
-    vec3 r = run();
-    if (r.z == MAX) {
-        out_color = vec4(0.0, 0.0, 0.0, 0.0);
+vec4 color_at(vec2 frag) {
+    float trap;
+    float min_dz;
+    float dc_len;
+    vec3 r = run(frag, trap, min_dz, dc_len);
+    if (coloring == 1 || coloring == 2) {
+        float t = clamp(trap, 0.0, 1.0);
+        return vec4(hsv2rgb(vec3(0.6 + 0.5 * t + color_offset, 0.8, pow(1.0 - t, 2.0))), 1.0);
+    }
+    if (r.z == float(max_iter)) {
+        if (interior) {
+            // dark at the center of a bulb, brighter towards its edge
+            float t = sqrt(clamp(min_dz, 0.0, 1.0));
+            return vec4(0.05 * t, 0.1 * t, 0.15 + 0.45 * t, 1.0);
+        }
+        return vec4(0.0, 0.0, 0.0, 0.0);
+    } else if (coloring == 4) {
+        // |z| log|z| / |dc| estimates the distance from c to the set, here over the width
+        // of the glow in pixels: bright on the boundary, dark once that far from it
+        float zl = length(r.xy);
+        float pixel = span.x / viewport.x;
+        float t = clamp(zl * log(zl) / dc_len / (pixel * outline_width), 0.0, 1.0);
+        return vec4(hsv2rgb(vec3(0.6 + color_offset, 0.6 * t, pow(1.0 - t, 2.0))), 1.0);
     } else if (r.z == 0.0) {
-        out_color = vec4(1.0, 1.0, 1.0, 1.0);
+        return vec4(1.0, 1.0, 1.0, 1.0);
     } else {
-        float c = r.z / MAX;
+        float c = coloring == 3
+            ? texelFetch(histogram, ivec2(int(r.z), 0), 0).r
+            : r.z / float(max_iter);
         float sum = r.x * r.x + r.y * r.y;
-        vec3 color = hsv2rgb(vec3(c , 0.9, sum / 4.0));
-        out_color = vec4(color, 1.0);
+        vec3 color = hsv2rgb(vec3(c + color_offset, 0.9, sum / limit));
+        return vec4(color, 1.0);
     }
 }
+
+void main() {
+#ifdef COUNT_PASS
+    float trap;
+    float min_dz;
+    float dc_len;
+    out_count = uvec4(uint(run(gl_FragCoord.xy, trap, min_dz, dc_len).z), 0u, 0u, 1u);
+#else
+    // average a samples x samples grid of sub-pixel positions
+    vec4 sum = vec4(0.0);
+    for (int i = 0; i < samples; i++) {
+        for (int j = 0; j < samples; j++) {
+            vec2 offset = (vec2(float(i), float(j)) + 0.5) / float(samples) - 0.5;
+            sum += color_at(gl_FragCoord.xy + offset);
+        }
+    }
+    out_color = sum / float(samples * samples);
+#endif
+}
 "#;
 
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+}
+
 impl Context {
-    fn new(gl: &glow::Context) -> Self {
+    pub(super) fn new(gl: &glow::Context) -> Result<Self, String> {
         use glow::HasContext as _;
 
-        let shader_version = if cfg!(target_arch = "wasm32") {
-            // in/out
-            "#version 300 es"
-        } else {
-            // location
-            "#version 330"
-        };
-
         unsafe {
-            let program = gl.create_program().expect("Cannot create program");
-
-            let shader_sources = [
-                (glow::VERTEX_SHADER, VERTEX_SHADER),
-                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
-            ];
-
-            let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader = gl
-                        .create_shader(*shader_type)
-                        .expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
-                    gl.compile_shader(shader);
-                    if !gl.get_shader_compile_status(shader) {
-                        panic!("{}", gl.get_shader_info_log(shader));
-                    }
-                    gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
-
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
-            }
+            let program = create_program(gl, "")?;
+            let fp64 = !cfg!(target_arch = "wasm32")
+                && gl.supported_extensions().contains("GL_ARB_gpu_shader_fp64");
+            // without it the double precision checkbox is disabled, the view still works
+            let program_f64 = if fp64 {
+                create_program(gl, HIGH_PRECISION)
+                    .map_err(|err| tracing::warn!(%err, "Cannot build the double precision shader"))
+                    .ok()
+            } else {
+                None
+            };
+            // without it histogram equalization falls back to the plain escape time coloring
+            let program_count = create_program(gl, COUNT_PASS)
+                .map_err(|err| tracing::warn!(%err, "Cannot build the escape count shader"))
+                .ok();
+            let program_count_f64 = match (program_f64, program_count) {
+                (Some(_), Some(_)) => create_program(gl, &format!("{}{}", HIGH_PRECISION, COUNT_PASS))
+                    .map_err(|err| tracing::warn!(%err, "Cannot build the double precision escape count shader"))
+                    .ok(),
+                _ => None,
+            };
+            // without it the perturbation checkbox is disabled
+            let program_perturbation = create_program(gl, PERTURBATION)
+                .map_err(|err| tracing::warn!(%err, "Cannot build the perturbation shader"))
+                .ok();
+            let program_count_perturbation = match (program_perturbation, program_count) {
+                (Some(_), Some(_)) => create_program(
+                    gl,
+                    &format!("{}{}", PERTURBATION, COUNT_PASS),
+                )
+                .map_err(
+                    |err| tracing::warn!(%err, "Cannot build the perturbation escape count shader"),
+                )
+                .ok(),
+                _ => None,
+            };
 
-            for shader in shaders {
-                gl.detach_shader(program, shader);
-                gl.delete_shader(shader);
-            }
+            let histogram = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(histogram));
+            // texelFetch ignores filtering, but the texture is incomplete with mipmaps
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+            let orbit = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(orbit));
+            // float textures can't be filtered everywhere, and texelFetch doesn't need it
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
 
             let vao = gl.create_vertex_array().unwrap();
             gl.bind_vertex_array(Some(vao));
@@ -252,50 +1070,483 @@ impl Context {
             gl.enable_vertex_attrib_array(0);
             gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
 
-            Self {
+            Ok(Self {
                 program,
+                program_f64,
                 vao,
-                _vbo: vbo,
-                _ebo: ebo,
+                vbo,
+                ebo,
+                program_count,
+                program_count_f64,
+                program_perturbation,
+                program_count_perturbation,
+                orbit,
+                histogram,
+                histogram_size: 0,
+                cache: FrameCache::new(gl)?,
+                detail: None,
+            })
+        }
+    }
+
+    fn destroy(self, gl: &glow::Context) {
+        use glow::HasContext as _;
+        unsafe {
+            gl.delete_program(self.program);
+            for program in [
+                self.program_f64,
+                self.program_count,
+                self.program_count_f64,
+                self.program_perturbation,
+                self.program_count_perturbation,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                gl.delete_program(program);
             }
+            gl.delete_texture(self.histogram);
+            gl.delete_texture(self.orbit);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+            gl.delete_buffer(self.ebo);
+        }
+        self.cache.destroy(gl);
+    }
+
+    /// Render `params` at `size` pixels into RGBA8 rows from top to bottom.
+    fn render_offscreen(
+        &mut self,
+        gl: &glow::Context,
+        params: &Params,
+        size: (u32, u32),
+    ) -> Option<Vec<u8>> {
+        let view = (size.0 as f32, size.1 as f32);
+        if params.coloring == Coloring::Histogram {
+            self.update_histogram(gl, view, params);
         }
+        export::render_pixels(gl, size, |gl| self.paint(gl, view, (0.5, 0.5), params))
     }
 
-    fn paint(
+    /// `paint`, unless the last frame drawn in this viewport had the same `params`
+    pub(super) fn paint_cached(
         &mut self,
         gl: &glow::Context,
         view: (f32, f32),
-        center: (f32, f32),
-        ratio: f32,
-        margin: f32,
+        margin: (f32, f32),
+        params: &Params,
     ) {
+        if !self.cache.draw(gl, params) {
+            if params.coloring == Coloring::Histogram {
+                self.update_histogram(gl, view, params);
+            }
+            self.paint(gl, view, margin, params);
+            self.cache.store(gl, *params);
+        }
+    }
+
+    /// The most detailed boundary near `params.center`, from the escape counts of a patch
+    /// around it, see [`plane::steepest`]. `None` without the escape count pass.
+    pub(super) fn find_detail(&self, gl: &glow::Context, params: &Params) -> Option<(f64, f64)> {
+        let program = self.count_program(params)?;
+        let patch = Params {
+            ratio: params.ratio * plane::DETAIL_ZOOM,
+            samples: 1,
+            ..*params
+        };
+        let n = plane::DETAIL_PATCH;
+        let counts = render_counts(gl, (n, n), |gl| {
+            let view = (n as f32, n as f32);
+            self.draw(gl, program, view, (0.5, 0.5), &patch, Coloring::Escape)
+        })?;
+        plane::steepest(&counts, params.center, params.ratio)
+    }
+
+    /// Render the escape counts of `params` in a viewport `view` pixels wide at a lower
+    /// resolution, and upload their cumulative distribution to `histogram`. The counts go
+    /// through a framebuffer of their own, the one bound before is bound back afterwards.
+    fn update_histogram(&mut self, gl: &glow::Context, view: (f32, f32), params: &Params) {
         use glow::HasContext as _;
-        let wh = view.0 / view.1;
-        let min = (center.0 - 1.5 / ratio * wh, center.1 - 1.5 / ratio);
-        let max = (center.0 + 1.5 / ratio * wh, center.1 + 1.5 / ratio);
+        self.histogram_size = 0;
+        let program = match self.count_program(params) {
+            Some(program) => program,
+            None => return,
+        };
+        let size = (
+            (view.0 / COUNT_SCALE).ceil().max(1.0) as u32,
+            (view.1 / COUNT_SCALE).ceil().max(1.0) as u32,
+        );
+        let count_view = (size.0 as f32, size.1 as f32);
+        let counts = match render_counts(gl, size, |gl| {
+            self.draw(gl, program, count_view, (0.5, 0.5), params, params.coloring)
+        }) {
+            Some(counts) => counts,
+            None => return,
+        };
+
+        // the points inside the set reach the last count and take no part in the palette
+        let width = params.iterations.max(1) as usize;
+        let mut bins = vec![0u32; width];
+        for count in counts {
+            if let Some(bin) = bins.get_mut(count as usize) {
+                *bin += 1;
+            }
+        }
+        let total = bins.iter().sum::<u32>().max(1) as f32;
+        let mut below = 0;
+        let shares: Vec<f32> = bins
+            .iter()
+            .map(|bin| {
+                below += bin;
+                below as f32 / total
+            })
+            .collect();
         unsafe {
-            gl.use_program(Some(self.program));
+            let bytes =
+                std::slice::from_raw_parts(shares.as_ptr() as *const u8, width * size_of::<f32>());
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.histogram));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::R32F as i32,
+                width as i32,
+                1,
+                0,
+                glow::RED,
+                glow::FLOAT,
+                Some(bytes),
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+        self.histogram_size = width as i32;
+    }
+
+    fn paint(&mut self, gl: &glow::Context, view: (f32, f32), margin: (f32, f32), params: &Params) {
+        if let Some(color) = params.background {
+            super::clear_viewport(gl, color);
+        }
+        let program = match (self.program_perturbation, self.program_f64) {
+            (Some(program), _) if self.perturbs(params) => program,
+            (_, Some(program)) if params.high_precision => program,
+            _ => self.program,
+        };
+        let coloring = if params.coloring == Coloring::Histogram && self.histogram_size == 0 {
+            Coloring::Escape
+        } else {
+            params.coloring
+        };
+        self.draw(gl, program, view, margin, params, coloring);
+    }
+
+    /// Whether `params` are drawn by the perturbation programs. A center escaping on the first
+    /// step leaves no reference to iterate along, the plain programs draw it.
+    fn perturbs(&self, params: &Params) -> bool {
+        params.perturbation
+            && params.m == 2
+            && self.program_perturbation.is_some()
+            && !escapes_at_once(params.center, params.escape_radius)
+    }
+
+    /// The escape count program for `params`, `None` if it failed to build
+    fn count_program(&self, params: &Params) -> Option<glow::Program> {
+        if self.perturbs(params) && self.program_count_perturbation.is_some() {
+            return self.program_count_perturbation;
+        }
+        match (self.program_count_f64, self.program_count) {
+            (Some(program), _) if params.high_precision => Some(program),
+            (_, program) => program,
+        }
+    }
+
+    /// Draw `params` with `program` in the current viewport, `view` pixels wide and starting
+    /// `margin` pixels from the origin of the window, colored by `coloring`.
+    fn draw(
+        &self,
+        gl: &glow::Context,
+        program: glow::Program,
+        view: (f32, f32),
+        margin: (f32, f32),
+        params: &Params,
+        coloring: Coloring,
+    ) {
+        use glow::HasContext as _;
+        let (min, max) = plane::bounds(view, params.center, params.ratio);
+        // a double is the sum of its nearest float and the rounding error
+        let split = |x: f64| (x as f32, (x - x as f32 as f64) as f32);
+        let (min_x, min_x_lo) = split(min.0);
+        let (min_y, min_y_lo) = split(min.1);
+        let (max_x, max_x_lo) = split(max.0);
+        let (max_y, max_y_lo) = split(max.1);
+        unsafe {
+            gl.use_program(Some(program));
             gl.bind_vertex_array(Some(self.vao));
             gl.uniform_2_f32(
-                gl.get_uniform_location(self.program, "viewport").as_ref(),
+                gl.get_uniform_location(program, "viewport").as_ref(),
                 view.0,
                 view.1,
             );
             gl.uniform_2_f32(
-                gl.get_uniform_location(self.program, "min").as_ref(),
-                min.0,
-                min.1,
+                gl.get_uniform_location(program, "min").as_ref(),
+                min_x,
+                min_y,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(program, "max").as_ref(),
+                max_x,
+                max_y,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(program, "min_lo").as_ref(),
+                min_x_lo,
+                min_y_lo,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(program, "max_lo").as_ref(),
+                max_x_lo,
+                max_y_lo,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(program, "margin").as_ref(),
+                margin.0,
+                margin.1,
             );
             gl.uniform_2_f32(
-                gl.get_uniform_location(self.program, "max").as_ref(),
-                max.0,
-                max.1,
+                gl.get_uniform_location(program, "span").as_ref(),
+                (max.0 - min.0) as f32,
+                (max.1 - min.1) as f32,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "samples").as_ref(),
+                params.samples,
+            );
+            gl.uniform_1_i32(gl.get_uniform_location(program, "m").as_ref(), params.m);
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "max_iter").as_ref(),
+                params.iterations,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(program, "limit").as_ref(),
+                params.escape_radius,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "coloring").as_ref(),
+                coloring as i32,
             );
             gl.uniform_1_f32(
-                gl.get_uniform_location(self.program, "margin").as_ref(),
-                margin,
+                gl.get_uniform_location(program, "outline_width").as_ref(),
+                params.outline_width,
             );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(program, "interior").as_ref(),
+                params.color_interior as i32,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(program, "color_offset").as_ref(),
+                params.color_offset,
+            );
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.histogram));
+            gl.uniform_1_i32(gl.get_uniform_location(program, "histogram").as_ref(), 0);
+            if self.perturbs(params) {
+                let mut orbit =
+                    reference_orbit(params.center, params.iterations, params.escape_radius);
+                let len = orbit.len();
+                let (width, rows) = orbit_size(len);
+                orbit.resize(width * rows, [0.0; 2]);
+                let bytes = std::slice::from_raw_parts(
+                    orbit.as_ptr() as *const u8,
+                    orbit.len() * size_of::<[f32; 2]>(),
+                );
+                gl.active_texture(glow::TEXTURE1);
+                gl.bind_texture(glow::TEXTURE_2D, Some(self.orbit));
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::RG32F as i32,
+                    width as i32,
+                    rows as i32,
+                    0,
+                    glow::RG,
+                    glow::FLOAT,
+                    Some(bytes),
+                );
+                gl.uniform_1_i32(gl.get_uniform_location(program, "orbit").as_ref(), 1);
+                gl.uniform_1_i32(
+                    gl.get_uniform_location(program, "orbit_len").as_ref(),
+                    len as i32,
+                );
+            }
             gl.draw_elements(glow::TRIANGLES, INDICES.len() as i32, glow::UNSIGNED_INT, 0);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+}
+
+/// Whether the orbit of 0 under z² + `c` escapes past `limit` on its first step, leaving
+/// [`reference_orbit`] with only Z(0) and Z(1).
+fn escapes_at_once(c: (f64, f64), limit: f32) -> bool {
+    c.0 * c.0 + c.1 * c.1 > limit as f64
+}
+
+/// Width and rows of the texture holding an orbit of `len` points, see [`ORBIT_WIDTH`]
+fn orbit_size(len: usize) -> (usize, usize) {
+    (len.min(ORBIT_WIDTH), (len + ORBIT_WIDTH - 1) / ORBIT_WIDTH)
+}
+
+/// Orbit of 0 under z² + `c` in double precision, the reference of the perturbation programs.
+/// It has `iterations + 2` points from Z(0) = 0, fewer if it escapes past `limit` first. The
+/// points are stored as floats, their rounding only adds a relative error to the offsets.
+fn reference_orbit(c: (f64, f64), iterations: i32, limit: f32) -> Vec<[f32; 2]> {
+    let mut orbit = vec![[0.0, 0.0]];
+    let mut z = (0.0f64, 0.0f64);
+    for _ in 0..=iterations.max(1) {
+        z = (z.0 * z.0 - z.1 * z.1 + c.0, 2.0 * z.0 * z.1 + c.1);
+        orbit.push([z.0 as f32, z.1 as f32]);
+        if z.0 * z.0 + z.1 * z.1 > limit as f64 {
+            break;
+        }
+    }
+    orbit
+}
+
+/// Render `draw` into a `size` unsigned integer target and read back the first channel of
+/// each pixel, in the order of OpenGL with rows from the bottom.
+///
+/// Like [`export::render_pixels`], the viewport, scissor state and framebuffer are restored
+/// before returning.
+fn render_counts(
+    gl: &glow::Context,
+    size: (u32, u32),
+    draw: impl FnOnce(&glow::Context),
+) -> Option<Vec<u32>> {
+    use glow::HasContext as _;
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    unsafe {
+        let mut viewport = [0; 4];
+        gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+        let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+        let blend = gl.is_enabled(glow::BLEND);
+        let framebuffer = export::framebuffer_binding(gl);
+
+        let texture = gl.create_texture().ok()?;
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::R32UI as i32,
+            width,
+            height,
+            0,
+            glow::RED_INTEGER,
+            glow::UNSIGNED_INT,
+            None,
+        );
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        let fbo = match gl.create_framebuffer() {
+            Ok(fbo) => fbo,
+            Err(err) => {
+                tracing::error!(%err, "Cannot create the escape count framebuffer");
+                gl.delete_texture(texture);
+                return None;
+            }
+        };
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(texture),
+            0,
+        );
+
+        let mut counts = None;
+        if gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE {
+            // blending is undefined on integer targets
+            gl.disable(glow::BLEND);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.viewport(0, 0, width, height);
+            draw(gl);
+
+            // RGBA_INTEGER is the one integer format WebGL is sure to read back
+            let mut buffer = vec![0u8; (size.0 * size.1 * 4) as usize * size_of::<u32>()];
+            gl.read_pixels(
+                0,
+                0,
+                width,
+                height,
+                glow::RGBA_INTEGER,
+                glow::UNSIGNED_INT,
+                glow::PixelPackData::Slice(&mut buffer),
+            );
+            counts = Some(
+                buffer
+                    .chunks_exact(4 * size_of::<u32>())
+                    .map(|pixel| u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]))
+                    .collect(),
+            );
+        } else {
+            tracing::error!(?size, "Escape count framebuffer is incomplete");
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+        gl.delete_framebuffer(fbo);
+        gl.delete_texture(texture);
+        gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        if scissor {
+            gl.enable(glow::SCISSOR_TEST);
+        }
+        if blend {
+            gl.enable(glow::BLEND);
+        }
+        counts
+    }
+}
+
+/// The escape time program, with `defines` prepended to the fragment shader.
+unsafe fn create_program(gl: &glow::Context, defines: &str) -> Result<glow::Program, String> {
+    super::create_program(
+        gl,
+        &[
+            (glow::VERTEX_SHADER, VERTEX_SHADER),
+            (
+                glow::FRAGMENT_SHADER,
+                &format!("{}{}", defines, FRAGMENT_SHADER),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_orbit_is_long_enough_to_perturb() {
+        // the shader reads Z(1) and Z(2) before it can restart from Z(0)
+        for c in [
+            (0.0, 0.0),
+            (-0.75, 0.1),
+            (0.3, 0.5),
+            (1.9, 0.0),
+            (2.1, 0.0),
+            (-3.0, 4.0),
+        ] {
+            let orbit = reference_orbit(c, 100, 4.0);
+            assert_eq!(orbit.len() < 3, escapes_at_once(c, 4.0), "c = {:?}", c);
+        }
+        assert_eq!(reference_orbit((0.0, 0.0), 2048, 4.0).len(), 2050);
+    }
+
+    #[test]
+    fn orbit_fits_the_texture() {
+        for len in [1, 3, 1023, 1024, 1025, 2050, 100_000] {
+            let (width, rows) = orbit_size(len);
+            assert!(width <= ORBIT_WIDTH && width * rows >= len, "len {}", len);
+            // the last row is needed
+            assert!(width * (rows - 1) < len, "len {}", len);
         }
     }
 }
@@ -0,0 +1,397 @@
+use super::{export, plane, View, ViewArgs};
+use eframe::egui::{self, *};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{mem::size_of, sync::Arc};
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NewtonShader {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    /// When the view was last brought back in range, for [`plane::clamp_warning`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clamped_at: Option<f64>,
+    center: (f64, f64),
+    ratio: f64,
+    /// Draw in the square in the middle of the canvas, whatever the shape of the window
+    aspect_lock: bool,
+}
+
+impl Default for NewtonShader {
+    fn default() -> Self {
+        Self {
+            gl: Default::default(),
+            error: None,
+            clamped_at: None,
+            center: (0.0, 0.0),
+            ratio: 1.0,
+            aspect_lock: false,
+        }
+    }
+}
+
+impl super::View for NewtonShader {
+    fn name(&self) -> &'static str {
+        "Newton Fractal (Shader)"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "Basins of the roots found by Newton's method, colored by the root each point reaches.\n\
+             z -> z - (z^3 - 1) / 3z^2",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "drag: pan\n\
+             scroll: zoom at the cursor\n\
+             keys, while the settings are open:\n\
+             - arrows or WASD: pan\n\
+             - Enter / PageDown: zoom in\n\
+             - Backspace / PageUp: zoom out\n\
+             - Escape or Home: reset the view",
+        )
+    }
+
+    fn status_text(&self) -> Option<String> {
+        Some(plane::status(self.center, self.ratio))
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        let context = self
+            .gl
+            .get_or_try_init(|| Context::new(&cc.gl).map(|context| Arc::new(Mutex::new(context))));
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        let context = Context::new(gl)?;
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        let view = (size.0 as f32, size.1 as f32);
+        export::render_offscreen(gl, size, |gl| {
+            context.paint(gl, view, self.center, self.ratio, (0.5, 0.5))
+        })
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+        // with the aspect locked, the fractal takes only the middle square of the canvas
+        let rect = if self.aspect_lock {
+            plane::square(rect)
+        } else {
+            rect
+        };
+        let painter = painter.with_clip_rect(rect);
+        // Reserve a slot below the settings popup, the callback is filled in after input handling
+        let canvas = painter.add(Shape::Noop);
+
+        let hover = ui
+            .input()
+            .pointer
+            .hover_pos()
+            .filter(|pos| rect.contains(*pos));
+        let coordinates =
+            hover.map(|pos| plane::format(plane::to_complex(rect, pos, self.center, self.ratio)));
+        let popup = super::settings_popup(ui, self, coordinates);
+
+        // The popup widgets are registered first, so they get the drag before the canvas does
+        let response = ui.interact(rect, ui.id().with("canvas"), Sense::drag());
+        let from_popup = ui
+            .input()
+            .pointer
+            .press_origin()
+            .map_or(false, |pos| popup.contains(pos));
+        if response.dragged() && !from_popup {
+            plane::pan(rect, response.drag_delta(), &mut self.center, self.ratio);
+        }
+        if let Some(pos) = response.hover_pos() {
+            let scroll = ui.input().scroll_delta.y;
+            if scroll != 0.0 && !popup.contains(pos) {
+                let factor = 1.1f64.powf(scroll as f64 / 50.0);
+                plane::zoom_at(rect, pos, &mut self.center, &mut self.ratio, factor);
+            }
+        }
+
+        let clamped = plane::sanitize(&mut self.center, &mut self.ratio);
+        plane::clamp_warning(
+            &painter,
+            rect,
+            clamped,
+            &mut self.clamped_at,
+            ui.input().time,
+        );
+
+        let gl = self.gl.clone();
+        let ppp = ui.ctx().pixels_per_point();
+        let (width, height) = (rect.width() * ppp, rect.height() * ppp);
+        let margin = plane::margin(rect, ui.ctx().input().screen_rect(), ppp);
+        let center = self.center;
+        let ratio = self.ratio;
+
+        let callback = egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut gl = gl.get().unwrap().lock();
+                    super::revive(&mut *gl, painter.gl(), Context::new);
+                    gl.paint(painter.gl(), (width, height), center, ratio, margin);
+                } else {
+                    eprintln!("Can't do custom painting because we are not using a glow context");
+                }
+            }),
+        };
+        painter.set(canvas, callback);
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        // keys typed into a field are not shortcuts
+        let shortcuts = super::shortcuts_enabled(ui);
+        if shortcuts {
+            if ui.input().key_pressed(Key::ArrowLeft) || ui.input().key_pressed(Key::A) {
+                self.center.0 -= 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::ArrowRight) || ui.input().key_pressed(Key::D) {
+                self.center.0 += 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::ArrowDown) || ui.input().key_pressed(Key::S) {
+                self.center.1 -= 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::ArrowUp) || ui.input().key_pressed(Key::W) {
+                self.center.1 += 0.1 / self.ratio;
+            }
+            if ui.input().key_pressed(Key::Enter) || ui.input().key_pressed(Key::PageDown) {
+                self.ratio *= 1.2;
+            }
+            if ui.input().key_pressed(Key::Backspace) || ui.input().key_pressed(Key::PageUp) {
+                self.ratio /= 1.2;
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("center :");
+            ui.label("x:");
+            ui.add(DragValue::new(&mut self.center.0).speed(0.01));
+            ui.label("y:");
+            ui.add(DragValue::new(&mut self.center.1).speed(0.01));
+        });
+        plane::zoom_ui(ui, &mut self.ratio);
+        ui.checkbox(&mut self.aspect_lock, "aspect lock")
+            .on_hover_text("draw in a square in the middle of the canvas");
+        if ui.button("reset").clicked() || (shortcuts && ui.input().key_pressed(Key::Escape)) {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.center = (0.0, 0.0);
+        self.ratio = 1.0;
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        self.center = args.center.unwrap_or(self.center);
+        self.ratio = args.zoom.unwrap_or(self.ratio);
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            center: Some(self.center),
+            zoom: Some(self.ratio),
+            ..Default::default()
+        }
+    }
+}
+
+impl NewtonShader {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+}
+
+#[derive(Debug)]
+struct Context {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    _vbo: glow::Buffer,
+    _ebo: glow::Buffer,
+}
+
+const VERTICES: &[f32] = &[-1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0];
+const INDICES: &[i32] = &[0, 1, 2, 1, 2, 3];
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+void main() {
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+// the escape loop needs more than mediump, which is all some mobile GPUs give by default
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+uniform vec2 viewport;
+uniform vec2 min;
+uniform vec2 max;
+uniform vec2 margin;
+out vec4 out_color;
+const float MAX = 32.0;
+const float EPSILON = 0.001;
+
+// roots of z^3 - 1 and the color of their basins
+const vec2 ROOTS[3] = vec2[3](vec2(1.0, 0.0), vec2(-0.5, 0.8660254), vec2(-0.5, -0.8660254));
+const vec3 COLORS[3] = vec3[3](vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0));
+
+vec2 cmul(vec2 a, vec2 b) {
+    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+vec2 cdiv(vec2 a, vec2 b) {
+    return vec2(a.x * b.x + a.y * b.y, a.y * b.x - a.x * b.y) / dot(b, b);
+}
+
+void main() {
+    vec2 z = mix(min, max, (gl_FragCoord.xy - margin) / viewport);
+    for (float count = 0.0; count < MAX; count += 1.0) {
+        // z = z - (z^3 - 1) / (3z^2)
+        vec2 z2 = cmul(z, z);
+        z -= cdiv(cmul(z2, z) - vec2(1.0, 0.0), 3.0 * z2);
+        for (int i = 0; i < 3; i++) {
+            if (distance(z, ROOTS[i]) < EPSILON) {
+                out_color = vec4(COLORS[i] * (1.0 - count / MAX), 1.0);
+                return;
+            }
+        }
+    }
+    out_color = vec4(0.0, 0.0, 0.0, 0.0);
+}
+"#;
+
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+}
+
+impl Context {
+    fn new(gl: &glow::Context) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let verts_slice = std::slice::from_raw_parts(
+                VERTICES.as_ptr() as *const u8,
+                VERTICES.len() * size_of::<f32>(),
+            );
+
+            let indices_slice = std::slice::from_raw_parts(
+                INDICES.as_ptr() as *const u8,
+                INDICES.len() * size_of::<i32>(),
+            );
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
+
+            let ebo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.buffer_data_u8_slice(
+                glow::ELEMENT_ARRAY_BUFFER,
+                indices_slice,
+                glow::DYNAMIC_DRAW,
+            );
+
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+
+            Ok(Self {
+                program,
+                vao,
+                _vbo: vbo,
+                _ebo: ebo,
+            })
+        }
+    }
+
+    fn paint(
+        &mut self,
+        gl: &glow::Context,
+        view: (f32, f32),
+        center: (f64, f64),
+        ratio: f64,
+        margin: (f32, f32),
+    ) {
+        use glow::HasContext as _;
+        let (min, max) = plane::bounds(view, center, ratio);
+        let (min, max) = ((min.0 as f32, min.1 as f32), (max.0 as f32, max.1 as f32));
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "viewport").as_ref(),
+                view.0,
+                view.1,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "min").as_ref(),
+                min.0,
+                min.1,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "max").as_ref(),
+                max.0,
+                max.1,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "margin").as_ref(),
+                margin.0,
+                margin.1,
+            );
+            gl.draw_elements(glow::TRIANGLES, INDICES.len() as i32, glow::UNSIGNED_INT, 0);
+        }
+    }
+}
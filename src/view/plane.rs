@@ -0,0 +1,543 @@
+//! Mapping between the canvas and the complex plane, shared by the escape time shaders
+//!
+//! Positions in the complex plane are `f64` so the double precision shader can zoom past the
+//! limits of `f32`, shaders working in single precision cast the result.
+
+use eframe::egui::{
+    pos2, vec2, Align2, Color32, FontId, InputState, Painter, Pos2, Rect, Shape, Slider, Stroke,
+    Ui, Vec2,
+};
+
+/// Corners `(min, max)` of the complex plane shown in a viewport of the given size.
+///
+/// The shorter side always spans `3.0 / ratio` around `center`, so a tall canvas shows as
+/// much of the real axis as a wide one shows of the imaginary axis.
+pub fn bounds(view: (f32, f32), center: (f64, f64), ratio: f64) -> ((f64, f64), (f64, f64)) {
+    let wh = view.0 as f64 / view.1 as f64;
+    let half = if wh >= 1.0 {
+        (1.5 / ratio * wh, 1.5 / ratio)
+    } else {
+        (1.5 / ratio, 1.5 / ratio / wh)
+    };
+    let min = (center.0 - half.0, center.1 - half.1);
+    let max = (center.0 + half.0, center.1 + half.1);
+    (min, max)
+}
+
+/// Complex coordinate of the screen position `pos` inside the canvas `rect`.
+pub fn to_complex(rect: Rect, pos: Pos2, center: (f64, f64), ratio: f64) -> (f64, f64) {
+    let (min, max) = bounds((rect.width(), rect.height()), center, ratio);
+    let tx = ((pos.x - rect.left()) / rect.width()) as f64;
+    // screen y grows downward while the imaginary axis grows upward
+    let ty = ((rect.bottom() - pos.y) / rect.height()) as f64;
+    (min.0 + (max.0 - min.0) * tx, min.1 + (max.1 - min.1) * ty)
+}
+
+/// Screen position of the complex coordinate `z`, the inverse of [`to_complex`].
+pub fn to_screen(rect: Rect, z: (f64, f64), center: (f64, f64), ratio: f64) -> Pos2 {
+    let (min, max) = bounds((rect.width(), rect.height()), center, ratio);
+    let tx = ((z.0 - min.0) / (max.0 - min.0)) as f32;
+    let ty = ((z.1 - min.1) / (max.1 - min.1)) as f32;
+    pos2(
+        rect.left() + rect.width() * tx,
+        rect.bottom() - rect.height() * ty,
+    )
+}
+
+/// Axes and gridlines over the canvas `rect`, with the tick values of the major lines.
+///
+/// Major lines are a power of ten apart, so about 1 to 10 fit the height, with a minor line
+/// halfway between them. Tick labels stick to the axes, or to the border when they are
+/// out of view.
+pub fn grid(painter: &Painter, rect: Rect, center: (f64, f64), ratio: f64) -> Vec<Shape> {
+    let (min, max) = bounds((rect.width(), rect.height()), center, ratio);
+    let step = 10f64.powf((max.1 - min.1).log10().floor());
+    let half = step / 2.0;
+    let digits = (-step.log10()).max(0.0) as usize;
+    let axis = Stroke::new(1.5, Color32::from_white_alpha(160));
+    let major = Stroke::new(1.0, Color32::from_white_alpha(64));
+    let minor = Stroke::new(1.0, Color32::from_white_alpha(24));
+    let font = FontId::monospace(12.0);
+    let label = |text: String, pos: Pos2, anchor: Align2| {
+        let galley = painter.layout_no_wrap(text, font.clone(), Color32::from_white_alpha(200));
+        Shape::galley(
+            anchor
+                .anchor_rect(Rect::from_min_size(pos, galley.size()))
+                .min,
+            galley,
+        )
+    };
+    let origin = to_screen(rect, (0.0, 0.0), center, ratio);
+    // not clamp, which panics when the canvas is smaller than a label
+    let label_x = origin.x.min(rect.right() - 60.0).max(rect.left());
+    let label_y = origin.y.min(rect.bottom() - 16.0).max(rect.top());
+
+    let mut shapes = Vec::new();
+    let mut i = (min.0 / half).ceil() as i64;
+    while i as f64 * half <= max.0 {
+        let x = i as f64 * half;
+        let sx = to_screen(rect, (x, 0.0), center, ratio).x;
+        let stroke = match i {
+            0 => axis,
+            _ if i % 2 == 0 => major,
+            _ => minor,
+        };
+        shapes.push(Shape::line_segment(
+            [pos2(sx, rect.top()), pos2(sx, rect.bottom())],
+            stroke,
+        ));
+        if i % 2 == 0 {
+            let text = format!("{:.*}", digits, x);
+            shapes.push(label(text, pos2(sx + 2.0, label_y), Align2::LEFT_TOP));
+        }
+        i += 1;
+    }
+    let mut i = (min.1 / half).ceil() as i64;
+    while i as f64 * half <= max.1 {
+        let y = i as f64 * half;
+        let sy = to_screen(rect, (0.0, y), center, ratio).y;
+        let stroke = match i {
+            0 => axis,
+            _ if i % 2 == 0 => major,
+            _ => minor,
+        };
+        shapes.push(Shape::line_segment(
+            [pos2(rect.left(), sy), pos2(rect.right(), sy)],
+            stroke,
+        ));
+        if i % 2 == 0 && i != 0 {
+            let text = format!("{:.*}i", digits, y);
+            shapes.push(label(text, pos2(label_x + 2.0, sy), Align2::LEFT_BOTTOM));
+        }
+        i += 1;
+    }
+    shapes
+}
+
+/// Format a complex number as `a + bi`.
+pub fn format(z: (f64, f64)) -> String {
+    let sign = if z.1.is_sign_negative() { '-' } else { '+' };
+    format!("{:.6} {} {:.6}i", z.0, sign, z.1.abs())
+}
+
+/// Center and magnification of a view, for the status bar.
+pub fn status(center: (f64, f64), ratio: f64) -> String {
+    format!(
+        "center: {}   zoom: {}",
+        format(center),
+        format_magnification(ratio)
+    )
+}
+
+/// Zoom by `factor` while keeping the complex coordinate under `pos` fixed.
+pub fn zoom_at(rect: Rect, pos: Pos2, center: &mut (f64, f64), ratio: &mut f64, factor: f64) {
+    let p = to_complex(rect, pos, *center, *ratio);
+    *ratio *= factor;
+    center.0 = p.0 - (p.0 - center.0) / factor;
+    center.1 = p.1 - (p.1 - center.1) / factor;
+}
+
+/// Move the plane by a drag of `delta` points, keeping the point under the pointer beneath it
+/// whatever the shape of `rect`.
+pub fn pan(rect: Rect, delta: Vec2, center: &mut (f64, f64), ratio: f64) {
+    let to = rect.center();
+    let p = to_complex(rect, to - delta, *center, ratio);
+    let q = to_complex(rect, to, *center, ratio);
+    center.0 += p.0 - q.0;
+    center.1 += p.1 - q.1;
+}
+
+/// Pan and zoom with two or more fingers on a touch screen, keeping the point of the plane
+/// under the fingers beneath them. `centroid` is the position of the fingers on the last frame,
+/// `None` when no pinch is running. Returns whether a pinch is running, the single pointer
+/// drag and click should be ignored then. A mouse never starts a pinch.
+pub fn pinch(
+    input: &InputState,
+    rect: Rect,
+    centroid: &mut Option<Pos2>,
+    center: &mut (f64, f64),
+    ratio: &mut f64,
+) -> bool {
+    let touch = match input.multi_touch() {
+        Some(touch) => touch,
+        None => {
+            *centroid = None;
+            return false;
+        }
+    };
+    let from = centroid.unwrap_or(touch.start_pos);
+    let to = from + touch.translation_delta;
+    *centroid = Some(to);
+    if rect.contains(touch.start_pos) {
+        let p = to_complex(rect, from, *center, *ratio);
+        let q = to_complex(rect, to, *center, *ratio);
+        center.0 += p.0 - q.0;
+        center.1 += p.1 - q.1;
+        zoom_at(rect, to, center, ratio, touch.zoom_delta as f64);
+    }
+    true
+}
+
+/// Deepest zoom offered by [`zoom_ui`], where double precision runs out
+const MAX_RATIO: f64 = 1e15;
+/// Widest zoom, the escape time sets are a speck long before
+const MIN_RATIO: f64 = 1e-3;
+/// Farthest center from the origin, every set fits within a few units of it
+const MAX_CENTER: f64 = 1e3;
+
+/// Bring `center` and `ratio` back to where [`bounds`] is finite and precise: a center at most
+/// [`MAX_CENTER`] from the origin on each axis and a zoom between [`MIN_RATIO`] and
+/// [`MAX_RATIO`], the latter shrunk by the distance of the center beyond 1 as doubles get
+/// coarser there. NaN is replaced by the default view. Returns whether anything changed.
+pub fn sanitize(center: &mut (f64, f64), ratio: &mut f64) -> bool {
+    let valid_center = if center.0.is_nan() || center.1.is_nan() {
+        (0.0, 0.0)
+    } else {
+        (
+            center.0.clamp(-MAX_CENTER, MAX_CENTER),
+            center.1.clamp(-MAX_CENTER, MAX_CENTER),
+        )
+    };
+    let valid_ratio = if ratio.is_nan() {
+        1.0
+    } else {
+        let far = valid_center.0.abs().max(valid_center.1.abs()).max(1.0);
+        ratio.clamp(MIN_RATIO, MAX_RATIO / far)
+    };
+    let changed = valid_ratio != *ratio || valid_center != *center;
+    *ratio = valid_ratio;
+    *center = valid_center;
+    changed
+}
+
+/// How long [`clamp_warning`] stays on the canvas, in seconds
+const WARNING_DURATION: f64 = 2.0;
+
+/// Tell the user for a moment that the view was brought back in range. `clamped` is whether
+/// that happened this frame and `since` when it last did.
+pub fn clamp_warning(
+    painter: &Painter,
+    rect: Rect,
+    clamped: bool,
+    since: &mut Option<f64>,
+    now: f64,
+) {
+    if clamped {
+        *since = Some(now);
+    }
+    match *since {
+        Some(time) if now - time < WARNING_DURATION => {
+            painter.text(
+                rect.center_bottom() - vec2(0.0, 8.0),
+                Align2::CENTER_BOTTOM,
+                "The view went out of range and was moved back",
+                FontId::proportional(14.0),
+                Color32::YELLOW,
+            );
+            painter.ctx().request_repaint();
+        }
+        _ => *since = None,
+    }
+}
+
+/// Two points picked on the canvas to read the distance between them. They are kept as complex
+/// coordinates, so they stay on the same spot of the set while the view moves.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ruler {
+    from: Option<(f64, f64)>,
+    to: Option<(f64, f64)>,
+}
+
+impl Ruler {
+    /// The first two clicks set the ends, the third one clears them.
+    pub fn click(&mut self, z: (f64, f64)) {
+        *self = match (self.from, self.to) {
+            (None, _) => Self {
+                from: Some(z),
+                to: None,
+            },
+            (Some(from), None) => Self {
+                from: Some(from),
+                to: Some(z),
+            },
+            (Some(_), Some(_)) => Self::default(),
+        };
+    }
+
+    /// The ends picked so far with the line between them, labelled with its length in the
+    /// complex plane and the size of a point at the current zoom.
+    pub fn shapes(
+        &self,
+        painter: &Painter,
+        rect: Rect,
+        center: (f64, f64),
+        ratio: f64,
+    ) -> Vec<Shape> {
+        let stroke = Stroke::new(1.5, Color32::YELLOW);
+        let mut shapes = Vec::new();
+        let ends: Vec<Pos2> = [self.from, self.to]
+            .iter()
+            .flatten()
+            .map(|z| to_screen(rect, *z, center, ratio))
+            .collect();
+        for end in &ends {
+            shapes.push(Shape::circle_stroke(*end, 4.0, stroke));
+        }
+        let (min, max) = bounds((rect.width(), rect.height()), center, ratio);
+        let scale = (max.1 - min.1) / rect.height() as f64;
+        let text = match (self.from, self.to) {
+            (Some(from), Some(to)) => {
+                shapes.push(Shape::line_segment([ends[0], ends[1]], stroke));
+                let distance = (to.0 - from.0).hypot(to.1 - from.1);
+                format!("distance: {:.6e}\nscale: {:.3e} per point", distance, scale)
+            }
+            (Some(_), None) => format!("click the other end\nscale: {:.3e} per point", scale),
+            _ => format!("click the first end\nscale: {:.3e} per point", scale),
+        };
+        let galley = painter.layout_no_wrap(text, FontId::monospace(12.0), Color32::YELLOW);
+        // next to the last end, or in the corner before any click
+        let anchor = ends.last().copied().unwrap_or(rect.left_top()) + vec2(8.0, 8.0);
+        shapes.push(Shape::rect_filled(
+            Rect::from_min_size(anchor, galley.size()).expand(2.0),
+            2.0,
+            Color32::from_black_alpha(160),
+        ));
+        shapes.push(Shape::galley(anchor, galley));
+        shapes
+    }
+}
+
+/// Format a zoom ratio as a magnification, e.g. `1.0e3×`.
+pub fn format_magnification(ratio: f64) -> String {
+    format!("{:.1e}×", ratio)
+}
+
+/// Parse a magnification typed by the user, with or without the trailing `×` or `x`.
+pub fn parse_magnification(text: &str) -> Option<f64> {
+    let text = text.trim().trim_end_matches(|c| c == '×' || c == 'x');
+    let ratio: f64 = text.trim().parse().ok()?;
+    (ratio.is_finite() && ratio > 0.0).then(|| ratio.clamp(1.0, MAX_RATIO))
+}
+
+/// Logarithmic zoom slider, plus a text field to jump to a magnification by pressing enter.
+pub fn zoom_ui(ui: &mut Ui, ratio: &mut f64) {
+    ui.add(
+        Slider::new(ratio, 1.0..=MAX_RATIO)
+            .logarithmic(true)
+            .text("zoom"),
+    );
+    ui.horizontal(|ui| {
+        ui.label("magnification :");
+        super::text_value_ui(
+            ui,
+            "magnification",
+            ratio,
+            |ratio| format_magnification(*ratio),
+            parse_magnification,
+        );
+    });
+}
+
+/// The largest square in the middle of `rect`, the canvas of a view with its aspect locked.
+pub fn square(rect: Rect) -> Rect {
+    Rect::from_center_size(rect.center(), Vec2::splat(rect.width().min(rect.height())))
+}
+
+/// Offset in physical pixels of the canvas `rect` from the bottom left corner of the window,
+/// matching the origin of `gl_FragCoord`.
+pub fn margin(rect: Rect, screen: Rect, pixels_per_point: f32) -> (f32, f32) {
+    (
+        rect.left() * pixels_per_point + 0.5,
+        (screen.bottom() - rect.bottom()) * pixels_per_point + 0.5,
+    )
+}
+
+/// Side in pixels of the patch of escape counts searched by [`steepest`]
+pub const DETAIL_PATCH: u32 = 64;
+/// Zoom of the patch of [`steepest`] over the view, so it spans a quarter of its shorter side
+pub const DETAIL_ZOOM: f64 = 4.0;
+
+/// The point of a patch where the escape count changes the most from one pixel to the next,
+/// the most detailed boundary near `center`. `counts` are the [`DETAIL_PATCH`]² escape counts
+/// rendered around `center` at `ratio * DETAIL_ZOOM`, rows from the bottom like OpenGL. `None`
+/// if the count is the same everywhere. Of equally steep pixels, the closest to `center` wins.
+pub fn steepest(counts: &[u32], center: (f64, f64), ratio: f64) -> Option<(f64, f64)> {
+    let n = DETAIL_PATCH as usize;
+    if counts.len() != n * n {
+        return None;
+    }
+    let at = |x: usize, y: usize| counts[y * n + x] as i64;
+    let mut best = None;
+    let mut best_score = (0, 0);
+    for y in 1..n - 1 {
+        for x in 1..n - 1 {
+            let gradient =
+                (at(x + 1, y) - at(x - 1, y)).abs() + (at(x, y + 1) - at(x, y - 1)).abs();
+            let offset =
+                (2 * x as i64 + 1 - n as i64).pow(2) + (2 * y as i64 + 1 - n as i64).pow(2);
+            // steeper first, then closer to the center
+            let score = (gradient, -offset);
+            if gradient > 0 && (best.is_none() || score > best_score) {
+                best = Some((x, y));
+                best_score = score;
+            }
+        }
+    }
+    let (x, y) = best?;
+    let (min, max) = bounds((n as f32, n as f32), center, ratio * DETAIL_ZOOM);
+    let t = ((x as f64 + 0.5) / n as f64, (y as f64 + 0.5) / n as f64);
+    Some((min.0 + (max.0 - min.0) * t.0, min.1 + (max.1 - min.1) * t.1))
+}
+
+/// Animated move of the view to another center and zoom, instead of a hard cut
+#[derive(Debug, Clone, Copy)]
+pub struct Tween {
+    from: ((f64, f64), f64),
+    to: ((f64, f64), f64),
+    /// `InputState::time` when the move started
+    start: f64,
+}
+
+impl Tween {
+    const DURATION: f64 = 0.5;
+
+    pub fn new(from: ((f64, f64), f64), to: ((f64, f64), f64), now: f64) -> Self {
+        Self {
+            from,
+            to,
+            start: now,
+        }
+    }
+}
+
+/// Move `center` and `ratio` along the running tween, dropping it once it arrived.
+pub fn fly(tween: &mut Option<Tween>, now: f64, center: &mut (f64, f64), ratio: &mut f64) {
+    let t = match tween {
+        Some(t) => t,
+        None => return,
+    };
+    let x = ((now - t.start) / Tween::DURATION).clamp(0.0, 1.0);
+    // smoothstep, so the move eases in and out
+    let s = x * x * (3.0 - 2.0 * x);
+    let (c, r) = interpolate(t.from, t.to, s);
+    *center = c;
+    *ratio = r;
+    if x >= 1.0 {
+        *tween = None;
+    }
+}
+
+/// The place a fraction `s` of the way from `from` to `to`, both as `(center, ratio)`. The
+/// zoom is interpolated on a log scale, so a linear `s` zooms at a constant speed.
+pub fn interpolate(from: ((f64, f64), f64), to: ((f64, f64), f64), s: f64) -> ((f64, f64), f64) {
+    let ((from_center, from_ratio), (to_center, to_ratio)) = (from, to);
+    let ratio = from_ratio * (to_ratio / from_ratio).powf(s);
+    // the center moves in step with the width of the view, otherwise a deep zoom drifts
+    // away from its target before the magnification catches up
+    let w = if (to_ratio / from_ratio - 1.0).abs() < 1e-9 {
+        s
+    } else {
+        (1.0 / ratio - 1.0 / from_ratio) / (1.0 / to_ratio - 1.0 / from_ratio)
+    };
+    let center = (
+        from_center.0 + (to_center.0 - from_center.0) * w,
+        from_center.1 + (to_center.1 - from_center.1) * w,
+    );
+    (center, ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTREMES: [f64; 9] = [
+        f64::NAN,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        0.0,
+        -0.0,
+        1e-300,
+        1e300,
+        -1e300,
+        1.0,
+    ];
+
+    #[test]
+    fn sanitized_bounds_are_finite_and_ordered() {
+        for &x in &EXTREMES {
+            for &y in &EXTREMES {
+                for &r in &EXTREMES {
+                    let (mut center, mut ratio) = ((x, y), r);
+                    sanitize(&mut center, &mut ratio);
+                    for view in [(1920.0, 1080.0), (1080.0, 1920.0), (1.0, 1.0)] {
+                        let (min, max) = bounds(view, center, ratio);
+                        let input = (x, y, r, view);
+                        assert!(min.0.is_finite() && min.1.is_finite(), "{:?}", input);
+                        assert!(max.0.is_finite() && max.1.is_finite(), "{:?}", input);
+                        assert!(min.0 < max.0 && min.1 < max.1, "{:?}", input);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sanitize_keeps_a_valid_view() {
+        let (mut center, mut ratio) = ((-0.75, 0.1), 2.0);
+        assert!(!sanitize(&mut center, &mut ratio));
+        assert_eq!((center, ratio), ((-0.75, 0.1), 2.0));
+    }
+
+    /// Escape counts of the detail patch, from the pixel coordinates
+    fn patch(count: impl Fn(usize, usize) -> u32) -> Vec<u32> {
+        let n = DETAIL_PATCH as usize;
+        (0..n * n).map(|i| count(i % n, i / n)).collect()
+    }
+
+    #[test]
+    fn steepest_needs_an_edge() {
+        assert_eq!(steepest(&[], (0.0, 0.0), 1.0), None);
+        assert_eq!(steepest(&patch(|_, _| 7), (0.0, 0.0), 1.0), None);
+    }
+
+    #[test]
+    fn steepest_finds_the_strongest_edge_nearest_the_center() {
+        let (center, ratio) = ((-0.75, 0.1), 2.0);
+        // a pixel of the patch in the plane
+        let pixel = 3.0 / (ratio * DETAIL_ZOOM) / DETAIL_PATCH as f64;
+        // a weak edge through the middle and a strong one to the right
+        let counts = patch(|x, _| match x {
+            0..=31 => 10,
+            32..=47 => 11,
+            _ => 60,
+        });
+        let (x, y) = steepest(&counts, center, ratio).unwrap();
+        // the pixel just left of the strong edge, on one of the two middle rows
+        assert!((x - (center.0 + 15.5 * pixel)).abs() < 1e-9, "{}", x);
+        assert!((y - center.1).abs() < pixel, "{}", y);
+
+        // of equally steep pixels, the closest to the center
+        let counts = patch(|x, _| if x < 40 { 10 } else { 50 });
+        let (x, _) = steepest(&counts, center, ratio).unwrap();
+        assert!((x - (center.0 + 7.5 * pixel)).abs() < 1e-9, "{}", x);
+    }
+
+    #[test]
+    fn square_is_centered_in_wide_and_tall_canvases() {
+        for rect in [
+            Rect::from_min_size(pos2(10.0, 20.0), vec2(1600.0, 900.0)),
+            Rect::from_min_size(pos2(10.0, 20.0), vec2(900.0, 1600.0)),
+            Rect::from_min_size(pos2(0.0, 0.0), vec2(500.0, 500.0)),
+        ] {
+            let inner = square(rect);
+            assert_eq!(inner.width(), inner.height(), "{:?}", rect);
+            assert_eq!(inner.width(), rect.width().min(rect.height()), "{:?}", rect);
+            assert_eq!(inner.center(), rect.center(), "{:?}", rect);
+            assert!(
+                rect.contains(inner.min) && rect.contains(inner.max),
+                "{:?}",
+                rect
+            );
+        }
+    }
+}
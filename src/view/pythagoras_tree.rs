@@ -0,0 +1,466 @@
+use super::{export, levels::Levels, View, ViewArgs};
+use eframe::{
+    egui::{self, containers::*, *},
+    emath::{pos2, Pos2},
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{
+    mem::{size_of, swap},
+    sync::Arc,
+};
+
+const DEFAULT_DEPTH: u32 = 8;
+const DEFAULT_ANGLE: f32 = 45.0;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PythagorasTree {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    depth: u32,
+    /// Deepest depth within the vertex budget
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_depth: u32,
+    /// Angle between the top of a square and the base of its left child, in degrees
+    angle: f32,
+}
+
+impl Default for PythagorasTree {
+    fn default() -> Self {
+        Self {
+            gl: Default::default(),
+            error: None,
+            depth: DEFAULT_DEPTH,
+            max_depth: super::max_depth(0, vertex_count),
+            angle: DEFAULT_ANGLE,
+        }
+    }
+}
+
+impl super::View for PythagorasTree {
+    fn name(&self) -> &'static str {
+        "Pythagoras Tree"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "Squares standing on the legs of a right triangle built on the square below.\n\
+             side of the children = side × cos(angle) and side × sin(angle)",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "+ / - buttons: change the depth\n\
+             angle: tilt of the left branch, 45° is the symmetric tree\n\
+             Home: reset the settings",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        self.depth = self.depth.min(self.max_depth);
+        let (max_depth, angle) = (self.max_depth, self.angle);
+        let context = self.gl.get_or_try_init(|| {
+            Context::new(&cc.gl, max_depth, angle).map(|context| Arc::new(Mutex::new(context)))
+        });
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        self.depth = self.depth.min(self.max_depth);
+        let mut context = Context::new(gl, self.max_depth, self.angle)?;
+        context.levels.wait(self.depth as usize);
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, self.depth, self.angle, size)
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        // only the depths computed so far, a deeper one would draw the deepest ready instead
+        let gl = self.gl.get()?.lock();
+        // levels of another angle are rebuilt on the next paint
+        if gl.angle != self.angle {
+            return None;
+        }
+        let ready = gl.levels.ready() as u32;
+        Some(("depth", (0..ready.min(self.max_depth + 1)).collect()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, value, self.angle, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+
+        super::settings_popup(ui, self, None);
+
+        let gl = self.gl.clone();
+        let max_depth = self.max_depth;
+        let depth = self.depth;
+        let angle = self.angle;
+        let ratio = rect.height() / rect.width();
+        if self.computing().is_some() {
+            // keep painting until the requested depth is ready
+            ui.ctx().request_repaint();
+        }
+
+        let callback = egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut gl = gl.get().unwrap().lock();
+                    super::revive(&mut *gl, painter.gl(), |gl| {
+                        Context::new(gl, max_depth, angle)
+                    });
+                    gl.paint(painter.gl(), depth, angle, ratio);
+                } else {
+                    eprintln!("Can't do custom painting because we are not using a glow context");
+                }
+            }),
+        };
+        painter.add(callback);
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        ui.label(format!(
+            "Painted square count: {}",
+            2usize.pow(self.depth + 1) - 1
+        ));
+        if let Some(depth) = self.computing() {
+            ui.label(format!("computing depth {}...", depth));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Depth :");
+            ui.add(
+                DragValue::new(&mut self.depth)
+                    .speed(1.0)
+                    .clamp_range(0..=self.max_depth),
+            );
+            if ui.button("+").clicked() && self.depth < self.max_depth {
+                self.depth += 1;
+            }
+            if ui.button("-").clicked() && self.depth > 0 {
+                self.depth -= 1;
+            }
+        });
+        ui.add(Slider::new(&mut self.angle, 10.0..=80.0).text("angle (°)"));
+        if ui.button("reset").clicked() {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.depth = DEFAULT_DEPTH;
+        self.angle = DEFAULT_ANGLE;
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        if let Some(depth) = args.depth {
+            self.depth = depth.min(self.max_depth);
+        }
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            depth: Some(self.depth),
+            ..Default::default()
+        }
+    }
+}
+
+impl PythagorasTree {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+    /// Depth being computed in the background, if the requested one is not ready yet.
+    fn computing(&self) -> Option<u32> {
+        let gl = self.gl.get()?.lock();
+        if gl.angle != self.angle {
+            // the levels are rebuilt on the next paint
+            return Some(0);
+        }
+        let ready = gl.levels.ready() as u32;
+        (ready <= self.depth).then(|| ready)
+    }
+}
+
+/// Vertices drawn at `depth`, two triangles for each square of this depth and above.
+fn vertex_count(depth: u32) -> usize {
+    6 * (2usize.pow(depth + 1) - 1)
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Vertex {
+    pos: Pos2,
+    /// Depth of the square, for the color
+    depth: f32,
+}
+
+/// The squares added at one depth, with the triangles of the whole tree up to it
+#[derive(Debug)]
+struct Level {
+    /// Corners counterclockwise from the left end of the base
+    squares: Vec<[Pos2; 4]>,
+    vertices: Vec<Vertex>,
+    bounds: Rect,
+}
+
+impl Level {
+    /// The trunk, a unit square standing on the x axis
+    fn first() -> Self {
+        let square = square(pos2(-0.5, 0.0), pos2(0.5, 0.0));
+        let mut level = Self {
+            squares: Vec::new(),
+            vertices: Vec::new(),
+            bounds: Rect::NOTHING,
+        };
+        level.push(square, 0.0);
+        level
+    }
+
+    fn push(&mut self, square: [Pos2; 4], depth: f32) {
+        for i in [0, 1, 2, 0, 2, 3] {
+            self.vertices.push(Vertex {
+                pos: square[i],
+                depth,
+            });
+        }
+        for p in square {
+            self.bounds.extend_with(p);
+        }
+        self.squares.push(square);
+    }
+}
+
+/// The square standing on the base from `a` to `b`, on its left side.
+fn square(a: Pos2, b: Pos2) -> [Pos2; 4] {
+    let d = b - a;
+    let up = vec2(-d.y, d.x);
+    [a, b, b + up, a + up]
+}
+
+/// Grow two squares on top of every square of `prev`, on the legs of the right triangle whose
+/// hypotenuse is the top side and whose left angle is `angle` radians.
+fn next_level(prev: &Level, angle: f32) -> Level {
+    let depth = prev.vertices.last().map_or(0.0, |v| v.depth) + 1.0;
+    let mut new = Level {
+        squares: Vec::with_capacity(prev.squares.len() * 2),
+        vertices: Vec::with_capacity(prev.vertices.len() + prev.squares.len() * 12),
+        bounds: prev.bounds,
+    };
+    new.vertices.extend_from_slice(&prev.vertices);
+    let (sin, cos) = angle.sin_cos();
+    for [_, _, right, left] in &prev.squares {
+        let top = *right - *left;
+        // the top side turned by `angle` and shortened to the left leg
+        let leg = vec2(top.x * cos - top.y * sin, top.x * sin + top.y * cos) * cos;
+        let apex = *left + leg;
+        new.push(square(*left, apex), depth);
+        new.push(square(apex, *right), depth);
+    }
+    tracing::debug!(len = new.vertices.len());
+    new
+}
+
+#[derive(Debug)]
+struct Context {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+    /// `levels[d]` is the tree of depth `d`, `levels[0]` is the trunk alone
+    levels: Levels<Level>,
+    max_depth: u32,
+    /// Angle in degrees the levels are built with
+    angle: f32,
+    /// Depth of the tree in the GPU buffer, `None` before the first upload
+    depth: Option<u32>,
+}
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+layout (location = 1) in float in_depth;
+uniform vec2 uni_scale;
+// center and scale that fit the tree in the clip space
+uniform vec3 uni_fit;
+uniform float uni_depth;
+out vec3 v_color;
+void main() {
+    gl_Position = vec4((in_pos - uni_fit.xy) * uni_fit.z, 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+    // from a brown trunk to green leaves
+    float t = in_depth / max(uni_depth, 1.0);
+    v_color = mix(vec3(0.55, 0.35, 0.2), vec3(0.35, 0.8, 0.3), t);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+in vec3 v_color;
+out vec4 out_color;
+void main() {
+    out_color = vec4(v_color, 1.0);
+}
+"#;
+
+/// Levels of the tree with `angle` in degrees, up to `max_depth`.
+fn build_levels(max_depth: u32, angle: f32) -> Levels<Level> {
+    let angle = angle.to_radians();
+    Levels::new(
+        Level::first(),
+        max_depth as usize + 1,
+        move |prev: &Level| next_level(prev, angle),
+    )
+}
+
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+
+    fn keep_from(&mut self, lost: Self) {
+        if lost.angle == self.angle {
+            self.levels = lost.levels;
+        }
+    }
+}
+
+impl Context {
+    fn new(gl: &glow::Context, max_depth: u32, angle: f32) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            Ok(Self {
+                program,
+                vao: gl.create_vertex_array().unwrap(),
+                vbo: gl.create_buffer().unwrap(),
+                levels: build_levels(max_depth, angle),
+                max_depth,
+                angle,
+                depth: None,
+            })
+        }
+    }
+
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, vertices: &[Vertex]) {
+        use glow::HasContext as _;
+
+        let mut vao = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao));
+
+        let verts_slice = std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            vertices.len() * size_of::<Vertex>(),
+        );
+
+        let mut vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
+
+        let stride = size_of::<Vertex>() as i32;
+        gl.enable_vertex_attrib_array(0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, stride, 0);
+        gl.enable_vertex_attrib_array(1);
+        gl.vertex_attrib_pointer_f32(1, 1, glow::FLOAT, false, stride, size_of::<Pos2>() as i32);
+        swap(&mut self.vao, &mut vao);
+        swap(&mut self.vbo, &mut vbo);
+        gl.delete_vertex_array(vao);
+        gl.delete_buffer(vbo);
+    }
+
+    fn export(
+        &mut self,
+        gl: &glow::Context,
+        depth: u32,
+        angle: f32,
+        size: (u32, u32),
+    ) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| self.paint(gl, depth, angle, ratio))
+    }
+
+    fn paint(&mut self, gl: &glow::Context, mut depth: u32, angle: f32, ratio: f32) {
+        use glow::HasContext as _;
+        if self.angle != angle {
+            self.levels = build_levels(self.max_depth, angle);
+            self.angle = angle;
+            self.depth = None;
+        }
+        self.levels.step();
+        // draw the deepest ready depth until the requested one is computed
+        depth = depth.min(self.levels.ready() as u32 - 1);
+        let level = self.levels.get(depth as usize).unwrap();
+        if self.depth != Some(depth) {
+            self.depth = Some(depth);
+            unsafe { self.update_vertices(gl, &level.vertices) };
+        }
+        let bounds = level.bounds;
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.bind_vertex_array(Some(self.vao));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            gl.uniform_3_f32(
+                gl.get_uniform_location(self.program, "uni_fit").as_ref(),
+                bounds.center().x,
+                bounds.center().y,
+                1.8 / bounds.width().max(bounds.height()),
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "uni_depth").as_ref(),
+                depth as f32,
+            );
+            gl.draw_arrays(glow::TRIANGLES, 0, level.vertices.len() as i32);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Seeded random numbers for the chaos game views, so a seed always draws the same points
+
+use eframe::egui::Ui;
+
+/// Seed of the chaos game views until the user picks another one
+pub const DEFAULT_SEED: u64 = 0x2545_f491;
+
+/// xorshift64 generator
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// The state is the seed mixed by splitmix64, so close seeds give unrelated sequences and
+    /// a seed of 0 does not leave xorshift stuck at 0.
+    pub fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Self(z.max(1))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Text field to type a seed, applied by pressing enter, and a button drawing a new one.
+pub fn seed_ui(ui: &mut Ui, seed: &mut u64) {
+    ui.horizontal(|ui| {
+        ui.label("seed :");
+        super::text_value_ui(ui, "seed", seed, u64::to_string, |text| {
+            text.trim().parse().ok()
+        });
+        if ui.button("new seed").clicked() {
+            // short enough to read out and type back
+            let time = ui.input().time.to_bits();
+            *seed = Rng::new(*seed ^ time).next_u32() as u64;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(seed: u64) -> Vec<u32> {
+        let mut rng = Rng::new(seed);
+        (0..64).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn a_seed_draws_the_same_numbers() {
+        for seed in [0, 1, DEFAULT_SEED, u64::MAX] {
+            assert_eq!(draws(seed), draws(seed), "seed {}", seed);
+        }
+        // close seeds are unrelated, and 0 doesn't get stuck
+        assert_ne!(draws(0), draws(1));
+        assert!(draws(0).iter().any(|&n| n != 0));
+    }
+
+    #[test]
+    fn floats_are_in_the_unit_interval() {
+        let mut rng = Rng::new(DEFAULT_SEED);
+        for _ in 0..10_000 {
+            let x = rng.next_f32();
+            assert!((0.0..1.0).contains(&x), "{}", x);
+        }
+    }
+}
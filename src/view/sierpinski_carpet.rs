@@ -0,0 +1,441 @@
+use super::{export, levels::Levels, View, ViewArgs};
+use eframe::{
+    egui::{self, containers::*, *},
+    emath::{pos2, Pos2},
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{
+    mem::{size_of, swap},
+    sync::Arc,
+};
+
+const DEFAULT_DEPTH: u32 = 3;
+/// Half the side of the initial square
+const HALF_SIDE: f32 = 0.8;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SierpinskiCarpet {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    depth: u32,
+    /// Deepest depth within the vertex budget
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_depth: u32,
+    /// Theme of the last frame, so an export uses the colors on screen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dark_mode: bool,
+}
+
+impl Default for SierpinskiCarpet {
+    fn default() -> Self {
+        Self {
+            gl: Default::default(),
+            error: None,
+            depth: DEFAULT_DEPTH,
+            max_depth: super::max_depth(0, vertex_count),
+            dark_mode: true,
+        }
+    }
+}
+
+impl super::View for SierpinskiCarpet {
+    fn name(&self) -> &'static str {
+        "Sierpinski Carpet"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "A square cut into a 3 × 3 grid without its center, again in each of the eight left.\n\
+             every square -> 8 squares of a third, the middle one removed",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "+ / - buttons: change the depth\n\
+             the hue of a square counts how many of its ancestors sat in the middle of an edge\n\
+             Home: reset the depth",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        self.depth = self.depth.min(self.max_depth);
+        let max_depth = self.max_depth;
+        let context = self.gl.get_or_try_init(|| {
+            Context::new(&cc.gl, max_depth).map(|context| Arc::new(Mutex::new(context)))
+        });
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        self.depth = self.depth.min(self.max_depth);
+        let mut context = Context::new(gl, self.max_depth)?;
+        context.levels.wait(self.depth as usize);
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, self.depth, self.dark_mode, size)
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        // only the depths computed so far, a deeper one would draw the deepest ready instead
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        Some(("depth", (0..ready.min(self.max_depth + 1)).collect()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        context.export(gl, value, self.dark_mode, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        self.dark_mode = ui.visuals().dark_mode;
+        let painter = Painter::new(
+            ui.ctx().clone(),
+            ui.layer_id(),
+            ui.available_rect_before_wrap(),
+        );
+        let rect = painter.clip_rect();
+        ui.expand_to_include_rect(rect);
+
+        super::settings_popup(ui, self, None);
+
+        let gl = self.gl.clone();
+        let max_depth = self.max_depth;
+        let depth = self.depth;
+        let dark_mode = self.dark_mode;
+        let ratio = rect.height() / rect.width();
+        if self.computing().is_some() {
+            // keep painting until the requested depth is ready
+            ui.ctx().request_repaint();
+        }
+
+        let callback = egui::PaintCallback {
+            rect,
+            callback: std::sync::Arc::new(move |_info, render_ctx| {
+                if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
+                    let mut gl = gl.get().unwrap().lock();
+                    super::revive(&mut *gl, painter.gl(), |gl| Context::new(gl, max_depth));
+                    gl.paint(painter.gl(), depth, dark_mode, ratio);
+                } else {
+                    eprintln!("Can't do custom painting because we are not using a glow context");
+                }
+            }),
+        };
+        painter.add(callback);
+    }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        ui.label(format!("Painted square count: {}", 8usize.pow(self.depth)));
+        if let Some(depth) = self.computing() {
+            ui.label(format!("computing depth {}...", depth));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Depth :");
+            ui.add(
+                DragValue::new(&mut self.depth)
+                    .speed(1.0)
+                    .clamp_range(0..=self.max_depth),
+            );
+            if ui.button("+").clicked() && self.depth < self.max_depth {
+                self.depth += 1;
+            }
+            if ui.button("-").clicked() && self.depth > 0 {
+                self.depth -= 1;
+            }
+        });
+        if ui.button("reset").clicked() {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.depth = DEFAULT_DEPTH;
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        if let Some(depth) = args.depth {
+            self.depth = depth.min(self.max_depth);
+        }
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            depth: Some(self.depth),
+            ..Default::default()
+        }
+    }
+}
+
+impl SierpinskiCarpet {
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
+    }
+    /// Depth being computed in the background, if the requested one is not ready yet.
+    fn computing(&self) -> Option<u32> {
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        (ready <= self.depth).then(|| ready)
+    }
+}
+
+/// One filled square of the carpet
+#[derive(Debug, Clone, Copy)]
+struct Square {
+    /// Bottom left corner
+    min: Pos2,
+    side: f32,
+    /// How many of the squares it was cut from sat in the middle of an edge of their parent
+    edges: u32,
+}
+
+/// Corner of a square as uploaded, with the shade of its square
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    pos: Pos2,
+    shade: f32,
+}
+
+/// Indices drawn at `depth`, two triangles for each square.
+fn vertex_count(depth: u32) -> usize {
+    6 * 8usize.pow(depth)
+}
+
+/// Cut every square of `prev` into a 3 × 3 grid and keep all but the center.
+fn next_level(prev: &[Square]) -> Vec<Square> {
+    let mut squares = Vec::with_capacity(prev.len() * 8);
+    for square in prev {
+        let side = square.side / 3.0;
+        for row in 0..3 {
+            for column in 0..3 {
+                if row == 1 && column == 1 {
+                    continue;
+                }
+                // the middles of the edges are the cells with one coordinate at 1
+                let edge = row == 1 || column == 1;
+                squares.push(Square {
+                    min: pos2(
+                        square.min.x + column as f32 * side,
+                        square.min.y + row as f32 * side,
+                    ),
+                    side,
+                    edges: square.edges + edge as u32,
+                });
+            }
+        }
+    }
+    tracing::debug!(squares = squares.len());
+    squares
+}
+
+#[derive(Debug)]
+struct Context {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    vbo: glow::Buffer,
+    ebo: glow::Buffer,
+    /// `levels[d]` is the squares of depth `d`, `levels[0]` is the initial square
+    levels: Levels<Vec<Square>>,
+    /// Depth of the geometry in the GPU buffers, `None` before the first upload
+    depth: Option<u32>,
+}
+
+const VERTEX_SHADER: &str = r#"
+layout (location = 0) in vec2 in_pos;
+// share of the depths at which the square was in the middle of an edge
+layout (location = 1) in float in_shade;
+uniform vec2 uni_scale;
+// 1 on a dark canvas, lower to keep the light colors visible on a light one
+uniform float uni_brightness;
+out vec3 v_color;
+
+vec3 hsv2rgb(vec3 c) {
+    vec4 K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+    vec3 p = abs(fract(c.xxx + K.xyz) * 6.0 - K.www);
+    return c.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), c.y);
+}
+
+void main() {
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+    v_color = hsv2rgb(vec3(0.55 + 0.45 * in_shade, 0.7, uni_brightness));
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+in vec3 v_color;
+out vec4 out_color;
+void main() {
+    out_color = vec4(v_color, 1.0);
+}
+"#;
+
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+
+    fn keep_from(&mut self, lost: Self) {
+        self.levels = lost.levels;
+    }
+}
+
+impl Context {
+    fn new(gl: &glow::Context, max_depth: u32) -> Result<Self, String> {
+        use glow::HasContext as _;
+
+        unsafe {
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
+
+            Ok(Self {
+                program,
+                vao: gl.create_vertex_array().unwrap(),
+                vbo: gl.create_buffer().unwrap(),
+                ebo: gl.create_buffer().unwrap(),
+                levels: Levels::new(
+                    vec![Square {
+                        min: pos2(-HALF_SIDE, -HALF_SIDE),
+                        side: 2.0 * HALF_SIDE,
+                        edges: 0,
+                    }],
+                    max_depth as usize + 1,
+                    |prev: &Vec<Square>| next_level(prev),
+                ),
+                depth: None,
+            })
+        }
+    }
+
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, squares: &[Square], depth: u32) {
+        use glow::HasContext as _;
+
+        let mut vertices = Vec::with_capacity(squares.len() * 4);
+        let mut indices = Vec::with_capacity(squares.len() * 6);
+        for square in squares {
+            let shade = square.edges as f32 / depth.max(1) as f32;
+            let i = vertices.len() as u32;
+            let (min, max) = (square.min, square.min + Vec2::splat(square.side));
+            for pos in [min, pos2(max.x, min.y), max, pos2(min.x, max.y)] {
+                vertices.push(Vertex { pos, shade });
+            }
+            indices.extend([i, i + 1, i + 2, i, i + 2, i + 3]);
+        }
+
+        let mut vao = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao));
+
+        let verts_slice = std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            vertices.len() * size_of::<Vertex>(),
+        );
+        let indices_slice = std::slice::from_raw_parts(
+            indices.as_ptr() as *const u8,
+            indices.len() * size_of::<u32>(),
+        );
+
+        let mut vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
+
+        let mut ebo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+        gl.buffer_data_u8_slice(
+            glow::ELEMENT_ARRAY_BUFFER,
+            indices_slice,
+            glow::DYNAMIC_DRAW,
+        );
+
+        let stride = size_of::<Vertex>() as i32;
+        gl.enable_vertex_attrib_array(0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, stride, 0);
+        gl.enable_vertex_attrib_array(1);
+        gl.vertex_attrib_pointer_f32(1, 1, glow::FLOAT, false, stride, size_of::<Pos2>() as i32);
+        swap(&mut self.vao, &mut vao);
+        swap(&mut self.vbo, &mut vbo);
+        swap(&mut self.ebo, &mut ebo);
+        gl.delete_vertex_array(vao);
+        gl.delete_buffer(vbo);
+        gl.delete_buffer(ebo);
+    }
+
+    fn export(
+        &mut self,
+        gl: &glow::Context,
+        depth: u32,
+        dark_mode: bool,
+        size: (u32, u32),
+    ) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| self.paint(gl, depth, dark_mode, ratio))
+    }
+
+    fn paint(&mut self, gl: &glow::Context, mut depth: u32, dark_mode: bool, ratio: f32) {
+        use glow::HasContext as _;
+        self.levels.step();
+        // draw the deepest ready depth until the requested one is computed
+        depth = depth.min(self.levels.ready() as u32 - 1);
+        if self.depth != Some(depth) {
+            let squares = self.levels.get(depth as usize).unwrap();
+            self.depth = Some(depth);
+            unsafe { self.update_vertices(gl, &squares, depth) };
+        }
+        unsafe {
+            gl.use_program(Some(self.program));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "uni_brightness")
+                    .as_ref(),
+                if dark_mode { 1.0 } else { 0.6 },
+            );
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_elements(
+                glow::TRIANGLES,
+                vertex_count(depth) as i32,
+                glow::UNSIGNED_INT,
+                0,
+            );
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use super::{
+    export,
+    levels::Levels,
+    rng::{self, Rng},
+    View, ViewArgs,
+};
 use eframe::{
     egui::{self, containers::*, *},
     emath::{pos2, Pos2},
@@ -10,19 +16,71 @@ use std::{
 };
 
 const DEFAULT_DEPTH: u32 = 2;
-const MAX_DEPTH: u32 = 10;
+const DEFAULT_POINTS: u32 = 50_000;
+const MAX_POINTS: u32 = 300_000;
+/// Seconds the holes of a new depth take to open
+const MORPH_DURATION: f64 = 0.3;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct SierpinskiTriangle {
+    #[cfg_attr(feature = "serde", serde(skip))]
     gl: OnceCell<Arc<Mutex<Context>>>,
+    /// Why `gl` could not be created
+    #[cfg_attr(feature = "serde", serde(skip))]
+    error: Option<String>,
+    mode: Mode,
     depth: u32,
+    /// Deepest depth within the vertex budget
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_depth: u32,
+    /// Number of chaos game points
+    points: u32,
+    /// Seed of the chaos game, the same seed always draws the same points
+    seed: u64,
+    /// Open the holes of a new depth from the top corners instead of showing them at once
+    smooth_depth: bool,
+    /// Draw only the edges of the subdivided triangles
+    wireframe: bool,
+    /// Theme of the last frame, so an export uses the colors on screen
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dark_mode: bool,
 }
 
 impl Default for SierpinskiTriangle {
     fn default() -> Self {
         Self {
             gl: Default::default(),
+            error: None,
+            mode: Mode::Subdivision,
             depth: DEFAULT_DEPTH,
+            max_depth: super::max_depth(0, vertex_count),
+            points: DEFAULT_POINTS,
+            seed: rng::DEFAULT_SEED,
+            smooth_depth: false,
+            wireframe: false,
+            dark_mode: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Mode {
+    /// Split every triangle into three, `depth` times
+    Subdivision,
+    /// Jump halfway towards a random corner, `points` times
+    ChaosGame,
+}
+
+impl Mode {
+    const ALL: [Mode; 2] = [Self::Subdivision, Self::ChaosGame];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Subdivision => "subdivision",
+            Self::ChaosGame => "chaos game",
         }
     }
 }
@@ -32,11 +90,84 @@ impl super::View for SierpinskiTriangle {
         "Sierpinski Triangle"
     }
 
-    fn is_dynamic(&self) -> bool {
-        false
+    fn description(&self) -> Option<&'static str> {
+        Some(
+            "A triangle with its middle quarter removed, again in each of the three corners left.\n\
+             chaos game: p -> (p + a random corner) / 2",
+        )
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some(
+            "mode: subdivide the triangles or play the chaos game\n\
+             + / - buttons: change the depth\n\
+             smooth depth: open the new holes gradually\n\
+             wireframe: draw the edges of the triangles only\n\
+             points: how many steps of the chaos game are drawn\n\
+             seed: type one and press enter to draw the same points again\n\
+             Home: reset the settings",
+        )
+    }
+
+    fn post_load(&mut self, cc: &eframe::CreationContext<'_>) {
+        self.depth = self.depth.min(self.max_depth);
+        let max_depth = self.max_depth;
+        let context = self.gl.get_or_try_init(|| {
+            Context::new(&cc.gl, max_depth).map(|context| Arc::new(Mutex::new(context)))
+        });
+        if let Err(err) = context {
+            tracing::error!(%err, view = self.name(), "Cannot build the shaders");
+            self.error = Some(err);
+        }
+    }
+
+    fn init_headless(&mut self, gl: &glow::Context) -> Result<(), String> {
+        self.depth = self.depth.min(self.max_depth);
+        let mut context = Context::new(gl, self.max_depth)?;
+        context.levels.wait(self.depth as usize);
+        self.gl = OnceCell::with_value(Arc::new(Mutex::new(context)));
+        Ok(())
+    }
+
+    fn render_to_image(&self, gl: &glow::Context, size: (u32, u32)) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        let points = (self.points, self.seed);
+        let depth = (self.depth, self.wireframe);
+        context.export(gl, self.mode, depth, points, self.dark_mode, size)
+    }
+
+    fn benchmark_sweep(&self) -> Option<(&'static str, Vec<u32>)> {
+        // only the depths computed so far, a deeper one would draw the deepest ready instead
+        if self.mode != Mode::Subdivision {
+            return None;
+        }
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        Some(("depth", (0..ready.min(self.max_depth + 1)).collect()))
+    }
+
+    fn render_benchmark(
+        &self,
+        gl: &glow::Context,
+        size: (u32, u32),
+        value: u32,
+    ) -> Option<ColorImage> {
+        let mut context = self.gl.get()?.lock();
+        let points = (self.points, self.seed);
+        let depth = (value, self.wireframe);
+        context.export(gl, self.mode, depth, points, self.dark_mode, size)
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save(&self, storage: &mut dyn eframe::epi::Storage) {
+        eframe::epi::set_value(storage, self.name(), self);
     }
 
     fn ui(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            super::gl_error_ui(ui, error);
+            return;
+        }
+        self.dark_mode = ui.visuals().dark_mode;
         let painter = Painter::new(
             ui.ctx().clone(),
             ui.layer_id(),
@@ -45,23 +176,35 @@ impl super::View for SierpinskiTriangle {
         let rect = painter.clip_rect();
         ui.expand_to_include_rect(rect);
 
-        Frame::popup(ui.style())
-            .stroke(Stroke::none())
-            .show(ui, |ui| {
-                ui.set_max_width(250.0);
-                CollapsingHeader::new("Settings").show(ui, |ui| self.options_ui(ui));
-            });
+        super::settings_popup(ui, self, None);
 
         let gl = self.gl.clone();
+        let max_depth = self.max_depth;
+        let mode = self.mode;
         let depth = self.depth;
+        let wireframe = self.wireframe;
+        let points = (self.points, self.seed);
+        let dark_mode = self.dark_mode;
         let ratio = rect.height() / rect.width();
+        let now = self.smooth_depth.then(|| ui.input().time);
+        if self.computing().is_some() {
+            // keep painting until the requested depth is ready
+            ui.ctx().request_repaint();
+        }
+        if let (Some(now), Mode::Subdivision) = (now, mode) {
+            if self.gl.get().unwrap().lock().morphing(depth, now) {
+                ui.ctx().request_repaint();
+            }
+        }
 
         let callback = egui::PaintCallback {
             rect,
             callback: std::sync::Arc::new(move |_info, render_ctx| {
                 if let Some(painter) = render_ctx.downcast_ref::<egui_glow::Painter>() {
                     let mut gl = gl.get().unwrap().lock();
-                    gl.paint(painter.gl(), depth, ratio);
+                    super::revive(&mut *gl, painter.gl(), |gl| Context::new(gl, max_depth));
+                    let depth = (depth, wireframe);
+                    gl.paint(painter.gl(), mode, depth, points, dark_mode, ratio, now);
                 } else {
                     eprintln!("Can't do custom painting because we are not using a glow context");
                 }
@@ -69,35 +212,131 @@ impl super::View for SierpinskiTriangle {
         };
         painter.add(callback);
     }
+
+    fn options_ui(&mut self, ui: &mut Ui) {
+        ComboBox::from_label("mode")
+            .selected_text(self.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in Mode::ALL {
+                    ui.selectable_value(&mut self.mode, mode, mode.name());
+                }
+            });
+        match self.mode {
+            Mode::Subdivision => self.depth_ui(ui),
+            Mode::ChaosGame => {
+                ui.horizontal(|ui| {
+                    ui.label("Points :");
+                    ui.add(
+                        DragValue::new(&mut self.points)
+                            .speed(100.0)
+                            .clamp_range(1..=MAX_POINTS),
+                    );
+                });
+                rng::seed_ui(ui, &mut self.seed);
+            }
+        }
+        let subdivided = self.mode == Mode::Subdivision && self.computing().is_none();
+        if ui
+            .add_enabled(subdivided, Button::new("Save SVG"))
+            .on_disabled_hover_text("only the subdivided triangles can be saved")
+            .clicked()
+        {
+            if let Some(svg) = self.to_svg() {
+                export::save_svg(&export::file_name(self.name(), "svg"), &svg);
+            }
+        }
+        if ui.button("reset").clicked() {
+            self.reset();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.mode = Mode::Subdivision;
+        self.depth = DEFAULT_DEPTH;
+        self.points = DEFAULT_POINTS;
+        self.seed = rng::DEFAULT_SEED;
+        self.smooth_depth = false;
+        self.wireframe = false;
+    }
+
+    fn apply_args(&mut self, args: &ViewArgs) {
+        if let Some(depth) = args.depth {
+            self.mode = Mode::Subdivision;
+            self.depth = depth.min(self.max_depth);
+        }
+    }
+
+    fn view_args(&self) -> ViewArgs {
+        ViewArgs {
+            depth: (self.mode == Mode::Subdivision).then(|| self.depth),
+            ..Default::default()
+        }
+    }
 }
 
 impl SierpinskiTriangle {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let default = Self::default();
-        default
-            .gl
-            .get_or_init(|| Arc::new(Mutex::new(Context::new(&cc.gl))));
-        default
+    pub fn new(cc: Option<&eframe::CreationContext<'_>>) -> Self {
+        let mut view: Self = super::restore(cc);
+        if let Some(cc) = cc {
+            view.post_load(cc);
+        }
+        view
     }
-    fn options_ui(&mut self, ui: &mut Ui) {
+    /// Depth being computed in the background, if the requested one is not ready yet.
+    fn computing(&self) -> Option<u32> {
+        if self.mode != Mode::Subdivision {
+            return None;
+        }
+        let ready = self.gl.get()?.lock().levels.ready() as u32;
+        (ready <= self.depth).then(|| ready)
+    }
+    fn depth_ui(&mut self, ui: &mut Ui) {
         ui.label(format!("Painted triangle count: {}", 3i32.pow(self.depth)));
+        if let Some(depth) = self.computing() {
+            ui.label(format!("computing depth {}...", depth));
+        }
         ui.horizontal(|ui| {
             ui.label("Depth :");
             ui.add(
                 DragValue::new(&mut self.depth)
                     .speed(1.0)
-                    .clamp_range(0..=MAX_DEPTH),
+                    .clamp_range(0..=self.max_depth),
             );
-            if ui.button("+").clicked() && self.depth < MAX_DEPTH {
+            if ui.button("+").clicked() && self.depth < self.max_depth {
                 self.depth += 1;
             }
             if ui.button("-").clicked() && self.depth > 0 {
                 self.depth -= 1;
             }
         });
-        if ui.button("reset").clicked() {
-            self.depth = DEFAULT_DEPTH;
+        ui.checkbox(&mut self.smooth_depth, "smooth depth")
+            .on_hover_text("open the holes gradually when the depth goes up by one");
+        ui.checkbox(&mut self.wireframe, "wireframe")
+            .on_hover_text("draw the edges of the triangles only");
+    }
+    /// The triangles at the current depth as an SVG document, `None` until they are computed.
+    /// Each triangle takes the color of the vertex shader at its center, as its fill or as its
+    /// outline in wireframe.
+    fn to_svg(&self) -> Option<String> {
+        let level = self.gl.get()?.lock().levels.get(self.depth as usize)?;
+        let brightness = if self.dark_mode { 1.0 } else { 0.6 };
+        let mut svg = export::Svg::default();
+        for triangle in &level.indices {
+            let corners = [triangle.l, triangle.r, triangle.u].map(|i| level.vertices[i as usize]);
+            let center = (corners[0].to_vec2() + corners[1].to_vec2() + corners[2].to_vec2()) / 3.0;
+            let color = [
+                (0.8 + center.y) / 3.0,
+                (0.8 - center.x - center.y) / 1.6,
+                (center.x + 0.8 - center.y) / 1.6,
+            ];
+            let color = color.map(|c| c * brightness);
+            if self.wireframe {
+                svg.polyline(&corners, true, color, 1.0);
+            } else {
+                svg.polygon(&corners, color);
+            }
         }
+        Some(svg.finish())
     }
 }
 
@@ -113,29 +352,132 @@ fn index(l: u32, r: u32, u: u32) -> TriangleIndex {
     TriangleIndex { l, r, u }
 }
 
+/// Triangles of one depth and the vertices they refer to
+#[derive(Debug)]
+struct Level {
+    vertices: Vec<Pos2>,
+    indices: Vec<TriangleIndex>,
+}
+
+/// Vertices drawn at `depth`, three for each triangle.
+fn vertex_count(depth: u32) -> usize {
+    3usize.pow(depth + 1)
+}
+
+/// The three edges of every triangle, as index pairs for `glow::LINES`. GLES and WebGL have no
+/// `polygon_mode`, so the wireframe needs its own index buffer.
+fn line_indices(triangles: &[TriangleIndex]) -> Vec<u32> {
+    let mut lines = Vec::with_capacity(triangles.len() * 6);
+    for t in triangles {
+        lines.extend([t.l, t.r, t.r, t.u, t.u, t.l]);
+    }
+    lines
+}
+
+/// Split every triangle of `prev` into the three at its corners.
+fn next_level(prev: &Level) -> Level {
+    let len = prev.indices.len();
+    let mut vertices = Vec::with_capacity(prev.vertices.len() + len * 3);
+    vertices.extend_from_slice(&prev.vertices);
+    let mut indices = Vec::with_capacity(len * 3);
+    for s in &prev.indices {
+        let i = vertices.len() as u32;
+        let l = vertices[s.l as usize].to_vec2();
+        let r = vertices[s.r as usize].to_vec2();
+        let u = vertices[s.u as usize].to_vec2();
+        let nl = ((l + u) / 2.0).to_pos2(); // i
+        let nr = ((r + u) / 2.0).to_pos2(); // i + 1
+        let nd = ((l + r) / 2.0).to_pos2(); // i + 2
+        let li = index(s.l, i + 2, i);
+        let ri = index(i + 2, s.r, i + 1);
+        let ui = index(i, i + 1, s.u);
+        vertices.extend([nl, nr, nd]);
+        indices.extend([li, ri, ui]);
+    }
+    tracing::debug!(indices = indices.len(), verts = vertices.len());
+    Level { vertices, indices }
+}
+
+/// Where each vertex of `level` sits on the triangles one depth above. The two new vertices
+/// on the upper sides of a triangle start at its top corner, so its hole opens from there.
+fn parents(level: &Level) -> Vec<Pos2> {
+    let mut parents = level.vertices.clone();
+    if level.vertices.len() > CORNERS.len() {
+        // the upper child of every split triangle, made of those two vertices and the corner
+        for upper in level.indices.iter().skip(2).step_by(3) {
+            let corner = level.vertices[upper.u as usize];
+            parents[upper.l as usize] = corner;
+            parents[upper.r as usize] = corner;
+        }
+    }
+    parents
+}
+
+/// Corners of the initial triangle, also the targets of the chaos game
+const CORNERS: [Pos2; 3] = [
+    Pos2 {
+        x: -0.8,
+        y: -0.8 / SQRT_3,
+    },
+    Pos2 {
+        x: 0.8,
+        y: -0.8 / SQRT_3,
+    },
+    Pos2 {
+        x: 0.0,
+        y: 1.6 / SQRT_3,
+    },
+];
+const SQRT_3: f32 = 1.732_050_8;
+
 #[derive(Debug)]
 struct Context {
     program: glow::Program,
     vao: glow::VertexArray,
     vbo: glow::Buffer,
     ebo: glow::Buffer,
-    vertices: Vec<Pos2>,
-    indices: Vec<Vec<TriangleIndex>>,
-    depth: u32,
+    /// Edges of the triangles of `ebo`, drawn in wireframe
+    lines_ebo: glow::Buffer,
+    /// Position of each vertex of `vbo` on the triangles one depth above
+    parents_vbo: glow::Buffer,
+    /// `levels[d]` is the geometry of depth `d`, `levels[0]` is the initial triangle
+    levels: Levels<Level>,
+    /// Depth of the geometry in the GPU buffers, `None` before the first upload
+    depth: Option<u32>,
+    /// Time the buffers went one depth deeper with the smooth depth on, while the holes open
+    grown_at: Option<f64>,
+    points_vao: glow::VertexArray,
+    points_vbo: glow::Buffer,
+    /// Every chaos game point generated so far, the game only ever appends
+    points: Vec<Pos2>,
+    /// Seed the points were generated from
+    seed: u64,
+    rng: Rng,
+    /// Number of points in `points_vbo`, `None` before the first upload
+    point_count: Option<u32>,
 }
 
 const VERTEX_SHADER: &str = r#"
 layout (location = 0) in vec2 in_pos;
-uniform float uni_ratio;
+layout (location = 1) in vec2 in_parent;
+uniform vec2 uni_scale;
+// 0 draws the triangles one depth above, 1 the triangles of the buffers
+uniform float uni_morph;
+// 1 on a dark canvas, lower to keep the light colors visible on a light one
+uniform float uni_brightness;
 out vec3 v_color;
 
 void main() {
-    gl_Position = vec4(in_pos, 0.0, 1.0);
-    gl_Position.x *= uni_ratio;
-    float r = (0.8 + in_pos.y) / 3.0;
-    float g = (0.8 - in_pos.x - in_pos.y) / 1.6;
-    float b = (in_pos.x + 0.8 - in_pos.y) / 1.6;
-    v_color = vec3(r, g, b);
+    vec2 pos = mix(in_parent, in_pos, uni_morph);
+    gl_Position = vec4(pos, 0.0, 1.0);
+    gl_Position.xy *= uni_scale;
+    gl_PointSize = 1.0;
+    // the colors come from the position before the aspect scale, so the gradient follows the
+    // triangle whatever the shape of the window
+    float r = (0.8 + pos.y) / 3.0;
+    float g = (0.8 - pos.x - pos.y) / 1.6;
+    float b = (pos.x + 0.8 - pos.y) / 1.6;
+    v_color = vec3(r, g, b) * uni_brightness;
 }
 "#;
 
@@ -148,117 +490,93 @@ void main() {
 }
 "#;
 
+impl super::GlResources for Context {
+    fn program(&self) -> glow::Program {
+        self.program
+    }
+
+    fn keep_from(&mut self, lost: Self) {
+        self.levels = lost.levels;
+    }
+}
+
 impl Context {
-    fn new(gl: &glow::Context) -> Self {
+    fn new(gl: &glow::Context, max_depth: u32) -> Result<Self, String> {
         use glow::HasContext as _;
 
-        let shader_version = if cfg!(target_arch = "wasm32") {
-            // in/out
-            "#version 300 es"
-        } else {
-            // location
-            "#version 330"
-        };
-
         unsafe {
-            let program = gl.create_program().expect("Cannot create program");
-
-            let shader_sources = [
-                (glow::VERTEX_SHADER, VERTEX_SHADER),
-                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
-            ];
-
-            let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader = gl
-                        .create_shader(*shader_type)
-                        .expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
-                    gl.compile_shader(shader);
-                    if !gl.get_shader_compile_status(shader) {
-                        panic!("{}", gl.get_shader_info_log(shader));
-                    }
-                    gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
-
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
-            }
-
-            for shader in shaders {
-                gl.detach_shader(program, shader);
-                gl.delete_shader(shader);
-            }
+            let program = super::create_program(
+                gl,
+                &[
+                    (glow::VERTEX_SHADER, VERTEX_SHADER),
+                    (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+                ],
+            )?;
 
-            Self {
+            Ok(Self {
                 program,
                 vao: gl.create_vertex_array().unwrap(),
                 vbo: gl.create_buffer().unwrap(),
                 ebo: gl.create_buffer().unwrap(),
-                vertices: vec![
-                    pos2(-0.8, -0.8 / 3.0_f32.sqrt()),
-                    pos2(0.8, -0.8 / 3.0_f32.sqrt()),
-                    pos2(0.0, 1.6 / 3.0_f32.sqrt()),
-                ],
-                indices: vec![vec![TriangleIndex { l: 0, r: 1, u: 2 }]],
-                depth: 0,
-            }
-        }
-    }
-
-    fn calc(&mut self, depth: u32) {
-        if self.indices.len() > depth as usize {
-            return;
-        }
-        for d in self.indices.len() - 1..depth as usize {
-            let len = self.indices[d].len();
-            let mut new = Vec::with_capacity(len * 3);
-            for s in &self.indices[d] {
-                let i = self.vertices.len() as u32;
-                let l = self.vertices[s.l as usize].to_vec2();
-                let r = self.vertices[s.r as usize].to_vec2();
-                let u = self.vertices[s.u as usize].to_vec2();
-                let nl = ((l + u) / 2.0).to_pos2(); // i
-                let nr = ((r + u) / 2.0).to_pos2(); // i + 1
-                let nd = ((l + r) / 2.0).to_pos2(); // i + 2
-                let li = index(s.l, i + 2, i);
-                let ri = index(i + 2, s.r, i + 1);
-                let ui = index(i, i + 1, s.u);
-                self.vertices.extend([nl, nr, nd]);
-                new.extend([li, ri, ui]);
-            }
-            tracing::debug!(depth = d, indices = new.len(), verts = self.vertices.len());
-            self.indices.push(new);
+                lines_ebo: gl.create_buffer().unwrap(),
+                parents_vbo: gl.create_buffer().unwrap(),
+                levels: Levels::new(
+                    Level {
+                        vertices: CORNERS.to_vec(),
+                        indices: vec![TriangleIndex { l: 0, r: 1, u: 2 }],
+                    },
+                    max_depth as usize + 1,
+                    next_level,
+                ),
+                depth: None,
+                grown_at: None,
+                points_vao: gl.create_vertex_array().unwrap(),
+                points_vbo: gl.create_buffer().unwrap(),
+                points: vec![pos2(0.0, 0.0)],
+                seed: rng::DEFAULT_SEED,
+                rng: Rng::new(rng::DEFAULT_SEED),
+                point_count: None,
+            })
         }
     }
 
-    unsafe fn update_vertices(&mut self, gl: &glow::Context) {
+    unsafe fn update_vertices(&mut self, gl: &glow::Context, level: &Level) {
         use glow::HasContext as _;
 
         let mut vao = gl.create_vertex_array().unwrap();
         gl.bind_vertex_array(Some(vao));
 
-        let len = (3usize.pow(self.depth) + 1) * 3 / 2;
-        let verts_slice = &self.vertices[..len];
+        let verts_slice = level.vertices.as_slice();
         let verts_slice = std::slice::from_raw_parts(
             verts_slice.as_ptr() as *const u8,
             verts_slice.len() * size_of::<Pos2>(),
         );
 
-        let indices_slice = self.indices[self.depth as usize].as_slice();
+        let parents = parents(level);
+        let parents_slice = std::slice::from_raw_parts(
+            parents.as_ptr() as *const u8,
+            parents.len() * size_of::<Pos2>(),
+        );
+
+        let indices_slice = level.indices.as_slice();
         let indices_slice = std::slice::from_raw_parts(
             indices_slice.as_ptr() as *const u8,
             indices_slice.len() * size_of::<TriangleIndex>(),
         );
 
+        let lines = line_indices(&level.indices);
+        let lines_slice =
+            std::slice::from_raw_parts(lines.as_ptr() as *const u8, lines.len() * size_of::<u32>());
+
         let mut vbo = gl.create_buffer().unwrap();
         gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
         gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::DYNAMIC_DRAW);
 
+        let mut lines_ebo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(lines_ebo));
+        gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, lines_slice, glow::DYNAMIC_DRAW);
+
+        // bound last, so the vertex array draws the triangles unless paint binds the lines
         let mut ebo = gl.create_buffer().unwrap();
         gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
         gl.buffer_data_u8_slice(
@@ -269,34 +587,253 @@ impl Context {
 
         gl.enable_vertex_attrib_array(0);
         gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+
+        let mut parents_vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(parents_vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, parents_slice, glow::DYNAMIC_DRAW);
+        gl.enable_vertex_attrib_array(1);
+        gl.vertex_attrib_pointer_f32(1, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+
         swap(&mut self.vao, &mut vao);
         swap(&mut self.vbo, &mut vbo);
         swap(&mut self.ebo, &mut ebo);
+        swap(&mut self.lines_ebo, &mut lines_ebo);
+        swap(&mut self.parents_vbo, &mut parents_vbo);
         gl.delete_vertex_array(vao);
         gl.delete_buffer(vbo);
         gl.delete_buffer(ebo);
+        gl.delete_buffer(lines_ebo);
+        gl.delete_buffer(parents_vbo);
     }
 
-    fn paint(&mut self, gl: &glow::Context, mut depth: u32, ratio: f32) {
-        use glow::HasContext as _;
-        depth = depth.min(MAX_DEPTH);
-        if self.depth != depth {
-            self.calc(depth);
-            self.depth = depth;
-            unsafe { self.update_vertices(gl) };
+    /// Whether the triangles at `depth` are not in the buffers yet or their holes are still
+    /// opening at `now`, so the view has to be painted again.
+    fn morphing(&self, depth: u32, now: f64) -> bool {
+        self.depth != Some(depth) || self.grown_at.map_or(false, |at| now - at < MORPH_DURATION)
+    }
+
+    /// Make sure at least `count` chaos game points are generated from `seed`.
+    fn calc_points(&mut self, count: u32, seed: u64) {
+        if self.seed != seed {
+            self.seed = seed;
+            self.rng = Rng::new(seed);
+            self.points.truncate(1);
+        }
+        let count = count as usize;
+        if self.points.len() >= count {
+            return;
+        }
+        self.points.reserve(count - self.points.len());
+        let mut p = *self.points.last().unwrap();
+        while self.points.len() < count {
+            let corner = CORNERS[self.rng.next_u32() as usize % 3];
+            p = pos2((p.x + corner.x) / 2.0, (p.y + corner.y) / 2.0);
+            self.points.push(p);
         }
+        tracing::debug!(points = self.points.len());
+    }
+
+    unsafe fn update_points(&mut self, gl: &glow::Context, count: u32) {
+        use glow::HasContext as _;
+
+        let mut vao = gl.create_vertex_array().unwrap();
+        gl.bind_vertex_array(Some(vao));
+
+        let verts_slice = &self.points[..count as usize];
+        let verts_slice = std::slice::from_raw_parts(
+            verts_slice.as_ptr() as *const u8,
+            verts_slice.len() * size_of::<Pos2>(),
+        );
+
+        let mut vbo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, verts_slice, glow::STATIC_DRAW);
+
+        gl.enable_vertex_attrib_array(0);
+        gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 2 * size_of::<f32>() as i32, 0);
+        swap(&mut self.points_vao, &mut vao);
+        swap(&mut self.points_vbo, &mut vbo);
+        gl.delete_vertex_array(vao);
+        gl.delete_buffer(vbo);
+    }
+
+    fn export(
+        &mut self,
+        gl: &glow::Context,
+        mode: Mode,
+        depth: (u32, bool),
+        points: (u32, u64),
+        dark_mode: bool,
+        size: (u32, u32),
+    ) -> Option<ColorImage> {
+        let ratio = size.1 as f32 / size.0 as f32;
+        export::render_offscreen(gl, size, |gl| {
+            self.paint(gl, mode, depth, points, dark_mode, ratio, None)
+        })
+    }
+
+    /// Draw the triangles or the points, `depth` being the depth of the triangles and whether
+    /// only their edges are drawn, `points` the count and seed of the points. With the time
+    /// `now`, the holes of a depth one deeper than the last painted open gradually; `None`
+    /// draws them at once.
+    #[allow(clippy::too_many_arguments)]
+    fn paint(
+        &mut self,
+        gl: &glow::Context,
+        mode: Mode,
+        depth: (u32, bool),
+        points: (u32, u64),
+        dark_mode: bool,
+        ratio: f32,
+        now: Option<f64>,
+    ) {
+        use glow::HasContext as _;
         unsafe {
             gl.use_program(Some(self.program));
-            gl.bind_vertex_array(Some(self.vao));
+            let scale = super::aspect_scale(ratio);
+            gl.uniform_2_f32(
+                gl.get_uniform_location(self.program, "uni_scale").as_ref(),
+                scale.0,
+                scale.1,
+            );
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "uni_brightness")
+                    .as_ref(),
+                if dark_mode { 1.0 } else { 0.6 },
+            );
+        }
+        match mode {
+            Mode::Subdivision => self.paint_triangles(gl, depth.0, depth.1, now),
+            Mode::ChaosGame => self.paint_points(gl, points.0, points.1),
+        }
+    }
+
+    fn paint_points(&mut self, gl: &glow::Context, mut count: u32, seed: u64) {
+        use glow::HasContext as _;
+        count = count.clamp(1, MAX_POINTS);
+        if self.point_count != Some(count) || self.seed != seed {
+            self.calc_points(count, seed);
+            self.point_count = Some(count);
+            unsafe { self.update_points(gl, count) };
+        }
+        unsafe {
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "uni_morph").as_ref(),
+                1.0,
+            );
+            gl.bind_vertex_array(Some(self.points_vao));
+            gl.draw_arrays(glow::POINTS, 0, count as i32);
+        }
+    }
+
+    fn paint_triangles(
+        &mut self,
+        gl: &glow::Context,
+        mut depth: u32,
+        wireframe: bool,
+        now: Option<f64>,
+    ) {
+        use glow::HasContext as _;
+        self.levels.step();
+        // draw the deepest ready depth until the requested one is computed
+        depth = depth.min(self.levels.ready() as u32 - 1);
+        if self.depth != Some(depth) {
+            let level = self.levels.get(depth as usize).unwrap();
+            let grown = self.depth.map_or(false, |last| depth == last + 1);
+            self.grown_at = now.filter(|_| grown);
+            self.depth = Some(depth);
+            unsafe { self.update_vertices(gl, &level) };
+        }
+        let morph = match (now, self.grown_at) {
+            (Some(now), Some(at)) => ((now - at) / MORPH_DURATION).min(1.0) as f32,
+            _ => 1.0,
+        };
+        unsafe {
             gl.uniform_1_f32(
-                gl.get_uniform_location(self.program, "uni_ratio").as_ref(),
-                ratio,
+                gl.get_uniform_location(self.program, "uni_morph").as_ref(),
+                morph,
             );
-            gl.draw_elements(
-                glow::TRIANGLES,
-                3i32.pow(self.depth + 1),
-                glow::UNSIGNED_INT,
-                0,
+            gl.bind_vertex_array(Some(self.vao));
+            // the element buffer is part of the vertex array, so it is set on every paint
+            if wireframe {
+                gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.lines_ebo));
+                let count = 2 * vertex_count(depth) as i32;
+                gl.draw_elements(glow::LINES, count, glow::UNSIGNED_INT, 0);
+            } else {
+                gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.ebo));
+                gl.draw_elements(
+                    glow::TRIANGLES,
+                    vertex_count(depth) as i32,
+                    glow::UNSIGNED_INT,
+                    0,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_depth_is_computed() {
+        let max_depth = super::super::max_depth(0, vertex_count);
+        let mut level = Level {
+            vertices: CORNERS.to_vec(),
+            indices: vec![index(0, 1, 2)],
+        };
+        for depth in 0..=max_depth {
+            if depth > 0 {
+                level = next_level(&level);
+            }
+            assert_eq!(level.indices.len(), 3usize.pow(depth), "depth {}", depth);
+            assert_eq!(
+                level.indices.len() * 3,
+                vertex_count(depth),
+                "depth {}",
+                depth
+            );
+            let len = level.vertices.len() as u32;
+            assert!(
+                level
+                    .indices
+                    .iter()
+                    .all(|t| t.l < len && t.r < len && t.u < len),
+                "depth {}",
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn colors_ignore_the_aspect_scale() {
+        let code: Vec<&str> = VERTEX_SHADER
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with("//") && !line.starts_with("uniform"))
+            .collect();
+        // wide and tall windows only scale the output position
+        let scaled: Vec<&str> = code
+            .iter()
+            .copied()
+            .filter(|line| line.contains("uni_scale"))
+            .collect();
+        assert_eq!(scaled, ["gl_Position.xy *= uni_scale;"]);
+        // `pos` is only set by its declaration, before the scale
+        let set: Vec<&str> = code
+            .iter()
+            .copied()
+            .filter(|line| line.starts_with("pos") || line.contains(" pos ="))
+            .collect();
+        assert_eq!(set, ["vec2 pos = mix(in_parent, in_pos, uni_morph);"]);
+        // so every channel of the gradient follows the unscaled triangle
+        for channel in ["float r =", "float g =", "float b ="] {
+            let line = code.iter().find(|line| line.starts_with(channel)).unwrap();
+            assert!(
+                line.contains("pos.") && !line.contains("gl_Position"),
+                "{}",
+                line
             );
         }
     }